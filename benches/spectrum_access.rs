@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use thermostreaming::*;
+
+// Compares the mzML XML path with the native path when reading all the scans of small.RAW
+// Note: both paths run the .NET spectrum writer (WriteSpectrumNoReturn), the native path only skips the copy
+// of the mzML spectrum XML and its parsing, and doesn't provide the charges, resolutions, noises and baselines
+// Note: the Mono runtime is bound to the main thread, which is also the one running the benchmarks
+
+const RAW_FILE_PATH_STR: &str = "./resources/small.RAW";
+
+fn bench_spectrum_access(c: &mut Criterion) {
+    // Note: the assemblies copied by build.rs next to the bench executable are found by the discovery
+    MONO_EMBEDDINATOR.lock().unwrap().configure_with_discovery(&MonoDiscovery::new()).expect("e4k config failed");
+
+    let streamer = RawFileStreamer::new(RAW_FILE_PATH_STR).expect("streamer creation failed");
+    let scan_numbers = streamer.get_first_scan_number() ..= streamer.get_last_scan_number();
+
+    let mut group = c.benchmark_group("small.RAW");
+
+    group.bench_function("xml_spectra", |b| b.iter(|| {
+        scan_numbers.clone().map(|s_num| streamer.get_spectrum(s_num).unwrap().data.mz_list.len()).sum::<usize>()
+    }));
+    group.bench_function("native_scans", |b| b.iter(|| {
+        scan_numbers.clone().map(|s_num| streamer.get_native_scan(s_num).unwrap().masses.len()).sum::<usize>()
+    }));
+    group.bench_function("xml_spectrum_metadata", |b| b.iter(|| {
+        scan_numbers.clone().map(|s_num| streamer.get_spectrum_metadadata(s_num).unwrap().cv_params.len()).sum::<usize>()
+    }));
+    group.bench_function("native_scan_headers", |b| b.iter(|| {
+        scan_numbers.clone().map(|s_num| streamer.get_native_scan_header(s_num).unwrap().ms_order as usize).sum::<usize>()
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_spectrum_access);
+criterion_main!(benches);
@@ -0,0 +1,192 @@
+use anyhow::*;
+use arrow::array::{ArrayBuilder, ArrayRef, Float64Builder, Int8Builder, ListBuilder, StringBuilder, UInt32Builder, UInt8Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use std::sync::Arc;
+
+use crate::error::StreamerError;
+use crate::mzml_spectrum::MzMLSpectrum;
+use crate::scan_index::ScanIndexEntry;
+use crate::spectrum_iterator::SpectrumIterator;
+
+/// Fields of the scalar spectrum metadata columns (the `ScanIndexEntry` fields, retention times in minutes).
+pub fn scan_fields() -> Vec<Field> {
+    vec![
+        Field::new("scan_number", DataType::UInt32, false),
+        Field::new("ms_level", DataType::UInt8, false),
+        Field::new("retention_time", DataType::Float64, false),
+        Field::new("precursor_mz", DataType::Float64, true),
+        Field::new("precursor_charge", DataType::Int8, true),
+        Field::new("tic", DataType::Float64, false),
+        Field::new("base_peak_mz", DataType::Float64, false),
+        Field::new("base_peak_intensity", DataType::Float64, false),
+        Field::new("filter_string", DataType::Utf8, false),
+    ]
+}
+
+/// Schema of the batches returned by `SpectrumBatchIterator`: the scan fields followed by the `mz` and `intensity` list columns.
+pub fn spectra_schema() -> SchemaRef {
+    let mut fields = scan_fields();
+    fields.push(Field::new("mz", DataType::new_list(DataType::Float64, true), false));
+    fields.push(Field::new("intensity", DataType::new_list(DataType::Float64, true), false));
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Converts the given spectra to a `RecordBatch` following `spectra_schema`.
+pub fn spectra_to_record_batch(spectra: &[MzMLSpectrum]) -> Result<RecordBatch, StreamerError> {
+    let mut batch_builder = SpectrumBatchBuilder::default();
+    for spectrum in spectra {
+        batch_builder.append_spectrum(spectrum).map_err(|e| StreamerError::export("Arrow", e))?;
+    }
+
+    batch_builder.finish().map_err(|e| StreamerError::export("Arrow", e))
+}
+
+/// Iterator grouping the spectra of a `SpectrumIterator` into record batches of at most `batch_size` rows,
+/// created with `SpectrumSelection::record_batches()` or `RawFileStreamer::record_batches()`.
+///
+/// The peaks of a batch are copied once into contiguous Arrow buffers (the list columns can't reference the vectors
+/// of the spectra), which can then be handed to other Arrow consumers without further copy.
+/// When a spectrum can't be loaded, the spectra loaded before it are returned as a batch, followed by the error.
+pub struct SpectrumBatchIterator<'a> {
+    spectra: SpectrumIterator<'a>,
+    batch_size: usize,
+    pending_error: Option<StreamerError>,
+}
+
+impl<'a> SpectrumBatchIterator<'a> {
+    pub(crate) fn new(spectra: SpectrumIterator<'a>, batch_size: usize) -> Self {
+        Self {
+            spectra,
+            // A zero batch size would never make progress
+            batch_size: batch_size.max(1),
+            pending_error: None,
+        }
+    }
+}
+
+impl Iterator for SpectrumBatchIterator<'_> {
+    type Item = Result<RecordBatch, StreamerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.pending_error.take() {
+            return Some(Err(error));
+        }
+
+        let mut batch_builder = SpectrumBatchBuilder::default();
+        while batch_builder.len() < self.batch_size {
+            match self.spectra.next() {
+                Some(Result::Ok(spectrum)) => {
+                    if let Err(e) = batch_builder.append_spectrum(&spectrum) {
+                        self.pending_error = Some(StreamerError::export("Arrow", e));
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    self.pending_error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        if batch_builder.len() == 0 {
+            return self.pending_error.take().map(Err);
+        }
+
+        Some(batch_builder.finish().map_err(|e| StreamerError::export("Arrow", e)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (n_spectra, _) = self.spectra.size_hint();
+        let n_batches = n_spectra.div_ceil(self.batch_size) + usize::from(self.pending_error.is_some());
+
+        // Note: no upper bound, since an error splits the batch being built
+        (n_batches, None)
+    }
+}
+
+/// Builds the scalar metadata columns described by `scan_fields`.
+#[derive(Default)]
+pub(crate) struct ScanColumnsBuilder {
+    scan_numbers: UInt32Builder,
+    ms_levels: UInt8Builder,
+    retention_times: Float64Builder,
+    precursor_mz_values: Float64Builder,
+    precursor_charges: Int8Builder,
+    tic_values: Float64Builder,
+    base_peak_mz_values: Float64Builder,
+    base_peak_intensities: Float64Builder,
+    filter_strings: StringBuilder,
+}
+
+impl ScanColumnsBuilder {
+    pub(crate) fn len(&self) -> usize {
+        self.scan_numbers.len()
+    }
+
+    pub(crate) fn append_entry(&mut self, entry: &ScanIndexEntry) {
+        self.scan_numbers.append_value(entry.scan_number);
+        self.ms_levels.append_value(entry.ms_level);
+        self.retention_times.append_value(entry.retention_time);
+        self.precursor_mz_values.append_option(entry.precursor_mz);
+        self.precursor_charges.append_option(entry.precursor_charge);
+        self.tic_values.append_value(entry.tic);
+        self.base_peak_mz_values.append_value(entry.base_peak_mz);
+        self.base_peak_intensities.append_value(entry.base_peak_intensity);
+        self.filter_strings.append_value(&entry.filter_string);
+    }
+
+    pub(crate) fn finish_columns(&mut self) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(self.scan_numbers.finish()),
+            Arc::new(self.ms_levels.finish()),
+            Arc::new(self.retention_times.finish()),
+            Arc::new(self.precursor_mz_values.finish()),
+            Arc::new(self.precursor_charges.finish()),
+            Arc::new(self.tic_values.finish()),
+            Arc::new(self.base_peak_mz_values.finish()),
+            Arc::new(self.base_peak_intensities.finish()),
+            Arc::new(self.filter_strings.finish()),
+        ]
+    }
+}
+
+#[derive(Default)]
+struct SpectrumBatchBuilder {
+    scan_columns: ScanColumnsBuilder,
+    mz_lists: ListBuilder<Float64Builder>,
+    intensity_lists: ListBuilder<Float64Builder>,
+}
+
+impl SpectrumBatchBuilder {
+    fn len(&self) -> usize {
+        self.scan_columns.len()
+    }
+
+    fn append_spectrum(&mut self, spectrum: &MzMLSpectrum) -> Result<()> {
+        let scan_number = spectrum.get_scan_number().ok_or_else(|| anyhow!("can't parse scan number of spectrum '{}'", spectrum.metadata.id))?;
+        let data = &spectrum.data;
+        if data.mz_list.len() != data.intensity_list.len() {
+            bail!("spectrum '{}' has {} m/z values but {} intensity values", spectrum.metadata.id, data.mz_list.len(), data.intensity_list.len());
+        }
+
+        self.scan_columns.append_entry(&ScanIndexEntry::from_spectrum(scan_number, spectrum));
+
+        self.mz_lists.values().append_slice(&data.mz_list);
+        self.mz_lists.append(true);
+        self.intensity_lists.values().append_slice(&data.intensity_list);
+        self.intensity_lists.append(true);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch> {
+        let mut columns = self.scan_columns.finish_columns();
+        columns.push(Arc::new(self.mz_lists.finish()));
+        columns.push(Arc::new(self.intensity_lists.finish()));
+
+        Ok(RecordBatch::try_new(spectra_schema(), columns)?)
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+
+use crate::mzml::*;
+use crate::mzml_spectrum::BinaryDataArrayList;
+use crate::mzml_writer::{encode_binary_data_array, new_cv_param, BinaryPrecision};
+use crate::mzml_spectrum::{Activation, IsolationWindow};
+use crate::scan_index::ScanIndexEntry;
+use crate::srm::SrmTransition;
+
+/// Type of chromatogram, defining its mzML CV term.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChromatogramKind {
+    /// Total ion current chromatogram (MS:1000235)
+    TotalIonCurrent,
+    /// Base peak chromatogram (MS:1000628)
+    BasePeak,
+    /// Selected ion current chromatogram (MS:1000627), i.e. an extracted ion chromatogram
+    SelectedIonCurrent,
+    /// Selected reaction monitoring chromatogram (MS:1001473) of a single transition
+    SelectedReactionMonitoring,
+}
+
+impl ChromatogramKind {
+    pub fn get_cv_accession(&self) -> &'static str {
+        match self {
+            ChromatogramKind::TotalIonCurrent => TIC_CHROMATOGRAM_CV_ACCESSION,
+            ChromatogramKind::BasePeak => BASEPEAK_CHROMATOGRAM_CV_ACCESSION,
+            ChromatogramKind::SelectedIonCurrent => SIC_CHROMATOGRAM_CV_ACCESSION,
+            ChromatogramKind::SelectedReactionMonitoring => SRM_CHROMATOGRAM_CV_ACCESSION,
+        }
+    }
+
+    pub fn get_cv_name(&self) -> &'static str {
+        match self {
+            ChromatogramKind::TotalIonCurrent => "total ion current chromatogram",
+            ChromatogramKind::BasePeak => "basepeak chromatogram",
+            ChromatogramKind::SelectedIonCurrent => "selected ion current chromatogram",
+            ChromatogramKind::SelectedReactionMonitoring => "selected reaction monitoring chromatogram",
+        }
+    }
+
+    // Prefix of the chromatogram ids (ThermoRawFileParser names its TIC chromatogram "TIC")
+    fn get_id_prefix(&self) -> &'static str {
+        match self {
+            ChromatogramKind::TotalIonCurrent => "TIC",
+            ChromatogramKind::BasePeak => "BPC",
+            ChromatogramKind::SelectedIonCurrent => "XIC",
+            ChromatogramKind::SelectedReactionMonitoring => "SRM SIC",
+        }
+    }
+}
+
+/// Chromatogram computed from the scans of a RAW file, with intensities expressed in detector counts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chromatogram {
+    pub id: String,
+    pub kind: ChromatogramKind,
+    /// MS levels of the scans used to compute the chromatogram (None for all the MS levels)
+    pub ms_levels: Option<Vec<u8>>,
+    /// m/z window of the extracted ion chromatograms
+    pub mz_range: Option<(f64, f64)>,
+    /// Precursor and product of the SRM chromatograms
+    pub transition: Option<SrmTransition>,
+    pub time_unit: TimeUnit,
+    pub scan_numbers: Vec<u32>,
+    pub times: Vec<f64>,
+    pub intensities: Vec<f64>,
+}
+
+impl Chromatogram {
+    /// Builds a TIC or BPC chromatogram from the TIC or base peak intensity of the given scans (retention times in minutes).
+    pub fn from_scan_entries<'a, I>(kind: ChromatogramKind, ms_levels: Option<&[u8]>, scan_entries: I) -> Self
+    where
+        I: IntoIterator<Item = &'a ScanIndexEntry> {
+
+        let mut chromatogram = Self {
+            id: match ms_levels {
+                Some(ms_levels) => {
+                    let ms_levels_as_strings: Vec<String> = ms_levels.iter().map(|l| format!("MS{}", l)).collect();
+                    format!("{} {}", kind.get_id_prefix(), ms_levels_as_strings.join(","))
+                }
+                None => kind.get_id_prefix().to_string(),
+            },
+            kind,
+            ms_levels: ms_levels.map(|ms_levels| ms_levels.to_vec()),
+            mz_range: None,
+            transition: None,
+            time_unit: TimeUnit::Minute,
+            scan_numbers: vec![],
+            times: vec![],
+            intensities: vec![],
+        };
+
+        let selected_entries = scan_entries.into_iter().filter(|e| ms_levels.map(|ms_levels| ms_levels.contains(&e.ms_level)).unwrap_or(true));
+        for entry in selected_entries {
+            chromatogram.scan_numbers.push(entry.scan_number);
+            chromatogram.times.push(entry.retention_time);
+            chromatogram.intensities.push(match kind {
+                ChromatogramKind::TotalIonCurrent => entry.tic,
+                ChromatogramKind::BasePeak => entry.base_peak_intensity,
+                // Without m/z window, the ion current is the TIC (narrower windows need the peaks, see RawFileStreamer::extract_xics())
+                ChromatogramKind::SelectedIonCurrent | ChromatogramKind::SelectedReactionMonitoring => entry.tic,
+            });
+        }
+
+        chromatogram
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Returns the time of the most intense point, expressed in the chromatogram time unit.
+    pub fn get_apex_time(&self) -> Option<f64> {
+        self.intensities.iter().enumerate()
+            .max_by(|(_, i1), (_, i2)| i1.total_cmp(i2))
+            .map(|(idx, _)| self.times[idx])
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChromatogramList {
+    #[serde(rename = "@count")]
+    pub count: String,
+    #[serde(rename = "@defaultDataProcessingRef")]
+    pub default_data_processing_ref: String,
+    #[serde(rename = "chromatogram", default)]
+    pub chromatograms: Vec<MzMLChromatogram>,
+}
+
+impl ChromatogramList {
+    pub fn from_chromatograms(chromatograms: &[Chromatogram], default_data_processing_ref: &str, zlib_compression: bool) -> Result<Self> {
+        let mzml_chromatograms = chromatograms.iter().enumerate()
+            .map(|(index, chromatogram)| MzMLChromatogram::from_chromatogram(chromatogram, index, zlib_compression, BinaryPrecision::Float32))
+            .collect::<Result<Vec<MzMLChromatogram>>>()?;
+
+        Ok(Self {
+            count: mzml_chromatograms.len().to_string(),
+            default_data_processing_ref: default_data_processing_ref.to_string(),
+            chromatograms: mzml_chromatograms,
+        })
+    }
+}
+
+/// mzML chromatogram element (ChromatogramType of the mzML schema).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MzMLChromatogram {
+    #[serde(rename = "@index")]
+    pub index: String,
+    #[serde(rename = "@id")]
+    pub id: String,
+    #[serde(rename = "@defaultArrayLength")]
+    pub default_array_length: String,
+    #[serde(rename = "cvParam", default)]
+    pub cv_params: Vec<CvParam>,
+    #[serde(rename = "userParam", default)]
+    pub user_params: Vec<UserParam>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precursor: Option<ChromatogramPrecursor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<ChromatogramProduct>,
+    #[serde(rename = "binaryDataArrayList")]
+    pub binary_data_array_list: BinaryDataArrayList,
+}
+
+/// Precursor of a chromatogram, which unlike the spectrum precursors has no spectrum reference nor selected ions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChromatogramPrecursor {
+    #[serde(rename = "isolationWindow")]
+    pub isolation_window: IsolationWindow,
+    pub activation: Activation,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChromatogramProduct {
+    #[serde(rename = "isolationWindow")]
+    pub isolation_window: IsolationWindow,
+}
+
+impl MzMLChromatogram {
+    /// Converts the chromatogram, the times being always written as 64-bit floats.
+    pub fn from_chromatogram(chromatogram: &Chromatogram, index: usize, zlib_compression: bool, intensity_precision: BinaryPrecision) -> Result<Self> {
+        if chromatogram.times.len() != chromatogram.intensities.len() {
+            bail!("chromatogram '{}' has {} times but {} intensities", chromatogram.id, chromatogram.times.len(), chromatogram.intensities.len());
+        }
+
+        let time_unit = chromatogram.time_unit;
+        let binary_data_arrays = vec![
+            encode_binary_data_array(&chromatogram.times, BinaryPrecision::Float64, zlib_compression,
+                new_cv_param(TIME_ARRAY_CV_ACCESSION, "time array", Some((time_unit.get_unit_accession(), time_unit.get_unit_name()))))?,
+            encode_binary_data_array(&chromatogram.intensities, intensity_precision, zlib_compression,
+                new_cv_param(INTENSITY_ARRAY_CV_ACCESSION, "intensity array", Some((NUMBER_OF_DETECTOR_COUNTS_UNIT_ACCESSION, "number of detector counts"))))?,
+        ];
+
+        let mut user_params = vec![];
+        if let Some(ms_levels) = chromatogram.ms_levels.as_ref() {
+            user_params.push(UserParam {
+                name: "ms level filter".to_string(),
+                value: ms_levels.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(","),
+                r#type: "xsd:string".to_string(),
+            });
+        }
+        if let Some((mz_start, mz_end)) = chromatogram.mz_range {
+            for (name, mz) in [("lower m/z limit", mz_start), ("upper m/z limit", mz_end)] {
+                user_params.push(UserParam {
+                    name: name.to_string(),
+                    value: mz.to_string(),
+                    r#type: "xsd:double".to_string(),
+                });
+            }
+        }
+
+        Ok(Self {
+            index: index.to_string(),
+            id: chromatogram.id.clone(),
+            default_array_length: chromatogram.len().to_string(),
+            cv_params: vec![new_cv_param(chromatogram.kind.get_cv_accession(), chromatogram.kind.get_cv_name(), None)],
+            user_params,
+            precursor: chromatogram.transition.as_ref().map(_new_transition_precursor),
+            product: chromatogram.transition.as_ref().map(_new_transition_product),
+            binary_data_array_list: BinaryDataArrayList {
+                count: binary_data_arrays.len().to_string(),
+                binary_data_arrays,
+            },
+        })
+    }
+}
+
+fn _new_transition_precursor(transition: &SrmTransition) -> ChromatogramPrecursor {
+    let mut activation_cv_params = vec![new_cv_param(transition.activation.get_cv_accession(), transition.activation.get_cv_name(), None)];
+    if let Some(collision_energy) = transition.collision_energy {
+        activation_cv_params.push(_new_valued_cv_param(COLLISION_ENERGY_CV_ACCESSION, "collision energy", collision_energy, (ELECTRONVOLT_UNIT_ACCESSION, "electronvolt")));
+    }
+
+    ChromatogramPrecursor {
+        isolation_window: IsolationWindow {
+            cv_params: vec![_new_valued_cv_param(ISOLATION_WINDOW_TARGET_MZ_CV_ACCESSION, "isolation window target m/z", transition.precursor_mz, (MZ_UNIT_ACCESSION, "m/z"))],
+        },
+        activation: Activation { cv_params: activation_cv_params },
+    }
+}
+
+fn _new_transition_product(transition: &SrmTransition) -> ChromatogramProduct {
+    let product_mz = transition.get_product_mz();
+
+    ChromatogramProduct {
+        isolation_window: IsolationWindow {
+            cv_params: vec![
+                _new_valued_cv_param(ISOLATION_WINDOW_TARGET_MZ_CV_ACCESSION, "isolation window target m/z", product_mz, (MZ_UNIT_ACCESSION, "m/z")),
+                _new_valued_cv_param(ISOLATION_WINDOW_LOWER_OFFSET_CV_ACCESSION, "isolation window lower offset", product_mz - transition.product_mz_start, (MZ_UNIT_ACCESSION, "m/z")),
+                _new_valued_cv_param(ISOLATION_WINDOW_UPPER_OFFSET_CV_ACCESSION, "isolation window upper offset", transition.product_mz_end - product_mz, (MZ_UNIT_ACCESSION, "m/z")),
+            ],
+        },
+    }
+}
+
+fn _new_valued_cv_param(accession: &str, name: &str, value: f64, unit: (&str, &str)) -> CvParam {
+    CvParam {
+        value: Some(value.to_string()),
+        ..new_cv_param(accession, name, Some(unit))
+    }
+}
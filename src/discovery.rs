@@ -0,0 +1,273 @@
+//! Discovery of the ThermoRawFileParser assemblies and of the Mono runtime, see `MonoEmbeddinator::configure_with_discovery`.
+//!
+//! Each location is searched in the following order, the first valid candidate being retained:
+//! 1. the explicit configuration (which is authoritative: the search stops there if it is invalid)
+//! 2. the environment variables (`THERMO_STREAMER_ASSEMBLY_DIR` for the assemblies, `MONO_ROOT` then `MONO_PATH` for Mono)
+//! 3. `pkg-config mono-2` (Mono only)
+//! 4. the well-known installation prefixes (Mono only)
+//! 5. the directory of the current executable
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory of the ThermoRawFileParser assemblies.
+pub const ASSEMBLY_DIR_ENV_VAR: &str = "THERMO_STREAMER_ASSEMBLY_DIR";
+/// Installation prefix of Mono (e.g. "/usr"), whose "lib" sub-directory contains the runtime assemblies.
+pub const MONO_ROOT_ENV_VAR: &str = "MONO_ROOT";
+// Legacy: search path of the Mono assemblies (e.g. "/usr/lib/mono/4.5"), only the part preceding "mono" is used
+const MONO_PATH_ENV_VAR: &str = "MONO_PATH";
+
+const RAW_FILE_PARSER_ASSEMBLY_FILE_NAME: &str = "ThermoRawFileParser.dll";
+// Location of the core library of the .NET profile used by Embeddinator, relative to the Mono runtime directory
+const MONO_CORE_LIBRARY_PATH: &str = "mono/4.5/mscorlib.dll";
+// Name of the directory where build.rs copies the assemblies, next to the executables
+const RAW_FILE_PARSER_DIR_NAME: &str = "rawfileparser";
+
+#[cfg(windows)]
+const WELL_KNOWN_MONO_RUNTIME_DIRS: &[&str] = &[r"C:\Program Files\Mono\lib"];
+#[cfg(not(windows))]
+const WELL_KNOWN_MONO_RUNTIME_DIRS: &[&str] = &["/usr/lib", "/usr/local/lib", "/opt/mono/lib"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscoverySource {
+    ExplicitConfig,
+    EnvironmentVariable(&'static str),
+    PkgConfig,
+    WellKnownPrefix,
+    ExecutableDirectory,
+}
+
+impl fmt::Display for DiscoverySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscoverySource::ExplicitConfig => write!(f, "explicit config"),
+            DiscoverySource::EnvironmentVariable(name) => write!(f, "environment variable {}", name),
+            DiscoverySource::PkgConfig => write!(f, "pkg-config mono-2"),
+            DiscoverySource::WellKnownPrefix => write!(f, "well-known prefix"),
+            DiscoverySource::ExecutableDirectory => write!(f, "executable directory"),
+        }
+    }
+}
+
+/// A location evaluated during the discovery.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveryCandidate {
+    pub source: DiscoverySource,
+    /// None if the source didn't provide any path (e.g. unset environment variable)
+    pub path: Option<PathBuf>,
+    /// None if the candidate has been retained
+    pub rejection_reason: Option<String>,
+}
+
+impl DiscoveryCandidate {
+    pub fn is_accepted(&self) -> bool {
+        self.path.is_some() && self.rejection_reason.is_none()
+    }
+}
+
+/// Candidates evaluated for each location, see `MonoDiscovery::discover`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiscoveryReport {
+    pub assembly_dir_candidates: Vec<DiscoveryCandidate>,
+    pub mono_runtime_dir_candidates: Vec<DiscoveryCandidate>,
+}
+
+impl DiscoveryReport {
+    /// Returns the retained directory of the ThermoRawFileParser assemblies.
+    pub fn get_assembly_dir(&self) -> Option<&Path> {
+        _find_accepted_path(&self.assembly_dir_candidates)
+    }
+
+    /// Returns the retained directory of the Mono runtime assemblies (the one containing the "mono" directory).
+    pub fn get_mono_runtime_dir(&self) -> Option<&Path> {
+        _find_accepted_path(&self.mono_runtime_dir_candidates)
+    }
+}
+
+impl fmt::Display for DiscoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("ThermoRawFileParser assemblies", &self.assembly_dir_candidates),
+            ("Mono runtime", &self.mono_runtime_dir_candidates),
+        ];
+
+        for (description, candidates) in sections {
+            writeln!(f, "{}:", description)?;
+            if candidates.is_empty() {
+                writeln!(f, "  no candidate")?;
+            }
+
+            for candidate in candidates {
+                let path_str = candidate.path.as_ref().map(|p| format!("'{}'", p.display())).unwrap_or_else(|| "-".to_string());
+                match candidate.rejection_reason.as_ref() {
+                    Some(reason) => writeln!(f, "  [rejected] {} ({}): {}", path_str, candidate.source, reason)?,
+                    None => writeln!(f, "  [accepted] {} ({})", path_str, candidate.source)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Searches the ThermoRawFileParser assemblies and the Mono runtime (see the module documentation for the search order).
+#[derive(Clone, Debug, Default)]
+pub struct MonoDiscovery {
+    assembly_dir: Option<PathBuf>,
+    mono_runtime_dir: Option<PathBuf>,
+}
+
+impl MonoDiscovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_assembly_dir<P: AsRef<Path>>(mut self, assembly_dir: P) -> Self {
+        self.assembly_dir = Some(assembly_dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn with_mono_runtime_dir<P: AsRef<Path>>(mut self, mono_runtime_dir: P) -> Self {
+        self.mono_runtime_dir = Some(mono_runtime_dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn discover(&self) -> DiscoveryReport {
+        let mut report = DiscoveryReport::default();
+
+        let exe_dir = std::env::current_exe().ok().and_then(|exe_path| exe_path.parent().map(Path::to_path_buf));
+
+        // ThermoRawFileParser assemblies
+        let mut assembly_dir_search = CandidateSearch::new(&mut report.assembly_dir_candidates, _check_assembly_dir);
+        if let Some(assembly_dir) = self.assembly_dir.as_ref() {
+            assembly_dir_search.evaluate_explicit(assembly_dir);
+        }
+        assembly_dir_search.evaluate_env_var(ASSEMBLY_DIR_ENV_VAR, PathBuf::from);
+        if let Some(exe_dir) = exe_dir.as_ref() {
+            assembly_dir_search.evaluate(DiscoverySource::ExecutableDirectory, exe_dir.join(RAW_FILE_PARSER_DIR_NAME));
+            // Test and bench executables are located in the "deps" sub-directory of the target directory
+            if exe_dir.ends_with("deps") {
+                if let Some(target_dir) = exe_dir.parent() {
+                    assembly_dir_search.evaluate(DiscoverySource::ExecutableDirectory, target_dir.join(RAW_FILE_PARSER_DIR_NAME));
+                }
+            }
+        }
+
+        let assembly_dir = report.get_assembly_dir().map(Path::to_path_buf);
+
+        // Mono runtime
+        let mut mono_runtime_dir_search = CandidateSearch::new(&mut report.mono_runtime_dir_candidates, _check_mono_runtime_dir);
+        if let Some(mono_runtime_dir) = self.mono_runtime_dir.as_ref() {
+            mono_runtime_dir_search.evaluate_explicit(mono_runtime_dir);
+        }
+        mono_runtime_dir_search.evaluate_env_var(MONO_ROOT_ENV_VAR, |mono_root| PathBuf::from(mono_root).join("lib"));
+        mono_runtime_dir_search.evaluate_env_var(MONO_PATH_ENV_VAR, |mono_path| {
+            PathBuf::from(mono_path.split("mono").next().unwrap_or(&mono_path))
+        });
+        mono_runtime_dir_search.evaluate_pkg_config();
+        for well_known_dir in WELL_KNOWN_MONO_RUNTIME_DIRS {
+            mono_runtime_dir_search.evaluate(DiscoverySource::WellKnownPrefix, PathBuf::from(well_known_dir));
+        }
+        // Note: a runtime may be bundled with the assemblies (this was the only supported layout on Windows)
+        for bundle_dir in exe_dir.into_iter().chain(assembly_dir) {
+            mono_runtime_dir_search.evaluate(DiscoverySource::ExecutableDirectory, bundle_dir);
+        }
+
+        report
+    }
+}
+
+// Records the evaluated candidates until a valid one is found
+struct CandidateSearch<'a> {
+    candidates: &'a mut Vec<DiscoveryCandidate>,
+    check_fn: fn(&Path) -> Result<(), String>,
+    done: bool,
+}
+
+impl<'a> CandidateSearch<'a> {
+    fn new(candidates: &'a mut Vec<DiscoveryCandidate>, check_fn: fn(&Path) -> Result<(), String>) -> Self {
+        Self { candidates, check_fn, done: false }
+    }
+
+    fn evaluate(&mut self, source: DiscoverySource, path: PathBuf) {
+        if self.done {
+            return;
+        }
+
+        let rejection_reason = (self.check_fn)(&path).err();
+        self.done = rejection_reason.is_none();
+        self.candidates.push(DiscoveryCandidate { source, path: Some(path), rejection_reason });
+    }
+
+    // The search ends with the explicit config, even if it is invalid
+    fn evaluate_explicit(&mut self, path: &Path) {
+        self.evaluate(DiscoverySource::ExplicitConfig, path.to_path_buf());
+        self.done = true;
+    }
+
+    fn evaluate_env_var(&mut self, name: &'static str, to_path: impl FnOnce(String) -> PathBuf) {
+        if self.done {
+            return;
+        }
+
+        match std::env::var(name) {
+            Ok(value) if !value.trim().is_empty() => {
+                self.evaluate(DiscoverySource::EnvironmentVariable(name), to_path(value));
+            }
+            _ => self.reject(DiscoverySource::EnvironmentVariable(name), "not set"),
+        }
+    }
+
+    fn evaluate_pkg_config(&mut self) {
+        if self.done {
+            return;
+        }
+
+        match Command::new("pkg-config").args(["--variable=libdir", "mono-2"]).output() {
+            Ok(output) if output.status.success() => {
+                let libdir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if libdir.is_empty() {
+                    self.reject(DiscoverySource::PkgConfig, "no libdir variable");
+                } else {
+                    self.evaluate(DiscoverySource::PkgConfig, PathBuf::from(libdir));
+                }
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                self.reject(DiscoverySource::PkgConfig, &format!("package not found ({})", stderr));
+            }
+            Err(e) => self.reject(DiscoverySource::PkgConfig, &format!("can't run pkg-config ({})", e)),
+        }
+    }
+
+    fn reject(&mut self, source: DiscoverySource, reason: &str) {
+        self.candidates.push(DiscoveryCandidate { source, path: None, rejection_reason: Some(reason.to_string()) });
+    }
+}
+
+fn _check_assembly_dir(path: &Path) -> Result<(), String> {
+    _check_dir_contains(path, RAW_FILE_PARSER_ASSEMBLY_FILE_NAME)
+}
+
+fn _check_mono_runtime_dir(path: &Path) -> Result<(), String> {
+    _check_dir_contains(path, MONO_CORE_LIBRARY_PATH)
+}
+
+fn _check_dir_contains(path: &Path, relative_file_path: &str) -> Result<(), String> {
+    if !path.exists() {
+        return Err("no such directory".to_string());
+    }
+    if !path.is_dir() {
+        return Err("not a directory".to_string());
+    }
+    if !path.join(relative_file_path).is_file() {
+        return Err(format!("{} not found", relative_file_path));
+    }
+
+    Ok(())
+}
+
+fn _find_accepted_path(candidates: &[DiscoveryCandidate]) -> Option<&Path> {
+    candidates.iter().find(|candidate| candidate.is_accepted()).and_then(|candidate| candidate.path.as_deref())
+}
@@ -0,0 +1,145 @@
+use thiserror::Error;
+
+use crate::discovery::DiscoveryReport;
+
+// Maximum number of characters of an XML fragment quoted in an error message
+const MAX_XML_EXCERPT_LEN: usize = 200;
+
+/// Errors raised by the Mono runtime management, the spectrum access, the mzML parsers and the exports.
+///
+/// Functions returning an `anyhow::Result` propagate these errors unchanged, thus they can be retrieved with
+/// `anyhow::Error::downcast_ref::<StreamerError>()`.
+#[derive(Debug, Error)]
+pub enum StreamerError {
+    #[error("Mono Embeddinator is not configured")]
+    MonoNotConfigured,
+
+    #[error("Mono Embeddinator is disposed")]
+    MonoDisposed,
+
+    #[error("Mono Embeddinator can't be configured twice")]
+    MonoAlreadyConfigured,
+
+    #[error("the Mono runtime can't be disposed while {guard_count} guard(s) are alive")]
+    MonoRuntimeInUse { guard_count: usize },
+
+    /// The report lists each evaluated location and why it was rejected
+    #[error("can't find the ThermoRawFileParser assemblies or the Mono runtime\n{report}")]
+    DiscoveryFailed { report: DiscoveryReport },
+
+    #[error("can't find {description} at '{path}'")]
+    MissingDirectory { description: &'static str, path: String },
+
+    #[error("forbidden operation: Mono Embeddinator was initiated in thread '{init_thread_id}' but is now used from thread '{current_thread_id}'")]
+    WrongThread { init_thread_id: u64, current_thread_id: u64 },
+
+    #[error("can't attach thread '{thread}' to the Mono runtime: {reason}")]
+    ThreadAttachment { thread: String, reason: &'static str },
+
+    #[error("can't find the RAW file '{path}'")]
+    RawFileNotFound { path: String },
+
+    #[error("'{path}' is a directory, not a RAW file")]
+    RawFileIsDirectory { path: String },
+
+    #[error("can't read the RAW file '{path}': {reason}")]
+    RawFileNotReadable { path: String, reason: String },
+
+    #[error("'{path}' is not a Thermo RAW file (invalid header)")]
+    NotARawFile { path: String },
+
+    #[error("the RAW file '{path}' is still being acquired")]
+    RawFileInAcquisition { path: String },
+
+    #[error("the RAW file '{path}' contains no scan (first scan number = {first_scan_number}, last scan number = {last_scan_number})")]
+    NoScans { path: String, first_scan_number: i32, last_scan_number: i32 },
+
+    #[error("requested spectrum number ({scan_number}) is out of the scan range [{first_scan_number}, {last_scan_number}]")]
+    ScanOutOfRange { scan_number: u32, first_scan_number: u32, last_scan_number: u32 },
+
+    /// The offending XML is kept entirely, while the error message only quotes its beginning
+    #[error("can't parse the mzML fragment '{}': {source}", _excerpt(.fragment))]
+    XmlParse { source: quick_xml::DeError, fragment: String },
+
+    /// Managed exception thrown during a call to the ThermoRawFileParser glue
+    #[error("{exception_type} thrown by ThermoRawFileParser: {message}")]
+    DotNetException { exception_type: String, message: String, stack_trace: Option<String> },
+
+    /// Failure of the Embeddinator glue itself (assembly loading, class or method lookup...)
+    #[error("Embeddinator error: {message}")]
+    Embeddinator { message: String },
+
+    #[error("the Mono host has been shut down")]
+    MonoHostShutDown,
+
+    /// Failure to read or write a file or a writer handled by this crate (exports, index files, XIC targets...)
+    #[error("{context}: {source}")]
+    Io { context: String, source: std::io::Error },
+
+    /// Invalid usage of an export writer, or content that can't be written in its format
+    #[error("{format} export failed: {message}")]
+    Export { format: &'static str, message: String },
+
+    /// Invalid content of a file or a string read by this crate (MGF, XIC targets, index files...)
+    #[error("invalid {input}: {message}")]
+    InvalidInput { input: &'static str, message: String },
+
+    #[error("stale index cache: {reason}")]
+    StaleIndexCache { reason: String },
+
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+// Errors of this crate are propagated through anyhow, thus they are extracted instead of being wrapped again
+impl From<anyhow::Error> for StreamerError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<StreamerError>() {
+            Ok(streamer_error) => streamer_error,
+            Err(error) => StreamerError::Other(error),
+        }
+    }
+}
+
+impl StreamerError {
+    pub(crate) fn xml_parse(source: quick_xml::DeError, fragment: &str) -> Self {
+        StreamerError::XmlParse { source, fragment: fragment.to_string() }
+    }
+
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        StreamerError::Io { context: context.into(), source }
+    }
+
+    /// Converts the error of an export writer, the errors of this crate and the I/O errors being kept as such.
+    pub(crate) fn export(format: &'static str, error: anyhow::Error) -> Self {
+        _convert_error(error, |message| StreamerError::Export { format, message })
+    }
+
+    /// Converts the error raised while reading an input, the errors of this crate and the I/O errors being kept as such.
+    pub(crate) fn invalid_input(input: &'static str, error: anyhow::Error) -> Self {
+        _convert_error(error, |message| StreamerError::InvalidInput { input, message })
+    }
+}
+
+// The context of an I/O error is made of the messages wrapping it (if any)
+fn _convert_error(error: anyhow::Error, new_error: impl FnOnce(String) -> StreamerError) -> StreamerError {
+    let error = match error.downcast::<StreamerError>() {
+        Ok(streamer_error) => return streamer_error,
+        Err(error) => error,
+    };
+
+    let messages: Vec<String> = error.chain().map(|e| e.to_string()).collect();
+    match error.downcast::<std::io::Error>() {
+        Ok(source) if messages.len() > 1 => StreamerError::io(messages[..messages.len() - 1].join(": "), source),
+        Ok(source) => StreamerError::io("I/O error", source),
+        Err(error) => new_error(format!("{:#}", error)),
+    }
+}
+
+fn _excerpt(fragment: &str) -> String {
+    let fragment = fragment.trim();
+    match fragment.char_indices().nth(MAX_XML_EXCERPT_LEN) {
+        Some((idx, _)) => format!("{}...", &fragment[..idx]),
+        None => fragment.to_string(),
+    }
+}
@@ -0,0 +1,435 @@
+use anyhow::anyhow;
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread::JoinHandle;
+
+use crate::chromatogram::{Chromatogram, ChromatogramKind};
+use crate::error::StreamerError;
+use crate::index_cache::IndexCacheStatus;
+use crate::mono::MONO_EMBEDDINATOR;
+use crate::mzml::MzMLMetaData;
+use crate::mzml_spectrum::{MzMLSpectrum, MzMLSpectrumMetaData, SpectrumData};
+use crate::native_scan::NativeScan;
+use crate::run_info::RunInfo;
+use crate::scan_index::ScanIndex;
+use crate::spectrum_iterator::SpectrumQuery;
+use crate::streamer::RawFileStreamer;
+use crate::streamer_options::StreamerOptions;
+use crate::trailer_extra::TrailerExtra;
+use crate::xic::XicRequest;
+
+// Result of a job, downcast by the caller to the result type of the job
+type HostReply = Box<dyn Any + Send>;
+
+// Job executed on the Mono worker thread, which sends its own reply
+type HostJob = Box<dyn FnOnce(&mut OpenedRawFiles) + Send>;
+
+// Requests sent to the Mono worker thread
+enum HostRequest {
+    Run(HostJob),
+    Dispose { file_id: u64 },
+    Shutdown,
+}
+
+// RAW files opened on the Mono worker thread
+#[derive(Default)]
+struct OpenedRawFiles {
+    streamers: HashMap<u64, RawFileStreamer>,
+    next_file_id: u64,
+}
+
+impl OpenedRawFiles {
+    fn get(&self, file_id: u64) -> Result<&RawFileStreamer, StreamerError> {
+        self.streamers.get(&file_id).ok_or_else(|| StreamerError::Other(anyhow!("no opened RAW file with id {}", file_id)))
+    }
+}
+
+/// Owns the OS thread the Mono runtime is bound to.
+///
+/// Every call to the .NET side is executed on this thread, while the returned
+/// `RawFileStreamerHandle` values can be shared between threads (tokio, rayon...).
+/// Only one host can be started per process, since Mono can't be configured twice.
+pub struct MonoHost {
+    client: HostClient,
+    worker_thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for MonoHost {
+    fn drop(&mut self) {
+        let _ = self.client.sender.send(HostRequest::Shutdown);
+        if let Some(worker_thread) = self.worker_thread.take() {
+            let _ = worker_thread.join();
+        }
+    }
+}
+
+impl MonoHost {
+    pub fn start(raw_file_parser_directory: &str) -> Result<MonoHost, StreamerError> {
+        let (sender, receiver) = channel::<HostRequest>();
+        let (init_sender, init_receiver) = channel::<Result<(), StreamerError>>();
+        let raw_file_parser_directory = raw_file_parser_directory.to_string();
+
+        let worker_thread = std::thread::Builder::new()
+            .name("mono-host".to_string())
+            .spawn(move || {
+                let config_res = MONO_EMBEDDINATOR.lock().unwrap().configure(&raw_file_parser_directory);
+                let configured = config_res.is_ok();
+                let _ = init_sender.send(config_res);
+
+                if configured {
+                    Self::_serve_requests(receiver);
+                }
+            })
+            .map_err(|e| StreamerError::io("can't spawn the Mono host thread", e))?;
+
+        // Note: the thread only stops before replying if the configuration panicked
+        init_receiver.recv().map_err(|_| StreamerError::MonoHostShutDown)??;
+
+        Ok(MonoHost { client: HostClient::new(sender), worker_thread: Some(worker_thread) })
+    }
+
+    pub fn open(&self, raw_file_path: &str) -> Result<RawFileStreamerHandle, StreamerError> {
+        self.open_with_options(raw_file_path, &StreamerOptions::default())
+    }
+
+    pub fn open_with_options(&self, raw_file_path: &str, options: &StreamerOptions) -> Result<RawFileStreamerHandle, StreamerError> {
+        let (path, job_options) = (raw_file_path.to_string(), options.clone());
+        let (file_id, first_scan_number, last_scan_number) = self.client.call(move |files| {
+            let streamer = RawFileStreamer::new_with_options(&path, &job_options)?;
+            let opened_file = (files.next_file_id, streamer.get_first_scan_number(), streamer.get_last_scan_number());
+
+            files.streamers.insert(files.next_file_id, streamer);
+            files.next_file_id += 1;
+
+            Ok(opened_file)
+        })?;
+
+        Ok(RawFileStreamerHandle {
+            file_id,
+            raw_file_path: raw_file_path.to_string(),
+            first_scan_number,
+            last_scan_number,
+            options: options.clone(),
+            client: HostClient::new(self.client.sender.clone()),
+        })
+    }
+
+    fn _serve_requests(receiver: Receiver<HostRequest>) {
+        let mut files = OpenedRawFiles { next_file_id: 1, ..Default::default() };
+
+        for request in receiver.iter() {
+            match request {
+                HostRequest::Run(job) => job(&mut files),
+                HostRequest::Dispose { file_id } => {
+                    // Dropping the streamer disposes the .NET RAW file wrapper
+                    files.streamers.remove(&file_id);
+                }
+                HostRequest::Shutdown => break,
+            }
+        }
+
+        // Dispose the remaining RAW files while we are still on the Mono thread
+        files.streamers.clear();
+    }
+}
+
+// Sends jobs to the Mono worker thread, the reply channel being reused by the successive calls
+// (the lock being held until the reply is received, thus the replies can't be mixed).
+struct HostClient {
+    sender: Sender<HostRequest>,
+    reply_channel: Mutex<(Sender<HostReply>, Receiver<HostReply>)>,
+}
+
+impl std::fmt::Debug for HostClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HostClient")
+    }
+}
+
+impl HostClient {
+    fn new(sender: Sender<HostRequest>) -> Self {
+        Self { sender, reply_channel: Mutex::new(channel()) }
+    }
+
+    fn call<T, F>(&self, job: F) -> Result<T, StreamerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut OpenedRawFiles) -> Result<T, StreamerError> + Send + 'static {
+
+        let reply_channel = self.reply_channel.lock().unwrap_or_else(PoisonError::into_inner);
+        let (reply_sender, reply_receiver) = &*reply_channel;
+
+        let pending_reply = PendingReply::<T> { reply: Some(reply_sender.clone()), _result: PhantomData };
+        let host_job: HostJob = Box::new(move |files| pending_reply.send(job(files)));
+        self.sender.send(HostRequest::Run(host_job)).map_err(|_| StreamerError::MonoHostShutDown)?;
+
+        // Note: a reply is always sent, since dropping a job without running it replies MonoHostShutDown
+        let reply = reply_receiver.recv().map_err(|_| StreamerError::MonoHostShutDown)?;
+        *reply.downcast::<Result<T, StreamerError>>().expect("unexpected type of Mono host reply")
+    }
+}
+
+// Reply of a job, which is MonoHostShutDown if the job is dropped without being run (e.g. the worker thread stopped)
+struct PendingReply<T: Send + 'static> {
+    reply: Option<Sender<HostReply>>,
+    _result: PhantomData<fn() -> T>,
+}
+
+impl<T: Send + 'static> PendingReply<T> {
+    fn send(mut self, result: Result<T, StreamerError>) {
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.send(Box::new(result));
+        }
+    }
+}
+
+impl<T: Send + 'static> Drop for PendingReply<T> {
+    fn drop(&mut self) {
+        if let Some(reply) = self.reply.take() {
+            let _ = reply.send(Box::new(Err::<T, StreamerError>(StreamerError::MonoHostShutDown)));
+        }
+    }
+}
+
+/// Thread-safe handle on a RAW file opened by a `MonoHost`.
+///
+/// The underlying `RawFileStreamer` lives on the Mono thread and is disposed when the handle is dropped.
+/// Its accessors are forwarded to the Mono thread, the calls made on a handle being serialized.
+#[derive(Debug)]
+pub struct RawFileStreamerHandle {
+    file_id: u64,
+    raw_file_path: String,
+    first_scan_number: u32,
+    last_scan_number: u32,
+    options: StreamerOptions,
+    client: HostClient,
+}
+
+impl Drop for RawFileStreamerHandle {
+    fn drop(&mut self) {
+        let _ = self.client.sender.send(HostRequest::Dispose { file_id: self.file_id });
+    }
+}
+
+impl RawFileStreamerHandle {
+    pub fn get_raw_file_path(&self) -> &str {
+        &self.raw_file_path
+    }
+
+    pub fn get_first_scan_number(&self) -> u32 {
+        self.first_scan_number
+    }
+
+    pub fn get_last_scan_number(&self) -> u32 {
+        self.last_scan_number
+    }
+
+    pub fn get_options(&self) -> &StreamerOptions {
+        &self.options
+    }
+
+    pub fn get_metadata(&self) -> Result<MzMLMetaData, StreamerError> {
+        self._call(|streamer| Ok(streamer.get_metadata().clone()))
+    }
+
+    pub fn get_run_info(&self) -> Result<RunInfo, StreamerError> {
+        self._call(|streamer| streamer.get_run_info())
+    }
+
+    /// Returns a copy of the scan index of the file, if it has been opened with one.
+    pub fn get_scan_index(&self) -> Result<Option<ScanIndex>, StreamerError> {
+        self._call(|streamer| Ok(streamer.get_scan_index().cloned()))
+    }
+
+    pub fn get_index_cache_status(&self) -> Result<Option<IndexCacheStatus>, StreamerError> {
+        self._call(|streamer| Ok(streamer.get_index_cache_status().cloned()))
+    }
+
+    /// Returns a selection of all the spectra of the file, which can be restricted then iterated (see `SpectrumSelection`).
+    pub fn spectra(&self) -> HandleSpectrumSelection<'_> {
+        HandleSpectrumSelection { handle: self, query: SpectrumQuery::new(self.first_scan_number, self.last_scan_number) }
+    }
+
+    pub fn get_spectrum(&self, number: u32) -> Result<MzMLSpectrum, StreamerError> {
+        self._call(move |streamer| streamer.get_spectrum(number))
+    }
+
+    pub fn get_spectrum_metadata(&self, number: u32) -> Result<MzMLSpectrumMetaData, StreamerError> {
+        self._call(move |streamer| streamer.get_spectrum_metadadata(number))
+    }
+
+    pub fn get_spectrum_data(&self, number: u32) -> Result<SpectrumData, StreamerError> {
+        self._call(move |streamer| streamer.get_spectrum_data(number))
+    }
+
+    pub fn trailer_extra(&self, number: u32) -> Result<TrailerExtra, StreamerError> {
+        self._call(move |streamer| streamer.trailer_extra(number))
+    }
+
+    pub fn get_native_scan(&self, number: u32) -> Result<NativeScan, StreamerError> {
+        self._call(move |streamer| streamer.get_native_scan(number))
+    }
+
+    pub fn get_native_scan_header(&self, number: u32) -> Result<NativeScan, StreamerError> {
+        self._call(move |streamer| streamer.get_native_scan_header(number))
+    }
+
+    pub fn tic(&self) -> Result<Chromatogram, StreamerError> {
+        self._call(|streamer| streamer.tic())
+    }
+
+    pub fn bpc(&self) -> Result<Chromatogram, StreamerError> {
+        self._call(|streamer| streamer.bpc())
+    }
+
+    pub fn get_chromatogram(&self, kind: ChromatogramKind, ms_levels: Option<&[u8]>) -> Result<Chromatogram, StreamerError> {
+        let ms_levels = ms_levels.map(|ms_levels| ms_levels.to_vec());
+        self._call(move |streamer| streamer.get_chromatogram(kind, ms_levels.as_deref()))
+    }
+
+    pub fn has_srm_scans(&self) -> Result<bool, StreamerError> {
+        self._call(|streamer| streamer.has_srm_scans())
+    }
+
+    pub fn srm_chromatograms(&self) -> Result<Vec<Chromatogram>, StreamerError> {
+        self._call(|streamer| streamer.srm_chromatograms())
+    }
+
+    pub fn extract_xics(&self, requests: &[XicRequest]) -> Result<Vec<Chromatogram>, StreamerError> {
+        let requests = requests.to_vec();
+        self._call(move |streamer| streamer.extract_xics(&requests))
+    }
+
+    fn _call<T, F>(&self, streamer_call: F) -> Result<T, StreamerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&RawFileStreamer) -> Result<T, StreamerError> + Send + 'static {
+
+        let file_id = self.file_id;
+        self.client.call(move |files| files.get(file_id).and_then(streamer_call))
+    }
+}
+
+/// Selection of the spectra of a `RawFileStreamerHandle`, created with `RawFileStreamerHandle::spectra()`.
+///
+/// Same as `SpectrumSelection`, the selection being resolved then each spectrum being loaded on the Mono thread.
+pub struct HandleSpectrumSelection<'a> {
+    handle: &'a RawFileStreamerHandle,
+    query: SpectrumQuery,
+}
+
+impl HandleSpectrumSelection<'_> {
+    /// Restricts the iteration to a range of scan numbers (clamped to the file scan range).
+    pub fn with_scan_range(mut self, scan_range: RangeInclusive<u32>) -> Self {
+        self.query = self.query.with_scan_range(scan_range, self.handle.first_scan_number ..= self.handle.last_scan_number);
+        self
+    }
+
+    /// Restricts the iteration to the given MS levels.
+    pub fn with_ms_levels(mut self, ms_levels: &[u8]) -> Self {
+        self.query.ms_levels = Some(ms_levels.to_vec());
+        self
+    }
+
+    /// Restricts the iteration to a retention time window (inclusive bounds, in minutes).
+    pub fn with_rt_range(mut self, min_rt: f64, max_rt: f64) -> Self {
+        self.query.rt_range = Some((min_rt, max_rt));
+        self
+    }
+
+    /// Only loads the spectra metadata (the peaks of returned spectra are empty).
+    pub fn metadata_only(mut self) -> Self {
+        self.query.load_data = false;
+        self.query.load_metadata = true;
+        self
+    }
+
+    /// Only loads the spectra peaks (the metadata of returned spectra are default values).
+    pub fn data_only(mut self) -> Self {
+        self.query.load_data = true;
+        self.query.load_metadata = false;
+        self
+    }
+}
+
+impl<'a> IntoIterator for HandleSpectrumSelection<'a> {
+    type Item = Result<MzMLSpectrum, StreamerError>;
+    type IntoIter = HandleSpectrumIterator<'a>;
+
+    fn into_iter(self) -> HandleSpectrumIterator<'a> {
+        let query = self.query.clone();
+        let (scan_numbers, selection_error) = self.handle._call(move |streamer| Ok(query.resolve(streamer)))
+            .unwrap_or_else(|e| (vec![], Some(e)));
+
+        HandleSpectrumIterator {
+            handle: self.handle,
+            back: scan_numbers.len(),
+            scan_numbers,
+            selection_error,
+            load_data: self.query.load_data,
+            load_metadata: self.query.load_metadata,
+            front: 0,
+        }
+    }
+}
+
+/// Iterator over the spectra of a `HandleSpectrumSelection` (see `SpectrumIterator`).
+pub struct HandleSpectrumIterator<'a> {
+    handle: &'a RawFileStreamerHandle,
+    scan_numbers: Vec<u32>,
+    selection_error: Option<StreamerError>,
+    load_data: bool,
+    load_metadata: bool,
+    // Cursors in the selected scan numbers (back is exclusive)
+    front: usize,
+    back: usize,
+}
+
+impl HandleSpectrumIterator<'_> {
+    fn _load_spectrum(&self, scan_number: u32) -> Result<MzMLSpectrum, StreamerError> {
+        let (load_data, load_metadata) = (self.load_data, self.load_metadata);
+        self.handle._call(move |streamer| streamer._get_mzml_spectrum(scan_number, load_data, load_metadata).map_err(StreamerError::from))
+    }
+}
+
+impl Iterator for HandleSpectrumIterator<'_> {
+    type Item = Result<MzMLSpectrum, StreamerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front < self.back {
+            let scan_number = self.scan_numbers[self.front];
+            self.front += 1;
+            return Some(self._load_spectrum(scan_number));
+        }
+
+        // Selection errors are reported after the scans that could be resolved
+        self.selection_error.take().map(Err)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_remaining = self.back - self.front + usize::from(self.selection_error.is_some());
+
+        (n_remaining, Some(n_remaining))
+    }
+}
+
+impl DoubleEndedIterator for HandleSpectrumIterator<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.selection_error.take() {
+            return Some(Err(error));
+        }
+
+        if self.front < self.back {
+            self.back -= 1;
+            return Some(self._load_spectrum(self.scan_numbers[self.back]));
+        }
+
+        None
+    }
+}
+
+impl ExactSizeIterator for HandleSpectrumIterator<'_> {}
@@ -0,0 +1,157 @@
+use serde::{Serialize, Deserialize};
+use sha1::{Digest, Sha1};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::error::StreamerError;
+use crate::mzml::MzMLMetaData;
+use crate::scan_index::ScanIndex;
+use crate::streamer_options::PeakPicking;
+
+pub const INDEX_CACHE_FORMAT_VERSION: u32 = 1;
+pub const INDEX_CACHE_FILE_EXTENSION: &str = "tfscache.json";
+
+// Number of bytes hashed at the beginning of the RAW file
+const FINGERPRINT_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Identifies the content of a RAW file without reading it entirely.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawFileFingerprint {
+    pub file_size: u64,
+    pub modified_time_secs: u64,
+    pub modified_time_nanos: u32,
+    /// SHA-1 of the first block (64 KiB) of the file
+    pub first_block_sha1: String,
+}
+
+impl RawFileFingerprint {
+    pub fn compute(raw_file_path: &Path) -> Result<Self, StreamerError> {
+        let read_error = |e| StreamerError::io(format!("can't compute fingerprint of file '{}'", raw_file_path.display()), e);
+
+        let file_metadata = std::fs::metadata(raw_file_path).map_err(read_error)?;
+        // Note: a modification time before the epoch is handled as the epoch
+        let modified_time = file_metadata.modified().map_err(read_error)?.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut first_block = Vec::with_capacity(FINGERPRINT_BLOCK_SIZE as usize);
+        std::fs::File::open(raw_file_path).and_then(|file| file.take(FINGERPRINT_BLOCK_SIZE).read_to_end(&mut first_block))
+            .map_err(read_error)?;
+
+        Ok(Self {
+            file_size: file_metadata.len(),
+            modified_time_secs: modified_time.as_secs(),
+            modified_time_nanos: modified_time.subsec_nanos(),
+            first_block_sha1: format!("{:x}", Sha1::digest(&first_block)),
+        })
+    }
+}
+
+/// Where the index cache files are written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum IndexCacheLocation {
+    /// The cache is written next to the RAW file (e.g. "small.RAW.tfscache.json").
+    #[default]
+    NextToRawFile,
+    /// The cache is written in the given directory, with a name derived from the absolute RAW file path.
+    Directory(PathBuf),
+}
+
+/// Outcome of the index cache lookup performed when opening a RAW file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexCacheStatus {
+    /// A valid cache was found and reused.
+    Reused,
+    /// No cache was found, a new one has been written.
+    Created,
+    /// The cache was stale or corrupt (for the given reason), a new one has been written.
+    Rebuilt(String),
+    /// The cache was missing or unusable, and the new one couldn't be written (for the given reason).
+    /// The metadata and the scan index are still available, but they will be built again on the next opening.
+    NotWritten(String),
+}
+
+/// Persisted metadata and scan index of a RAW file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexCache {
+    pub format_version: u32,
+    pub fingerprint: RawFileFingerprint,
+    pub metadata: MzMLMetaData,
+    pub scan_index: ScanIndex,
+    /// Peak picking used to build the cache, which may change the meta-data
+    #[serde(default)]
+    pub peak_picking: PeakPicking,
+}
+
+impl IndexCache {
+    pub fn new(fingerprint: RawFileFingerprint, metadata: MzMLMetaData, scan_index: ScanIndex) -> Self {
+        Self { format_version: INDEX_CACHE_FORMAT_VERSION, fingerprint, metadata, scan_index, peak_picking: PeakPicking::default() }
+    }
+
+    pub fn get_cache_file_path(raw_file_path: &Path, location: &IndexCacheLocation) -> Result<PathBuf, StreamerError> {
+        match location {
+            IndexCacheLocation::NextToRawFile => {
+                Ok(PathBuf::from(format!("{}.{}", raw_file_path.display(), INDEX_CACHE_FILE_EXTENSION)))
+            }
+            IndexCacheLocation::Directory(cache_dir) => {
+                let abs_raw_file_path = std::fs::canonicalize(raw_file_path)
+                    .map_err(|e| StreamerError::io(format!("can't resolve path of file '{}'", raw_file_path.display()), e))?;
+                let file_name = raw_file_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+                // Prevent collisions between RAW files having the same name in different directories
+                let path_digest = format!("{:x}", Sha1::digest(abs_raw_file_path.to_string_lossy().as_bytes()));
+
+                Ok(cache_dir.join(format!("{}.{}.{}", file_name, &path_digest[..16], INDEX_CACHE_FILE_EXTENSION)))
+            }
+        }
+    }
+
+    /// Loads a cache file and checks it matches the given fingerprint.
+    ///
+    /// Returns `Ok(None)` when the file doesn't exist, and an error describing why the cache is unusable if it is stale or corrupt.
+    pub fn read_if_valid(cache_file_path: &Path, fingerprint: &RawFileFingerprint) -> Result<Option<Self>, StreamerError> {
+        if !cache_file_path.is_file() {
+            return Ok(None);
+        }
+
+        let file = std::fs::File::open(cache_file_path)
+            .map_err(|e| StreamerError::io(format!("can't open index cache file '{}'", cache_file_path.display()), e))?;
+        let index_cache: IndexCache = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| StreamerError::InvalidInput { input: "index cache", message: e.to_string() })?;
+
+        if index_cache.format_version != INDEX_CACHE_FORMAT_VERSION {
+            return Err(StreamerError::StaleIndexCache {
+                reason: format!("format version {} while {} is expected", index_cache.format_version, INDEX_CACHE_FORMAT_VERSION),
+            });
+        }
+        if &index_cache.fingerprint != fingerprint {
+            return Err(StreamerError::StaleIndexCache { reason: "the RAW file fingerprint has changed".to_string() });
+        }
+
+        Ok(Some(index_cache))
+    }
+
+    pub fn write_to_file(&self, cache_file_path: &Path) -> Result<(), StreamerError> {
+        let write_error = |e| StreamerError::io(format!("can't write index cache file '{}'", cache_file_path.display()), e);
+
+        let cache_dir = match cache_file_path.parent() {
+            Some(parent_dir) if !parent_dir.as_os_str().is_empty() => parent_dir,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(cache_dir)
+            .map_err(|e| StreamerError::io(format!("can't create index cache directory '{}'", cache_dir.display()), e))?;
+
+        // Write to a temporary file first, so that an interrupted write can't leave a truncated cache behind
+        // Note: the temporary file is unique, thus concurrent writers of the same cache don't mix their content
+        let mut tmp_file = tempfile::NamedTempFile::new_in(cache_dir)
+            .map_err(|e| StreamerError::io(format!("can't create temporary index cache file in '{}'", cache_dir.display()), e))?;
+
+        let mut tmp_file_writer = std::io::BufWriter::new(tmp_file.as_file_mut());
+        serde_json::to_writer(&mut tmp_file_writer, self).map_err(|e| write_error(e.into()))?;
+        tmp_file_writer.flush().map_err(write_error)?;
+        drop(tmp_file_writer);
+
+        tmp_file.persist(cache_file_path).map_err(|e| write_error(e.error))?;
+
+        Ok(())
+    }
+}
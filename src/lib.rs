@@ -1,8 +1,30 @@
 mod bindings;
+#[cfg(feature = "arrow")]
+pub mod arrow_batches;
+pub mod chromatogram;
+pub mod discovery;
+pub mod error;
+pub mod host;
+pub mod index_cache;
+pub mod mgf;
 pub mod mono;
 pub mod mzml;
 pub mod mzml_spectrum;
+pub mod mzml_writer;
+pub mod native_scan;
+#[cfg(feature = "parquet")]
+pub mod parquet_writer;
+pub mod raw_file_probe;
+pub mod run_info;
+pub mod scan_filter;
+pub mod scan_index;
+pub mod spectrum_iterator;
+pub mod srm;
 pub mod streamer;
+pub mod streamer_options;
+pub mod trailer_extra;
+pub mod xic;
+pub mod xic_batch;
 pub mod prelude;
 
 pub use prelude::*;
@@ -13,10 +35,7 @@ mod tests {
 
     //use anyhow::*;
 
-    #[test]
-    fn deserialize_mzml_metadata() {
-
-        let mzml_header_str = r#"<?xml version="1.0" encoding="utf-8"?>
+    const MZML_HEADER_STR: &str = r#"<?xml version="1.0" encoding="utf-8"?>
 <mzML xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.0.xsd" version="1.1.0" id="smal
 l" xmlns="http://psi.hupo.org/ms/mzml">
   <cvList count="2">
@@ -94,7 +113,11 @@ l" xmlns="http://psi.hupo.org/ms/mzml">
   <run id="small" defaultInstrumentConfigurationRef="IC1" startTimeStamp="2005-07-20T14:44:22.377Z" defaultSourceFileRef="RAW1" sampleRef="sample_1"/>
 </mzML>
 "#;
-        let mzml_metadata = parse_mzml_metadata(mzml_header_str).unwrap();
+
+    #[test]
+    fn deserialize_mzml_metadata() {
+
+        let mzml_metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
         assert_eq!(mzml_metadata.run.default_source_file_ref, "RAW1");
         assert_eq!(mzml_metadata.software_list.software_entries.first().unwrap().version, "1.2.3");
 
@@ -107,6 +130,895 @@ l" xmlns="http://psi.hupo.org/ms/mzml">
         assert_eq!(sample_number_param_opt.unwrap().value.as_ref().unwrap(), "1");
     }
 
+    #[test]
+    fn report_xml_parse_errors() {
+        let truncated_header = &MZML_HEADER_STR[..MZML_HEADER_STR.len() / 2];
+
+        let err = parse_mzml_metadata(truncated_header).unwrap_err();
+        match &err {
+            StreamerError::XmlParse { fragment, .. } => assert_eq!(fragment, truncated_header),
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(err.to_string().len() < 500, "the error message should only quote the beginning of the fragment");
+
+        // The error can still be retrieved once wrapped in an anyhow error
+        let wrapped_err = anyhow::Error::from(parse_mzml_spectrum_metadata("<spectrum").unwrap_err());
+        assert!(matches!(wrapped_err.downcast_ref::<StreamerError>(), Some(StreamerError::XmlParse { .. })));
+        assert!(matches!(StreamerError::from(wrapped_err), StreamerError::XmlParse { .. }));
+    }
+
+    #[test]
+    fn probe_raw_files() {
+        let raw_file_probe = RawFileStreamer::probe("./resources/small.RAW").unwrap();
+        assert!(raw_file_probe.path.ends_with("small.RAW") && std::path::Path::new(&raw_file_probe.path).is_absolute());
+        assert_eq!(raw_file_probe.file_size, 1504354);
+        assert_eq!(raw_file_probe.format_version, 57);
+
+        // Same time than the run start time stamp (2005-07-20T14:44:22.377Z)
+        let creation_time = raw_file_probe.creation_time.unwrap().duration_since(std::time::UNIX_EPOCH).unwrap();
+        assert_eq!(creation_time.as_millis(), 1121870662377);
+
+        assert!(matches!(RawFileStreamer::probe("./resources/missing.RAW"), Err(StreamerError::RawFileNotFound { .. })));
+        assert!(matches!(RawFileStreamer::probe("./resources"), Err(StreamerError::RawFileIsDirectory { .. })));
+        assert!(matches!(RawFileStreamer::probe("./Cargo.toml"), Err(StreamerError::NotARawFile { .. })));
+
+        let raw_file_bytes = std::fs::read("./resources/small.RAW").unwrap();
+        assert_eq!(parse_raw_file_header(&raw_file_bytes[..64]).map(|(version, _)| version), Some(57));
+        assert_eq!(parse_raw_file_header(&raw_file_bytes[..20]), None);
+        assert_eq!(parse_raw_file_header(&raw_file_bytes[1..65]), None);
+    }
+
+    #[test]
+    fn discover_mono_runtime() {
+        let tmp_dir = std::env::temp_dir().join("discover_mono_runtime");
+        let assembly_dir = tmp_dir.join("rawfileparser");
+        let mono_runtime_dir = tmp_dir.join("lib");
+        std::fs::create_dir_all(&assembly_dir).unwrap();
+        std::fs::create_dir_all(mono_runtime_dir.join("mono/4.5")).unwrap();
+        std::fs::write(assembly_dir.join("ThermoRawFileParser.dll"), "").unwrap();
+        std::fs::write(mono_runtime_dir.join("mono/4.5/mscorlib.dll"), "").unwrap();
+
+        let report = MonoDiscovery::new().with_assembly_dir(&assembly_dir).with_mono_runtime_dir(&mono_runtime_dir).discover();
+        assert_eq!(report.get_assembly_dir(), Some(assembly_dir.as_path()));
+        assert_eq!(report.get_mono_runtime_dir(), Some(mono_runtime_dir.as_path()));
+        assert_eq!(report.mono_runtime_dir_candidates.len(), 1);
+        assert_eq!(report.mono_runtime_dir_candidates[0].source, DiscoverySource::ExplicitConfig);
+
+        // An invalid explicit config is reported, and stops the search
+        let report = MonoDiscovery::new().with_assembly_dir(&mono_runtime_dir).with_mono_runtime_dir(tmp_dir.join("missing")).discover();
+        assert_eq!(report.get_assembly_dir(), None);
+        assert_eq!(report.get_mono_runtime_dir(), None);
+        assert_eq!(report.assembly_dir_candidates[0].rejection_reason.as_deref(), Some("ThermoRawFileParser.dll not found"));
+        assert_eq!(report.mono_runtime_dir_candidates[0].rejection_reason.as_deref(), Some("no such directory"));
+        assert!(report.to_string().contains("[rejected] '") && !report.to_string().contains("[accepted]"));
+
+        // Without explicit config, the assemblies copied by build.rs next to the test executable are found
+        assert!(MonoDiscovery::new().discover().get_assembly_dir().is_some());
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn read_run_info() {
+        let mzml_metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
+        let run_info = RunInfo::from_mzml_metadata(&mzml_metadata);
+        assert_eq!(run_info.acquisition_date.as_deref(), Some("2005-07-20T14:44:22.377Z"));
+        assert_eq!(run_info.get_instrument_model_name(), Some("LTQ FT"));
+        assert_eq!(run_info.instrument_serial_number.as_deref(), Some("SN06061F"));
+        assert_eq!(run_info.sample.vial.as_deref(), Some("1a1"));
+        assert_eq!(run_info.sample.dilution_factor, Some(1.0));
+        assert_eq!(run_info.operator, None);
+
+        let metadata_txt = "#General information\n\
+            Creation date=07/20/2005 14:44:22\n\
+            Created by=[MS, MS:1000529, created_by, LTQFT]\n\
+            Instrument name=LTQ FT\n\
+            Instrument serial number=[MS, MS:1000529, instrument serial number, SN00000]\n\
+            Software version=[NCIT, NCIT:C111093, Software Version, 2.0]\n\
+            Firmware version=\n\
+            #Sample information\n\
+            Sample injection volume=5\n\
+            Sample dilution factor=2\n";
+        let run_info = run_info.with_thermo_metadata(metadata_txt);
+
+        // Values of the mzML header are kept
+        assert_eq!(run_info.acquisition_date.as_deref(), Some("2005-07-20T14:44:22.377Z"));
+        assert_eq!(run_info.instrument_serial_number.as_deref(), Some("SN06061F"));
+        assert_eq!(run_info.sample.dilution_factor, Some(1.0));
+
+        assert_eq!(run_info.operator.as_deref(), Some("LTQFT"));
+        assert_eq!(run_info.instrument_name.as_deref(), Some("LTQ FT"));
+        assert_eq!(run_info.software_version.as_deref(), Some("2.0"));
+        assert_eq!(run_info.firmware_version, None);
+        assert_eq!(run_info.sample.injection_volume, Some(5.0));
+    }
+
+    #[test]
+    fn query_scan_index() {
+        let scan_index = ScanIndex::new(vec![
+            new_test_scan_entry(3, 2, 30.2, Some(500.2025), ""),
+            new_test_scan_entry(1, 1, 29.9, None, ""),
+            new_test_scan_entry(2, 2, 30.1, Some(500.21), ""),
+            new_test_scan_entry(4, 1, 30.5, None, ""),
+            new_test_scan_entry(5, 2, 31.2, Some(500.2), ""),
+        ]);
+
+        assert_eq!(scan_index.get_entries().first().unwrap().scan_number, 1);
+        assert_eq!(scan_index.get_entry(4).unwrap().retention_time, 30.5);
+
+        let scan_numbers = |entries: &[&ScanIndexEntry]| entries.iter().map(|e| e.scan_number).collect::<Vec<u32>>();
+        let ms2_scans_in_rt_range: Vec<&ScanIndexEntry> = scan_index.scans_in_rt_range(30.0, 31.0).iter()
+            .filter(|e| e.ms2_matches(500.2, 10.0))
+            .collect();
+        assert_eq!(scan_numbers(&ms2_scans_in_rt_range), vec![3]);
+        assert_eq!(scan_numbers(&scan_index.ms2_for_precursor(500.2, 10.0)), vec![3, 5]);
+
+        assert_eq!(scan_index.nearest_ms1(30.1).unwrap().scan_number, 1);
+        assert_eq!(scan_index.nearest_ms1(30.3).unwrap().scan_number, 4);
+        assert_eq!(scan_index.nearest_ms1(100.0).unwrap().scan_number, 4);
+        assert!(scan_index.covers_scan_range(1, 5));
+
+        let sidecar_file_path = std::env::temp_dir().join("query_scan_index.scanindex.json");
+        scan_index.write_to_file(&sidecar_file_path).expect("can't write scan index");
+        let loaded_scan_index = ScanIndex::read_from_file(&sidecar_file_path).expect("can't read scan index");
+        std::fs::remove_file(&sidecar_file_path).unwrap();
+
+        assert_eq!(loaded_scan_index, scan_index);
+    }
+
+    #[test]
+    fn validate_index_cache() {
+        let tmp_dir = std::env::temp_dir().join("validate_index_cache");
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+
+        let fake_raw_file_path = tmp_dir.join("fake.RAW");
+        std::fs::write(&fake_raw_file_path, b"fake RAW file content").unwrap();
+
+        let fingerprint = RawFileFingerprint::compute(&fake_raw_file_path).expect("can't compute fingerprint");
+        let metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
+        let index_cache = IndexCache::new(fingerprint.clone(), metadata, ScanIndex::default());
+
+        let cache_location = IndexCacheLocation::Directory(tmp_dir.join("cache"));
+        let cache_file_path = IndexCache::get_cache_file_path(&fake_raw_file_path, &cache_location).unwrap();
+        assert!(IndexCache::read_if_valid(&cache_file_path, &fingerprint).unwrap().is_none());
+
+        index_cache.write_to_file(&cache_file_path).expect("can't write index cache");
+        let loaded_cache = IndexCache::read_if_valid(&cache_file_path, &fingerprint).unwrap().expect("missing index cache");
+        assert_eq!(loaded_cache.metadata.run.default_source_file_ref, "RAW1");
+
+        // Concurrent writers of the same cache don't share their temporary file
+        std::thread::scope(|thread_scope| {
+            for _ in 0..4 {
+                thread_scope.spawn(|| index_cache.write_to_file(&cache_file_path).expect("can't write index cache"));
+            }
+        });
+        assert!(IndexCache::read_if_valid(&cache_file_path, &fingerprint).unwrap().is_some());
+        assert_eq!(std::fs::read_dir(tmp_dir.join("cache")).unwrap().count(), 1, "temporary files left behind");
+
+        // A modified RAW file makes the cache stale
+        std::fs::write(&fake_raw_file_path, b"modified fake RAW file content").unwrap();
+        let new_fingerprint = RawFileFingerprint::compute(&fake_raw_file_path).unwrap();
+        assert_ne!(new_fingerprint, fingerprint);
+        assert!(matches!(IndexCache::read_if_valid(&cache_file_path, &new_fingerprint), Err(StreamerError::StaleIndexCache { .. })));
+
+        std::fs::write(&cache_file_path, b"{ truncated").unwrap();
+        assert!(matches!(IndexCache::read_if_valid(&cache_file_path, &new_fingerprint), Err(StreamerError::InvalidInput { input: "index cache", .. })));
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn configure_streamer_options() {
+        let default_options = StreamerOptions::new();
+        assert_eq!(default_options.get_peak_picking(), &PeakPicking::AllMsLevels);
+        assert!(!default_options.is_scan_index_enabled());
+
+        let cache_options = StreamerOptions::new().with_index_cache(IndexCacheLocation::NextToRawFile);
+        assert!(cache_options.is_scan_index_enabled());
+        assert_eq!(cache_options.get_index_cache_location(), Some(&IndexCacheLocation::NextToRawFile));
+
+        let profile_ms1 = PeakPicking::AllMsLevelsExcept(vec![1]);
+        assert!(!profile_ms1.is_enabled_for(1) && profile_ms1.is_enabled_for(2) && profile_ms1.is_enabled_for(3));
+        assert!(profile_ms1.is_enabled_for_any() && profile_ms1.is_disabled_for_any());
+
+        let centroid_ms2 = PeakPicking::MsLevels(vec![2]);
+        assert!(!centroid_ms2.is_enabled_for(1) && centroid_ms2.is_enabled_for(2) && !centroid_ms2.is_enabled_for(3));
+
+        assert!(!PeakPicking::AllMsLevels.is_disabled_for_any());
+        assert!(!PeakPicking::Disabled.is_enabled_for_any());
+        assert!(!PeakPicking::MsLevels(vec![]).is_enabled_for_any());
+        assert!(!PeakPicking::AllMsLevelsExcept(vec![]).is_disabled_for_any());
+
+        assert_eq!(SpectrumRepresentation::from_cv_accession("MS:1000128"), Some(SpectrumRepresentation::Profile));
+        assert_eq!(SpectrumRepresentation::Centroid.get_cv_accession(), "MS:1000127");
+
+        assert!(StreamerOptions::new().with_peak_picking(profile_ms1).validate().is_ok());
+        assert!(StreamerOptions::new().with_peak_picking(PeakPicking::MsLevels(vec![0])).validate().is_err());
+        assert!(StreamerOptions::new().with_peak_picking(PeakPicking::MsLevels(vec![2, 2])).validate().is_err());
+        assert!(StreamerOptions::new().with_index_cache(IndexCacheLocation::Directory("".into())).validate().is_err());
+
+        let options = StreamerOptions::new()
+            .with_ignore_instrument_errors(true)
+            .with_no_zlib_compression(true)
+            .with_log_format(LogFormat::Silent)
+            .with_peak_picking(PeakPicking::Disabled);
+
+        let mut metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
+        options.record_in_metadata(&mut metadata);
+        options.record_in_metadata(&mut metadata);
+
+        let data_processing_list = &metadata.data_processing_list;
+        assert_eq!(data_processing_list.count, "2");
+        let options_processing = data_processing_list.data_processings.iter()
+            .find(|dp| dp.id == STREAMER_OPTIONS_DATA_PROCESSING_ID)
+            .expect("can't find the options data processing");
+        assert_eq!(options_processing.processing_method.software_ref, "ThermoRawFileParser");
+        assert!(options_processing.processing_method.cv_params.is_empty(), "no peak picking expected");
+
+        let user_param_value = |name: &str| options_processing.processing_method.user_params.iter()
+            .find(|up| up.name == name)
+            .map(|up| up.value.as_str());
+        assert_eq!(user_param_value("peak picking"), Some("disabled"));
+        assert_eq!(user_param_value("ignore instrument errors"), Some("true"));
+        assert_eq!(user_param_value("no zlib compression"), Some("true"));
+        assert_eq!(user_param_value("log format"), Some("silent"));
+    }
+
+    #[test]
+    fn write_indexed_mzml() {
+        use base64::Engine;
+        use sha1::{Digest, Sha1};
+        use std::io::{Cursor, Read};
+
+        let metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
+        let spectra = vec![
+            new_test_spectrum(1, 1, 0, vec![100.5, 200.25, 300.125]),
+            new_test_spectrum(2, 1, 0, vec![]),
+            new_test_spectrum(3, 1, 0, vec![445.12]),
+        ];
+
+        let mzml_bytes = MzMLWriter::new(Cursor::new(Vec::new()))
+            .with_mz_precision(BinaryPrecision::Float32)
+            .write_spectra(&metadata, spectra.clone().into_iter().map(Result::Ok), |s| Ok(Some(s)))
+            .expect("can't write mzML")
+            .into_inner();
+        let mzml_str = String::from_utf8(mzml_bytes).unwrap();
+
+        // Offsets of the spectra and of the index
+        let offsets: Vec<usize> = mzml_str.match_indices("<offset idRef=").map(|(pos, _)| {
+            let value_start = pos + mzml_str[pos..].find('>').unwrap() + 1;
+            let value_end = value_start + mzml_str[value_start..].find('<').unwrap();
+            mzml_str[value_start..value_end].parse().unwrap()
+        }).collect();
+        assert_eq!(offsets.len(), 3);
+        assert!(offsets.iter().all(|offset| mzml_str[*offset..].starts_with("<spectrum ")));
+
+        let index_list_offset_start = mzml_str.find("<indexListOffset>").unwrap() + "<indexListOffset>".len();
+        let index_list_offset: usize = mzml_str[index_list_offset_start..].split('<').next().unwrap().parse().unwrap();
+        assert!(mzml_str[index_list_offset..].starts_with("<indexList "));
+
+        let checksum_start = mzml_str.find("<fileChecksum>").unwrap() + "<fileChecksum>".len();
+        let expected_checksum = format!("{:x}", Sha1::digest(&mzml_str.as_bytes()[..checksum_start]));
+        assert!(mzml_str[checksum_start..].starts_with(&expected_checksum));
+
+        // Header and spectra can be parsed again
+        let header_end = mzml_str.find("<spectrumList").unwrap();
+        let header_str = format!("{}</run></mzML>", &mzml_str[mzml_str.find("<mzML").unwrap()..header_end]);
+        let parsed_metadata = parse_mzml_metadata(&header_str).expect("can't parse written mzML header");
+        assert_eq!(parsed_metadata.run.default_source_file_ref, "RAW1");
+        assert_eq!(parsed_metadata.software_list.software_entries.first().unwrap().version, "1.2.3");
+
+        let spectrum_end = offsets[2] + mzml_str[offsets[2]..].find("</spectrum>").unwrap() + "</spectrum>".len();
+        let parsed_spectrum = parse_mzml_spectrum_metadata(&mzml_str[offsets[2]..spectrum_end]).unwrap();
+        assert_eq!(parsed_spectrum.index, "2");
+        assert_eq!(parsed_spectrum.default_array_length, "1");
+        assert_eq!(parsed_spectrum.cv_params.first().unwrap().accession, MS_LEVEL_CV_ACCESSION);
+
+        // Binary arrays of the first spectrum: zlib compressed, 32-bit m/z values and 32-bit intensities
+        let decode_binary = |binary: &str| {
+            let compressed = base64::engine::general_purpose::STANDARD.decode(binary).unwrap();
+            let mut bytes = Vec::new();
+            flate2::read::ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut bytes).unwrap();
+            bytes.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap()) as f64).collect::<Vec<f64>>()
+        };
+        let binaries: Vec<&str> = mzml_str[offsets[0]..offsets[1]].split("<binary>").skip(1)
+            .map(|s| s.split("</binary>").next().unwrap())
+            .collect();
+        assert_eq!(decode_binary(binaries[0]), spectra[0].data.mz_list);
+        assert_eq!(decode_binary(binaries[1]), spectra[0].data.intensity_list);
+
+        // Uncompressed 64-bit arrays
+        let mzml_bytes = MzMLWriter::new(Cursor::new(Vec::new()))
+            .with_zlib_compression(false)
+            .with_intensity_precision(BinaryPrecision::Float64)
+            .write_spectra(&metadata, spectra[..1].iter().cloned().map(Result::Ok), |s| Ok(Some(s)))
+            .unwrap()
+            .into_inner();
+        let mzml_str = String::from_utf8(mzml_bytes).unwrap();
+        assert!(mzml_str.contains("MS:1000576") && mzml_str.contains("MS:1000523") && !mzml_str.contains("MS:1000521"));
+        let binary = mzml_str.split("<binary>").nth(2).unwrap().split("</binary>").next().unwrap();
+        let intensities: Vec<f64> = base64::engine::general_purpose::STANDARD.decode(binary).unwrap()
+            .chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(intensities, spectra[0].data.intensity_list);
+
+        // Spectra filtered out by the transform function are neither counted nor indexed
+        let mzml_bytes = MzMLWriter::new(Cursor::new(Vec::new()))
+            .write_spectra(&metadata, spectra.clone().into_iter().map(Result::Ok), |s| {
+                Ok(if s.data.mz_list.is_empty() { None } else { Some(s) })
+            })
+            .unwrap()
+            .into_inner();
+        let mzml_str = String::from_utf8(mzml_bytes).unwrap();
+        assert!(mzml_str.contains(r#"<spectrumList count="0000000002""#));
+        let checksum_start = mzml_str.find("<fileChecksum>").unwrap() + "<fileChecksum>".len();
+        let expected_checksum = format!("{:x}", Sha1::digest(&mzml_str.as_bytes()[..checksum_start]));
+        assert!(mzml_str[checksum_start..].starts_with(&expected_checksum), "the checksum should cover the overwritten count");
+        let offsets: Vec<usize> = mzml_str.match_indices("<offset idRef=").map(|(pos, _)| {
+            let value_start = pos + mzml_str[pos..].find('>').unwrap() + 1;
+            let value_end = value_start + mzml_str[value_start..].find('<').unwrap();
+            mzml_str[value_start..value_end].parse().unwrap()
+        }).collect();
+        assert_eq!(offsets.len(), 2);
+        let parsed_spectrum = parse_mzml_spectrum_metadata(
+            &mzml_str[offsets[1]..offsets[1] + mzml_str[offsets[1]..].find("</spectrum>").unwrap() + "</spectrum>".len()]
+        ).unwrap();
+        assert_eq!(parsed_spectrum.index, "1");
+        assert_eq!(parsed_spectrum.id, "controllerType=0 controllerNumber=1 scan=3");
+
+        // The number of written spectra must match the header
+        let mut writer = MzMLWriter::new(Vec::new());
+        writer.write_header(&metadata, 2).unwrap();
+        writer.write_spectrum(&spectra[0]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn build_and_write_chromatograms() {
+        let scan_entries: Vec<ScanIndexEntry> = [(1, 1, 0.1, 1e6), (2, 2, 0.2, 2e5), (3, 1, 0.3, 3e6), (4, 2, 0.4, 1e5)]
+            .into_iter()
+            .map(|(scan_number, ms_level, retention_time, tic)| ScanIndexEntry {
+                tic,
+                base_peak_intensity: tic / 10.0,
+                ..new_test_scan_entry(scan_number, ms_level, retention_time, None, "")
+            })
+            .collect();
+
+        let tic = Chromatogram::from_scan_entries(ChromatogramKind::TotalIonCurrent, None, &scan_entries);
+        assert_eq!(tic.id, "TIC");
+        assert_eq!(tic.len(), 4);
+        assert_eq!(tic.get_apex_time(), Some(0.3));
+
+        let ms1_bpc = Chromatogram::from_scan_entries(ChromatogramKind::BasePeak, Some(&[1]), &scan_entries);
+        assert_eq!(ms1_bpc.id, "BPC MS1");
+        assert_eq!(ms1_bpc.scan_numbers, vec![1, 3]);
+        assert_eq!(ms1_bpc.intensities, vec![1e5, 3e5]);
+        assert_eq!(ms1_bpc.time_unit.to_seconds(ms1_bpc.times[1]), 18.0);
+
+        let chromatogram_list = ChromatogramList::from_chromatograms(&[tic.clone(), ms1_bpc.clone()], "pwiz_Reader_conversion", true).unwrap();
+        let ms1_bpc_element = &chromatogram_list.chromatograms[1];
+        assert_eq!(ms1_bpc_element.index, "1");
+        assert_eq!(ms1_bpc_element.cv_params[0].accession, "MS:1000628");
+        assert_eq!(ms1_bpc_element.user_params[0].value, "1");
+        let time_array_cv_param = ms1_bpc_element.binary_data_array_list.binary_data_arrays[0].cv_params.last().unwrap();
+        assert_eq!(time_array_cv_param.accession, "MS:1000595");
+        assert_eq!(time_array_cv_param.unit_cv_ref.as_deref(), Some("UO"));
+
+        // The chromatograms are written after the spectra and indexed
+        let metadata = parse_mzml_metadata(MZML_HEADER_STR).unwrap();
+        let mut writer = MzMLWriter::new(Vec::new());
+        writer.write_header(&metadata, 1).unwrap();
+        assert!(writer.write_chromatograms(std::slice::from_ref(&tic)).is_err(), "all the spectra must be written first");
+        writer.write_spectrum(&new_test_spectrum(1, 1, 0, vec![100.5])).unwrap();
+        writer.write_chromatograms(&[tic, ms1_bpc]).unwrap();
+        assert!(writer.write_spectrum(&new_test_spectrum(2, 1, 0, vec![100.5])).is_err());
+
+        let mzml_str = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(mzml_str.contains("</spectrumList>\n<chromatogramList count=\"2\" defaultDataProcessingRef=\"ThermoRawFileParserProcessing\">"));
+        assert!(mzml_str.contains(r#"<indexList count="2">"#));
+        let chromatogram_index_str = mzml_str.split(r#"<index name="chromatogram">"#).nth(1).unwrap();
+        let bpc_offset: usize = chromatogram_index_str.split(r#"<offset idRef="BPC MS1">"#).nth(1).unwrap().split('<').next().unwrap().parse().unwrap();
+        assert!(mzml_str[bpc_offset..].starts_with(r#"<chromatogram index="1" id="BPC MS1""#));
+
+        let chromatogram_list_start = mzml_str.find("<chromatogramList").unwrap();
+        let chromatogram_list_end = mzml_str.find("</chromatogramList>").unwrap() + "</chromatogramList>".len();
+        let parsed_list: ChromatogramList = quick_xml::de::from_str(&mzml_str[chromatogram_list_start..chromatogram_list_end]).unwrap();
+        assert_eq!(parsed_list.chromatograms.len(), 2);
+        assert_eq!(parsed_list.chromatograms[1].default_array_length, "2");
+    }
+
+    #[test]
+    fn build_xic_requests() {
+        let (mz_start, mz_end) = MzTolerance::Ppm(10.0).to_mz_range(500.0);
+        assert!((mz_start - 499.995).abs() < 1e-9 && (mz_end - 500.005).abs() < 1e-9);
+        assert_eq!(MzTolerance::Da(0.5).to_mz_range(500.0), (499.5, 500.5));
+
+        let request = XicRequest::mz(445.12, MzTolerance::Ppm(10.0)).with_rt_range(0.2, 0.4);
+        assert_eq!(request.get_id(), "XIC 445.12 ± 10 ppm");
+        assert_eq!(request.get_filter(), DEFAULT_XIC_FILTER);
+        assert!(request.validate().is_ok());
+
+        assert!(XicRequest::mz(445.12, MzTolerance::Da(0.0)).validate().is_err());
+        assert!(XicRequest::mz_range(500.0, 400.0).validate().is_err());
+        assert!(XicRequest::peptide("PEPTIDE", 2, MzTolerance::Ppm(5.0)).validate().is_ok());
+        assert!(XicRequest::peptide("PEPT[+80]IDE", 2, MzTolerance::Ppm(5.0)).validate().is_err());
+        assert!(XicRequest::peptide("PEPTIDE", 0, MzTolerance::Ppm(5.0)).validate().is_err());
+        assert!(XicRequest::mz(445.12, MzTolerance::Amu(0.1)).with_rt_range(2.0, 1.0).validate().is_err());
+
+        let ms1_filter = "FTMS + p ESI Full ms [400.00-2000.00]";
+        let ms2_filter = "ITMS + c ESI d Full ms2 445.12@cid35.00 [110.00-460.00]";
+        assert!(filter_matches("ms", ms1_filter));
+        assert!(!filter_matches("ms", ms2_filter));
+        assert!(filter_matches("ITMS ms2", ms2_filter));
+        assert!(filter_matches("ms2", ms2_filter));
+        assert!(filter_matches("d Full ms2 445.1", ms2_filter));
+        assert!(filter_matches("Full ms2 445.12@cid35.00", ms2_filter));
+        assert!(!filter_matches("Full ms2 445.13", ms2_filter));
+        assert!(!filter_matches("Full ms2 445.12@hcd35.00", ms2_filter));
+        assert!(!filter_matches("!d ms2", ms2_filter));
+        assert!(filter_matches("!d", ms1_filter));
+        assert!(filter_matches("p Full ms [400.0-2000.0]", ms1_filter));
+        assert!(!filter_matches("FTMS ms2", ms2_filter));
+        assert!(!filter_matches("ms3", ms2_filter));
+        assert!(filter_matches("", ms2_filter));
+        assert!(XicRequest::mz(445.12, MzTolerance::Da(0.5)).with_filter("ms2 445.12@").validate().is_err());
+        assert!(request.matches_scan(&new_test_scan_entry(1, 1, 0.3, None, ms1_filter)));
+        assert!(!request.matches_scan(&new_test_scan_entry(2, 1, 0.3, None, ms2_filter)));
+        assert!(!request.matches_scan(&new_test_scan_entry(3, 1, 0.5, None, ms1_filter)));
+
+        // Only the peaks of the m/z window are summed, scans without any giving zero intensity points
+        let mut xic_builder = crate::xic::XicBuilder::new(&request, MzTolerance::Da(0.01).to_mz_range(445.12));
+        xic_builder.add_scan(&new_test_scan_entry(1, 1, 0.3, None, ms1_filter), &[445.10, 445.115, 445.125, 446.0], &[1.0, 2.0, 3.0, 4.0], 0);
+        xic_builder.add_scan(&new_test_scan_entry(3, 1, 0.35, None, ms1_filter), &[300.0, 600.0], &[1.0, 2.0], 0);
+        let xic = xic_builder.finish();
+        assert_eq!(xic.kind, ChromatogramKind::SelectedIonCurrent);
+        assert_eq!(xic.scan_numbers, vec![1, 3]);
+        assert_eq!(xic.intensities, vec![5.0, 0.0]);
+
+        let xic_element = MzMLChromatogram::from_chromatogram(&xic, 0, false, BinaryPrecision::Float32).unwrap();
+        assert_eq!(xic_element.cv_params[0].accession, "MS:1000627");
+        assert_eq!(xic_element.user_params.len(), 2);
+    }
+
+    #[test]
+    fn parse_xic_targets_and_detect_peaks() {
+        // ThermoRawFileParser xic command example
+        let json_batch = XicBatch::from_json_str(r#"[
+            {"mz": 673.363, "tolerance": 10, "tolerance_unit": "ppm"},
+            {"mz": 867.345, "tolerance": 0.02, "tolerance_unit": "da", "rt_start": 87.56, "rt_end": 99.56},
+            {"mz_start": 749.786, "mz_end": 750.4869, "rt_start": 60},
+            {"sequence": "TRANNEKS", "tolerance": 10, "scan_filter": "ms2"}
+        ]"#).unwrap();
+        assert_eq!(json_batch.len(), 4);
+        assert_eq!(json_batch.get_requests()[0], XicRequest::mz(673.363, MzTolerance::Ppm(10.0)));
+        assert_eq!(json_batch.get_requests()[1].get_rt_range(), Some((87.56, 99.56)));
+        assert_eq!(json_batch.get_requests()[2].get_rt_range(), Some((60.0, f64::INFINITY)));
+        assert_eq!(json_batch.get_requests()[3], XicRequest::peptide("TRANNEKS", 1, MzTolerance::Ppm(10.0)).with_filter("ms2"));
+
+        assert!(XicBatch::from_json_str(r#"[{"mz": 673.363, "mz_start": 600, "mz_end": 700}]"#).is_err());
+        assert!(XicBatch::from_json_str(r#"[{"mz": 673.363, "tolerance": 10, "tolerance_unit": "%"}]"#).is_err());
+        let commented_batch = XicBatch::from_json_str(r#"[{"mz": 673.363, "tolerance": 10, "comment": "unknown property"}]"#).unwrap();
+        assert_eq!(commented_batch.get_requests()[0], XicRequest::mz(673.363, MzTolerance::Ppm(10.0)));
+
+        let tsv_batch = XicBatch::from_tsv_str("# targets\nmz\ttolerance\ttolerance_unit\tsequence\tcharge\n673.363\t5\tmmu\t\t\n\t10\t\tPEPTIDE\t2\n").unwrap();
+        assert_eq!(tsv_batch.get_requests(), &[
+            XicRequest::mz(673.363, MzTolerance::Mmu(5.0)),
+            XicRequest::peptide("PEPTIDE", 2, MzTolerance::Ppm(10.0)),
+        ]);
+        assert!(XicBatch::from_tsv_str("mz\ttol\n673.363\t10\n").is_err());
+        let err = XicBatch::from_tsv_str("mz\ttolerance\n673.363\t10\nabc\t10\n").unwrap_err();
+        assert!(matches!(&err, StreamerError::InvalidInput { input: "XIC targets", message } if message.contains("line 3")));
+        assert!(matches!(XicBatch::from_file("./resources/missing_targets.tsv"), Err(StreamerError::Io { .. })));
+
+        // The peak stops at the local minimum before the second apex
+        let mut xic = Chromatogram::from_scan_entries(ChromatogramKind::SelectedIonCurrent, None, &[]);
+        xic.times = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        xic.intensities = vec![0.0, 10.0, 30.0, 20.0, 5.0, 8.0, 0.0];
+        let peak = XicPeak::from_chromatogram(&xic).unwrap();
+        assert_eq!(peak.apex_time, 3.0);
+        assert_eq!((peak.start_time, peak.end_time), (2.0, 5.0));
+        assert_eq!(peak.points_across_peak, 4);
+        assert_eq!(peak.area, 20.0 + 25.0 + 12.5);
+
+        xic.intensities = vec![0.0; 7];
+        assert_eq!(XicPeak::from_chromatogram(&xic), None);
+
+        let results = vec![XicBatchResult { request: XicRequest::mz_range(400.0, 401.0), chromatogram: xic, peak: None }];
+        let summary = String::from_utf8(write_xic_summary(&results, Vec::new()).unwrap()).unwrap();
+        assert_eq!(summary.lines().count(), 2);
+        assert!(summary.lines().nth(1).unwrap().ends_with("\tms\t0\t0\t7\t\t\t\t0"));
+    }
+
+    #[test]
+    fn build_and_write_srm_chromatograms() {
+        let transitions = parse_srm_filter("+ c ESI SRM ms2 524.300@cid25.00 [785.390-785.392, 914.430-914.432]").unwrap();
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(transitions[1], SrmTransition {
+            precursor_mz: 524.3,
+            product_mz_start: 914.43,
+            product_mz_end: 914.432,
+            activation: ActivationMethod::Cid,
+            collision_energy: Some(25.0),
+        });
+        assert_eq!(transitions[0].get_id(), "SRM SIC Q1=524.300 Q3=785.391 CE=25");
+        assert_eq!(parse_srm_filter("+ c NSI SRM ms2 445.120 [150.000-150.200]").unwrap()[0].collision_energy, None);
+        assert_eq!(parse_srm_filter("FTMS + p ESI Full ms [400.00-2000.00]"), None);
+        let mut srm_builder = crate::srm::SrmChromatogramsBuilder::default();
+        srm_builder.add_scan(&new_test_scan_entry(1, 2, 0.1, Some(524.3), ""), &transitions, &[785.391, 914.431], &[100.0, 50.0]);
+        srm_builder.add_scan(&new_test_scan_entry(2, 2, 0.2, Some(524.3), ""), &transitions, &[914.431], &[80.0]);
+        let srm_chromatograms = srm_builder.finish();
+        assert_eq!(srm_chromatograms.len(), 2);
+        assert_eq!(srm_chromatograms[0].intensities, vec![100.0, 0.0]);
+        assert_eq!(srm_chromatograms[1].intensities, vec![50.0, 80.0]);
+        assert_eq!(srm_chromatograms[1].transition, Some(transitions[1]));
+
+        let chromatogram_list = ChromatogramList::from_chromatograms(&srm_chromatograms, "pwiz_Reader_conversion", false).unwrap();
+        let chromatogram_list_str = quick_xml::se::to_string_with_root("chromatogramList", &chromatogram_list).unwrap();
+        assert!(chromatogram_list_str.contains(r#"accession="MS:1001473" name="selected reaction monitoring chromatogram""#));
+        let precursor_str = chromatogram_list_str.split("<precursor>").nth(1).unwrap().split("</precursor>").next().unwrap();
+        assert!(precursor_str.contains(r#"accession="MS:1000827" name="isolation window target m/z" value="524.3""#));
+        assert!(precursor_str.contains(r#"accession="MS:1000045" name="collision energy" value="25" unitCvRef="UO""#));
+        assert!(chromatogram_list_str.find("</product><binaryDataArrayList").is_some(), "the product must precede the binary data arrays");
+
+        let parsed_list: ChromatogramList = quick_xml::de::from_str(&chromatogram_list_str).unwrap();
+        let product = parsed_list.chromatograms[0].product.as_ref().unwrap();
+        let product_mz: f64 = product.isolation_window.cv_params[0].value.as_ref().unwrap().parse().unwrap();
+        assert!((product_mz - 785.391).abs() < 1e-6);
+    }
+
+    #[test]
+    fn get_typed_spectrum_metadata() {
+        let spectrum_header = r#"<spectrum index="1" id="controllerType=0 controllerNumber=1 scan=2" defaultArrayLength="0">
+  <cvParam cvRef="MS" accession="MS:1000511" name="ms level" value="2"/>
+  <cvParam cvRef="MS" accession="MS:1000580" name="MSn spectrum" value=""/>
+  <cvParam cvRef="MS" accession="MS:1000129" name="negative scan" value=""/>
+  <cvParam cvRef="MS" accession="MS:1000127" name="centroid spectrum" value=""/>
+  <cvParam cvRef="MS" accession="MS:1000504" name="base peak m/z" value="301.14" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+  <cvParam cvRef="MS" accession="MS:1000505" name="base peak intensity" value="12000.5" unitCvRef="MS" unitAccession="MS:1000131" unitName="number of detector counts"/>
+  <cvParam cvRef="MS" accession="MS:1000285" name="total ion current" value="50000"/>
+  <cvParam cvRef="MS" accession="MS:1000528" name="lowest observed m/z" value="110.07" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+  <cvParam cvRef="MS" accession="MS:1000527" name="highest observed m/z" value="890.4" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+  <scanList count="1">
+    <cvParam cvRef="MS" accession="MS:1000795" name="no combination" value=""/>
+    <scan instrumentConfigurationRef="IC2">
+      <cvParam cvRef="MS" accession="MS:1000016" name="scan start time" value="90" unitCvRef="UO" unitAccession="UO:0000010" unitName="second"/>
+      <cvParam cvRef="MS" accession="MS:1000512" name="filter string" value="FTMS - c NSI cv=-45.00 d Full ms2 445.12@etd25.00@hcd20.00 [110.00-900.00]"/>
+      <cvParam cvRef="MS" accession="MS:1000927" name="ion injection time" value="0.05" unitCvRef="UO" unitAccession="UO:0000010" unitName="second"/>
+      <cvParam cvRef="MS" accession="MS:1001581" name="FAIMS compensation voltage" value="-45" unitCvRef="UO" unitAccession="UO:0000218" unitName="volt"/>
+      <userParam name="[Thermo Trailer Extra]Monoisotopic M/Z:" value="445.1195" type="xsd:float"/>
+      <userParam name="[Thermo Trailer Extra]Charge State:" value="2" type="xsd:float"/>
+      <userParam name="[Thermo Trailer Extra]FT Resolution:" value="15,000" type="xsd:string"/>
+      <scanWindowList count="1"><scanWindow>
+        <cvParam cvRef="MS" accession="MS:1000501" name="scan window lower limit" value="110" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+        <cvParam cvRef="MS" accession="MS:1000500" name="scan window upper limit" value="900" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+      </scanWindow></scanWindowList>
+    </scan>
+  </scanList>
+  <precursorList count="1"><precursor spectrumRef="controllerType=0 controllerNumber=1 scan=1">
+    <isolationWindow>
+      <cvParam cvRef="MS" accession="MS:1000827" name="isolation window target m/z" value="445.12" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+      <cvParam cvRef="MS" accession="MS:1000828" name="isolation window lower offset" value="0.8" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+      <cvParam cvRef="MS" accession="MS:1000829" name="isolation window upper offset" value="0.8" unitCvRef="MS" unitAccession="MS:1000040" unitName="m/z"/>
+    </isolationWindow>
+    <selectedIonList count="1"><selectedIon>
+      <cvParam cvRef="MS" accession="MS:1000744" name="selected ion m/z" value="445.12"/>
+    </selectedIon></selectedIonList>
+    <activation>
+      <cvParam cvRef="MS" accession="MS:1000598" name="electron transfer dissociation" value=""/>
+      <cvParam cvRef="MS" accession="MS:1002678" name="supplemental beam-type collision-induced dissociation" value=""/>
+      <cvParam cvRef="MS" accession="MS:1000045" name="collision energy" value="20" unitCvRef="UO" unitAccession="UO:0000266" unitName="electronvolt"/>
+    </activation>
+  </precursor></precursorList>
+</spectrum>"#;
+        let spectrum = MzMLSpectrum::new(parse_mzml_spectrum_metadata(spectrum_header).unwrap(), SpectrumData { mz_list: vec![], intensity_list: vec![] });
+
+        assert_eq!(spectrum.get_polarity(), Some(Polarity::Negative));
+        assert_eq!(spectrum.get_representation(), Some(SpectrumRepresentation::Centroid));
+        assert_eq!(spectrum.get_total_ion_current(), Some(50000.0));
+        assert_eq!((spectrum.get_base_peak_mz(), spectrum.get_base_peak_intensity()), (Some(301.14), Some(12000.5)));
+        assert_eq!((spectrum.get_lowest_observed_mz(), spectrum.get_highest_observed_mz()), (Some(110.07), Some(890.4)));
+
+        // Times are converted from their declared units
+        assert_eq!(spectrum.get_first_scan_start_time(), Some(1.5));
+        assert_eq!(spectrum.get_scan_start_time(TimeUnit::Second), Some(90.0));
+        assert_eq!(spectrum.get_ion_injection_time(), Some(50.0));
+
+        assert!(spectrum.get_filter_string().unwrap().starts_with("FTMS - c NSI"));
+        assert_eq!(spectrum.get_scan_filter().unwrap().get_compensation_voltage(), spectrum.get_faims_compensation_voltage());
+        assert_eq!(spectrum.get_faims_compensation_voltage(), Some(-45.0));
+        assert_eq!(spectrum.get_scan_window(), Some((110.0, 900.0)));
+
+        let isolation_window = spectrum.get_isolation_window().unwrap();
+        assert_eq!(isolation_window.target_mz, 445.12);
+        assert_eq!(isolation_window.get_mz_range(), (445.12 - 0.8, 445.12 + 0.8));
+        assert_eq!(spectrum.get_activation_methods(), vec![ActivationMethod::Etd, ActivationMethod::SupplementalHcd]);
+        assert_eq!(spectrum.get_collision_energy(), Some(20.0));
+        assert_eq!(spectrum.get_master_scan_number(), Some(1));
+
+        let trailer_extra = spectrum.get_trailer_extra();
+        assert_eq!(trailer_extra.len(), 3);
+        assert_eq!(trailer_extra.get_number("Monoisotopic M/Z"), spectrum.get_precursor_mz_and_charge().0);
+        assert_eq!(trailer_extra.get("Charge State:"), Some(&TrailerValue::Number(2.0)));
+        assert_eq!(trailer_extra.get("FT Resolution"), Some(&TrailerValue::Text("15,000".to_string())));
+        assert_eq!(trailer_extra.iter().map(|(label, _)| label).collect::<Vec<_>>(), vec!["Monoisotopic M/Z", "Charge State", "FT Resolution"]);
+
+        // MS1 spectra have no precursor
+        let ms1_spectrum = new_test_spectrum(1, 1, 0, vec![100.5]);
+        assert_eq!(ms1_spectrum.get_polarity(), None);
+        assert_eq!(ms1_spectrum.get_isolation_window(), None);
+        assert!(ms1_spectrum.get_activation_methods().is_empty());
+        assert_eq!(ms1_spectrum.get_master_scan_number(), None);
+        assert!(ms1_spectrum.get_trailer_extra().is_empty());
+        assert_eq!(ms1_spectrum.get_first_scan_start_time(), Some(0.5));
+    }
+
+    // Filter strings of Orbitrap, ion trap, Astral, FAIMS, EThcD, multiplexed and triple quadrupole acquisitions
+    const SCAN_FILTER_CORPUS: [&str; 32] = [
+        "FTMS + p ESI Full ms [400.00-2000.00]",
+        "FTMS + c ESI Full ms [300.00-1800.00]",
+        "ITMS + c ESI d Full ms2 445.12@cid35.00 [110.00-460.00]",
+        "ITMS + c ESI d w Full ms2 445.12@cid35.00 [110.00-460.00]",
+        "ITMS + p ESI Z ms [443.00-453.00]",
+        "FTMS + p NSI Full ms [350.0000-1500.0000]",
+        "FTMS + c NSI d Full ms2 636.34@hcd28.00 [110.00-1285.00]",
+        "FTMS + c NSI d Full ms2 829.3945@hcd27.00 [120.0000-2000.0000]",
+        "ITMS + c NSI r d Full ms2 548.76@cid35.00 [140.00-1110.00]",
+        "ITMS + c NSI r d Full ms3 765.30@cid35.00 484.34@cid35.00 [120.00-1500.00]",
+        "FTMS + c NSI d Full ms3 1052.50@cid35.00 576.29@hcd65.00 [100.00-500.00]",
+        "FTMS + p NSI cv=-45.00 Full ms [350.0000-1500.0000]",
+        "FTMS + c NSI cv=-70.00 d Full ms2 714.8590@hcd30.00 [110.0000-1440.0000]",
+        "FTMS + c NSI d sa Full ms2 523.77@etd100.00@hcd25.00 [120.00-2000.00]",
+        "FTMS + c NSI d sa Full ms2 663.35@etd50.00@cid35.00 [120.00-1340.00]",
+        "ITMS + c NSI d Full ms2 802.41@etd66.67 [50.00-2000.00]",
+        "FTMS + p NSI SIM ms [500.00-510.00]",
+        "FTMS + p NSI SIM msx ms [398.00-402.00, 598.00-602.00]",
+        "FTMS + p NSI Full msx ms2 609.80@hcd25.00 703.84@hcd25.00 [100.00-2000.00]",
+        "FTMS + p NSI Full lock ms [375.0000-1500.0000]",
+        "FTMS {1,1} + p NSI Full ms [350.0000-1800.0000]",
+        "FTMS {1,2} + c NSI d Full ms2 500.2500@hcd30.00 [100.0000-1000.0000]",
+        "FTMS + p ESI sid=15.00 Full ms [150.00-2000.00]",
+        "FTMS - p ESI Full ms [100.00-1500.00]",
+        "ITMS - c ESI d Full ms2 301.14@cid35.00 [80.00-315.00]",
+        "FTMS + p APCI corona Full ms [100.00-1000.00]",
+        "ASTMS + c NSI d Full ms2 622.3322@hcd25.00 [150.0000-2000.0000]",
+        "ASTMS + p NSI Full ms2 450.5000@hcd25.00 [145.0000-1450.0000]",
+        "+ c ESI SRM ms2 524.300 [785.390-785.392, 914.430-914.432]",
+        "+ c ESI SRM ms2 379.200@cid12.00 [162.100-162.102]",
+        "TQMS + c NSI SRM ms2 445.120 [150.000-150.200]",
+        "+ p ESI Q1MS [100.000-1000.000]",
+    ];
+
+    #[test]
+    fn parse_and_format_scan_filters() {
+        for filter_string in SCAN_FILTER_CORPUS {
+            let scan_filter: ScanFilter = filter_string.parse().unwrap_or_else(|e| panic!("{:#}", e));
+            assert_eq!(scan_filter.to_string(), filter_string, "the filter should be formatted back to the same string");
+        }
+
+        let ms3_filter: ScanFilter = SCAN_FILTER_CORPUS[9].parse().unwrap();
+        assert_eq!(ms3_filter.analyzer, Some(FilterAnalyzer::IonTrap));
+        assert_eq!(ms3_filter.polarity, Some(Polarity::Positive));
+        assert_eq!(ms3_filter.data_type, Some(SpectrumRepresentation::Centroid));
+        assert_eq!(ms3_filter.ionization, Some(FilterIonization::Nsi));
+        assert_eq!(ms3_filter.flags, vec![FilterFlag::Other("r".to_string()), FilterFlag::Dependent]);
+        assert_eq!(ms3_filter.scan_mode, Some(FilterScanMode::Full));
+        assert_eq!(ms3_filter.ms_order, Some(3));
+        assert_eq!(ms3_filter.precursors.len(), 2);
+        assert_eq!(ms3_filter.get_precursor_mz(), Some(484.34));
+        assert_eq!(ms3_filter.mass_ranges, vec![(120.0, 1500.0)]);
+
+        let ethcd_filter: ScanFilter = SCAN_FILTER_CORPUS[13].parse().unwrap();
+        assert!(ethcd_filter.is_dependent() && ethcd_filter.has_supplemental_activation());
+        assert_eq!(ethcd_filter.precursors[0].activations, vec![
+            FilterActivation { method: ActivationMethod::Etd, energy: 100.0 },
+            FilterActivation { method: ActivationMethod::Hcd, energy: 25.0 },
+        ]);
+
+        let faims_filter: ScanFilter = SCAN_FILTER_CORPUS[12].parse().unwrap();
+        assert_eq!(faims_filter.get_compensation_voltage(), Some(-70.0));
+        assert_eq!(faims_filter.get_precursor_mz(), Some(714.859));
+
+        let msx_filter: ScanFilter = SCAN_FILTER_CORPUS[17].parse().unwrap();
+        assert!(msx_filter.multiplex);
+        assert_eq!(msx_filter.mass_ranges.len(), 2);
+
+        let srm_filter: ScanFilter = SCAN_FILTER_CORPUS[30].parse().unwrap();
+        assert_eq!(srm_filter.analyzer, Some(FilterAnalyzer::TripleQuadrupole));
+        assert_eq!(srm_filter.scan_mode, Some(FilterScanMode::Srm));
+        assert_eq!(FilterScanMode::Q1Ms, SCAN_FILTER_CORPUS[31].parse::<ScanFilter>().unwrap().scan_mode.unwrap());
+
+        let new_filter = ScanFilter {
+            analyzer: Some(FilterAnalyzer::Fourier),
+            polarity: Some(Polarity::Positive),
+            data_type: Some(SpectrumRepresentation::Centroid),
+            ms_order: Some(2),
+            precursors: vec![FilterPrecursor { mz: 445.12, activations: vec![FilterActivation { method: ActivationMethod::Hcd, energy: 30.0 }] }],
+            mass_ranges: vec![(100.0, 1000.0)],
+            ..Default::default()
+        };
+        assert_eq!(new_filter.to_string(), "FTMS + c ms2 445.12@hcd30.00 [100.00-1000.00]");
+
+        let mut ethcd_filter = new_filter.clone();
+        ethcd_filter.precursors[0].activations = vec![
+            FilterActivation { method: ActivationMethod::Etd, energy: 100.0 },
+            FilterActivation { method: ActivationMethod::SupplementalHcd, energy: 25.0 },
+        ];
+        assert_eq!(ethcd_filter.to_string(), "FTMS + c ms2 445.12@etd100.00@hcd25.00 [100.00-1000.00]");
+
+        assert!("FTMS + c NSI Full ms2 445.12@xyz35.00 [110.00-460.00]".parse::<ScanFilter>().is_err());
+        assert!("FTMS + c NSI Full ms2 445.12@cid35.00 [110.00-460.00".parse::<ScanFilter>().is_err());
+        assert!("FTMS + p NSI Full ms [abc-1500.00]".parse::<ScanFilter>().is_err());
+    }
+
+    // Scan index entry with a doubly charged precursor for MSn scans
+    fn new_test_scan_entry(scan_number: u32, ms_level: u8, retention_time: f64, precursor_mz: Option<f64>, filter_string: &str) -> ScanIndexEntry {
+        ScanIndexEntry {
+            scan_number, ms_level, retention_time, precursor_mz,
+            precursor_charge: precursor_mz.map(|_| 2),
+            tic: 1e6,
+            base_peak_mz: 445.12,
+            base_peak_intensity: 1e5,
+            filter_string: filter_string.to_string(),
+        }
+    }
+
+    // MS1 or MSn spectrum in the ThermoRawFileParser format, with a precursor m/z of 400 + scan number
+    fn new_test_spectrum(scan_number: u32, ms_level: u8, charge: i8, mz_list: Vec<f64>) -> MzMLSpectrum {
+        let precursor_list = if ms_level > 1 {
+            format!(r#"<precursorList count="1"><precursor spectrumRef="controllerType=0 controllerNumber=1 scan=1">
+  <selectedIonList count="1"><selectedIon>
+    <cvParam cvRef="MS" accession="MS:1000744" name="selected ion m/z" value="{}"/>
+    <cvParam cvRef="MS" accession="MS:1000041" name="charge state" value="{}"/>
+  </selectedIon></selectedIonList>
+  <activation/>
+</precursor></precursorList>"#, 400.0 + scan_number as f64, charge)
+        } else {
+            String::new()
+        };
+        let spectrum_header = format!(
+            r#"<spectrum index="0" id="controllerType=0 controllerNumber=1 scan={}" defaultArrayLength="{}">
+  <cvParam cvRef="MS" accession="MS:1000511" name="ms level" value="{}"/>
+  <scanList count="1"><scan instrumentConfigurationRef="IC1">
+    <cvParam cvRef="MS" accession="MS:1000016" name="scan start time" value="0.5" unitCvRef="UO" unitAccession="UO:0000031" unitName="minute"/>
+    <scanWindowList count="0"/>
+  </scan></scanList>
+  {}
+</spectrum>"#, scan_number, mz_list.len(), ms_level, precursor_list);
+        let intensity_list = mz_list.iter().map(|mz| mz * 10.0).collect();
+        MzMLSpectrum::new(parse_mzml_spectrum_metadata(&spectrum_header).unwrap(), SpectrumData { mz_list, intensity_list })
+    }
+
+    #[test]
+    fn write_and_parse_mgf() {
+        let spectra = [
+            new_test_spectrum(1, 1, 0, vec![100.5, 200.25]),
+            new_test_spectrum(2, 2, 2, vec![150.5, 250.25, 350.125]),
+            new_test_spectrum(3, 2, 0, vec![160.5, 260.25]),
+            new_test_spectrum(4, 2, -3, vec![170.5]),
+        ];
+        assert_eq!(spectra[1].get_scan_number(), Some(2));
+
+        assert!("{file}.{scan".parse::<TitleFormat>().is_err());
+        assert!("{file}.{mass}".parse::<TitleFormat>().is_err());
+        let title_format: TitleFormat = "{file}.{scan}.{scan}.{charge} RT:{rt}".parse().unwrap();
+        assert_eq!(title_format.to_string(), "{file}.{scan}.{scan}.{charge} RT:{rt}");
+
+        let mut mgf_writer = MgfWriter::new(Vec::new())
+            .with_file_name("small")
+            .with_title_format(title_format)
+            .with_min_peak_count(2)
+            .with_unknown_charges(&[2, 3]);
+        let written_flags: Vec<bool> = spectra.iter().map(|s| mgf_writer.write_spectrum(s).unwrap()).collect();
+        assert_eq!(written_flags, vec![false, true, true, false]);
+        assert_eq!(mgf_writer.get_entry_count(), 2);
+
+        let mgf_content = String::from_utf8(mgf_writer.finish().unwrap()).unwrap();
+        assert!(mgf_content.contains("CHARGE=2+ and 3+"));
+
+        let entries = parse_mgf(&mgf_content).expect("can't parse written MGF");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "small.2.2.2 RT:30");
+        assert_eq!(entries[0].precursor_mz, 402.0);
+        assert_eq!(entries[0].charges, vec![2]);
+        assert_eq!(entries[0].retention_time, Some(30.0));
+        assert_eq!(entries[0].scans.as_deref(), Some("2"));
+        assert_eq!(entries[0].mz_list, spectra[1].data.mz_list);
+        assert_eq!(entries[0].intensity_list, spectra[1].data.intensity_list);
+        assert_eq!(entries[1].title, "small.3.3.0 RT:30");
+        assert_eq!(entries[1].charges, vec![2, 3]);
+
+        // Negative charges and MS level selection
+        let mut mgf_writer = MgfWriter::new(Vec::new()).with_ms_levels(&[1, 2]).with_title_format("{index}".parse().unwrap());
+        assert!(spectra.iter().all(|s| mgf_writer.write_spectrum(s).unwrap() == (s.get_ms_level() > 1)));
+        let entries = parse_mgf(&String::from_utf8(mgf_writer.finish().unwrap()).unwrap()).unwrap();
+        assert_eq!(entries.iter().map(|e| e.title.as_str()).collect::<Vec<&str>>(), vec!["0", "1", "2"]);
+        assert_eq!(entries[2].charges, vec![-3]);
+
+        assert!(matches!(parse_mgf("BEGIN IONS\nPEPMASS=400.1\n100.0 1.0\n"), Err(StreamerError::InvalidInput { input: "MGF content", .. })));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn export_parquet_tables() {
+        use arrow::array::{Array, Float64Array, Int8Array, UInt32Array};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let spectra = vec![
+            new_test_spectrum(1, 1, 0, vec![100.5, 200.25, 300.125]),
+            new_test_spectrum(2, 2, 2, vec![150.5, 250.25]),
+            new_test_spectrum(3, 2, 3, vec![]),
+        ];
+
+        let tmp_dir = std::env::temp_dir().join("export_parquet_tables");
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let peaks_file_path = tmp_dir.join("peaks.parquet");
+        let scans_file_path = tmp_dir.join("scans.parquet");
+
+        let summary = ParquetExporter::new()
+            .with_row_group_size(2)
+            .with_compression(ParquetCompression::Zstd(3))
+            .write_spectra(
+                spectra.clone().into_iter().map(Result::Ok),
+                std::fs::File::create(&peaks_file_path).unwrap(),
+                std::fs::File::create(&scans_file_path).unwrap(),
+            )
+            .expect("can't export Parquet tables");
+        assert_eq!(summary, ParquetExportSummary { scan_count: 3, peak_count: 5 });
+
+        let peaks_reader_builder = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&peaks_file_path).unwrap()).unwrap();
+        assert_eq!(peaks_reader_builder.metadata().num_row_groups(), 3);
+        assert_eq!(peaks_reader_builder.schema(), &peaks_schema());
+
+        let peak_batches: Vec<_> = peaks_reader_builder.build().unwrap().map(|b| b.unwrap()).collect();
+        let peaks = arrow::compute::concat_batches(&peaks_schema(), &peak_batches).unwrap();
+        let scan_numbers = peaks.column(0).as_any().downcast_ref::<UInt32Array>().unwrap();
+        let intensities = peaks.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(scan_numbers.values().to_vec(), vec![1, 1, 1, 2, 2]);
+        assert_eq!(intensities.value(3), 1505.0);
+
+        let scans_reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&scans_file_path).unwrap()).unwrap().build().unwrap();
+        let scan_batches: Vec<_> = scans_reader.map(|b| b.unwrap()).collect();
+        let scans = arrow::compute::concat_batches(&scans_schema(), &scan_batches).unwrap();
+        assert_eq!(scans.num_rows(), 3);
+        let precursor_mz_values = scans.column_by_name("precursor_mz").unwrap().as_any().downcast_ref::<Float64Array>().unwrap();
+        let precursor_charges = scans.column_by_name("precursor_charge").unwrap().as_any().downcast_ref::<Int8Array>().unwrap();
+        assert!(precursor_mz_values.is_null(0));
+        assert_eq!(precursor_mz_values.value(1), 402.0);
+        assert_eq!(precursor_charges.value(2), 3);
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn convert_spectra_to_record_batch() {
+        use arrow::array::{Array, Float64Array, ListArray, UInt8Array};
+
+        let spectra = vec![
+            new_test_spectrum(1, 1, 0, vec![100.5, 200.25, 300.125]),
+            new_test_spectrum(2, 2, 2, vec![]),
+            new_test_spectrum(3, 2, 3, vec![445.12]),
+        ];
+
+        let batch = spectra_to_record_batch(&spectra).expect("can't convert spectra");
+        assert_eq!(batch.schema(), spectra_schema());
+        assert_eq!(batch.num_rows(), 3);
+
+        let ms_levels = batch.column_by_name("ms_level").unwrap().as_any().downcast_ref::<UInt8Array>().unwrap();
+        assert_eq!(ms_levels.values().to_vec(), vec![1, 2, 2]);
+
+        let mz_lists = batch.column_by_name("mz").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(mz_lists.value_offsets().to_vec(), vec![0, 3, 3, 4]);
+        let intensity_lists = batch.column_by_name("intensity").unwrap().as_any().downcast_ref::<ListArray>().unwrap();
+        let first_intensities = intensity_lists.value(0);
+        let first_intensities = first_intensities.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(first_intensities.values().to_vec(), spectra[0].data.intensity_list);
+
+        let mut invalid_spectrum = new_test_spectrum(4, 1, 0, vec![100.0]);
+        invalid_spectrum.data.intensity_list.clear();
+        assert!(spectra_to_record_batch(&[invalid_spectrum]).is_err());
+    }
+
     const RAW_FILE_PARSER_PATH_STR: &'static str =
         if cfg!(debug_assertions) {
             "./target/debug/rawfileparser"
@@ -114,10 +1026,45 @@ l" xmlns="http://psi.hupo.org/ms/mzml">
             "./target/release/rawfileparser"
         };
 
-    // WARNING: it is not possible to run multiple tests because rust unit tests are started in different threads
+    // Mono can only be configured once per process, thus the first test needing it configures it on a dedicated thread,
+    // which stays alive during the tests, while the test threads are attached to the runtime.
+    // Note: the scenarios changing the state of the runtime have their own test binary (see the "tests" directory).
+    fn configure_mono() {
+        static MONO_CONFIGURATION: std::sync::Once = std::sync::Once::new();
+
+        MONO_CONFIGURATION.call_once(|| {
+            let (configured_sender, configured_receiver) = std::sync::mpsc::channel();
+
+            std::thread::Builder::new().name("mono-tests".to_string()).spawn(move || {
+                let mut mono_embeddinator = MONO_EMBEDDINATOR.lock().unwrap();
+                mono_embeddinator.configure(RAW_FILE_PARSER_PATH_STR).expect("e4k config failed");
+                mono_embeddinator.enable_thread_attachment().expect("can't enable thread attachment");
+                drop(mono_embeddinator);
+
+                configured_sender.send(()).unwrap();
+                loop {
+                    std::thread::park();
+                }
+            }).expect("can't spawn the Mono thread");
+
+            configured_receiver.recv().expect("Mono configuration failed");
+        });
+    }
+
+    // Copies small.RAW to a directory of the temporary directory, so that the files written next to it don't end up in "resources"
+    fn copy_test_raw_file(dir_name: &str) -> String {
+        let tmp_dir = std::env::temp_dir().join(dir_name);
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+
+        let raw_file_path = tmp_dir.join("small.RAW");
+        std::fs::copy("./resources/small.RAW", &raw_file_path).unwrap();
+
+        raw_file_path.to_str().unwrap().to_string()
+    }
+
     #[test]
     fn get_spectra() {
-        MONO_EMBEDDINATOR.lock().unwrap().configure(RAW_FILE_PARSER_PATH_STR).expect("e4k config failed");
+        configure_mono();
 
         let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
 
@@ -134,7 +1081,279 @@ l" xmlns="http://psi.hupo.org/ms/mzml">
     }
 
     #[test]
-    fn multithreading_fails() {
-        assert!(MONO_EMBEDDINATOR.lock().unwrap().check_availability().is_err());
-     }
+    fn get_spectrum_metadata() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+        let s1 = streamer.get_spectrum(1).expect("get_spectrum failed");
+        assert_eq!(s1.get_polarity(), Some(Polarity::Positive));
+        assert!(s1.get_filter_string().is_some() && s1.get_scan_window().is_some());
+        assert!(s1.get_base_peak_intensity().unwrap() <= s1.get_total_ion_current().unwrap());
+    }
+
+    #[test]
+    fn get_trailer_extra() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+        let s1 = streamer.get_spectrum(1).expect("get_spectrum failed");
+        assert_eq!(streamer.trailer_extra(1).expect("trailer_extra failed"), s1.get_trailer_extra());
+    }
+
+    #[test]
+    fn get_run_info() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+        let run_info = streamer.get_run_info().expect("get_run_info failed");
+        assert!(run_info.instrument_model.is_some() && run_info.instrument_name.is_some());
+        assert_eq!(run_info.acquisition_date.as_deref(), Some(streamer.get_metadata().run.start_time_stamp.as_str()));
+    }
+
+    #[test]
+    fn iterate_spectra() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+        let n_scans = (streamer.get_last_scan_number() - streamer.get_first_scan_number() + 1) as usize;
+
+        let spectra = streamer.spectra().data_only().into_iter();
+        assert_eq!(spectra.len(), n_scans);
+
+        let total_n_peaks: usize = spectra.map(|s| s.expect("get_spectrum failed").data.mz_list.len()).sum();
+        assert_eq!(total_n_peaks, 47971, "inconsistency between expected and obtained total number of peaks");
+
+        let total_n_peaks_rev: usize = streamer.spectra().data_only().into_iter().rev().map(|s| s.expect("get_spectrum failed").data.mz_list.len()).sum();
+        assert_eq!(total_n_peaks_rev, total_n_peaks);
+
+        let ms1_spectra = streamer.spectra().with_ms_levels(&[1]).metadata_only().into_iter();
+        let ms2_spectra = streamer.spectra().with_ms_levels(&[2]).metadata_only().into_iter();
+        assert_eq!(ms1_spectra.len() + ms2_spectra.len(), n_scans);
+        assert!(ms1_spectra.map(|s| s.unwrap()).all(|s| s.get_ms_level() == 1 && s.data.mz_list.is_empty()));
+
+        let mut first_spectra = streamer.spectra().with_scan_range(1 ..= 2).with_rt_range(0.0, f64::MAX).into_iter();
+        assert_eq!(first_spectra.len(), 2);
+        assert_eq!(first_spectra.next().unwrap().unwrap().data.mz_list.len(), 1750);
+    }
+
+    #[test]
+    fn get_native_scans() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+        let mut total_n_peaks = 0;
+        for s_num in streamer.get_first_scan_number() ..= streamer.get_last_scan_number() {
+            let native_scan = streamer.get_native_scan(s_num).expect("get_native_scan failed");
+            let spectrum = streamer.get_spectrum(s_num).expect("get_spectrum failed");
+            let index_entry = ScanIndexEntry::from_spectrum(s_num, &spectrum);
+
+            assert_eq!(native_scan.ms_order, index_entry.ms_level);
+            assert_eq!(native_scan.retention_time, index_entry.retention_time);
+            assert_eq!(native_scan.tic, index_entry.tic);
+            assert_eq!(native_scan.base_peak_mass, index_entry.base_peak_mz);
+            assert_eq!(native_scan.filter_string, index_entry.filter_string);
+            assert_eq!((native_scan.precursor_mz, native_scan.precursor_charge), (index_entry.precursor_mz, index_entry.precursor_charge));
+            assert_eq!(native_scan.masses, spectrum.data.mz_list);
+            assert_eq!(native_scan.intensities, spectrum.data.intensity_list);
+
+            total_n_peaks += native_scan.masses.len();
+        }
+
+        assert_eq!(total_n_peaks, 47971, "inconsistency between expected and obtained total number of peaks");
+        assert!(streamer.get_native_scan_header(1).expect("get_native_scan_header failed").masses.is_empty());
+    }
+
+    #[test]
+    fn get_profile_and_centroid_spectra() {
+        configure_mono();
+
+        let centroid_streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+        // Profile MS1 and centroid MS2, with and without scan index to check both ways of finding the MS level
+        let profile_ms1_options = StreamerOptions::new().with_peak_picking(PeakPicking::AllMsLevelsExcept(vec![1]));
+        let profile_ms1_streamers = [
+            RawFileStreamer::new_with_options("./resources/small.RAW", &profile_ms1_options).expect("streamer creation failed"),
+            RawFileStreamer::new_with_options("./resources/small.RAW", &profile_ms1_options.clone().with_scan_index()).expect("streamer creation failed"),
+        ];
+
+        for streamer in profile_ms1_streamers.iter() {
+            for s_num in streamer.get_first_scan_number() ..= streamer.get_last_scan_number() {
+                let spectrum = streamer.get_spectrum(s_num).expect("get_spectrum failed");
+                let native_scan = streamer.get_native_scan(s_num).expect("get_native_scan failed");
+                assert_eq!(native_scan.representation, spectrum.get_representation());
+
+                if spectrum.get_ms_level() == 1 {
+                    assert_eq!(spectrum.get_representation(), Some(SpectrumRepresentation::Profile));
+                } else {
+                    let centroid_spectrum = centroid_streamer.get_spectrum(s_num).expect("get_spectrum failed");
+                    assert_eq!(spectrum.get_representation(), centroid_spectrum.get_representation());
+                    assert_eq!(spectrum.data, centroid_spectrum.data);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn get_record_batches() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+        let n_spectra = (streamer.get_last_scan_number() - streamer.get_first_scan_number() + 1) as usize;
+
+        let batches = streamer.record_batches(10).collect::<Result<Vec<_>, StreamerError>>().expect("record_batches failed");
+        assert_eq!(batches.len(), n_spectra.div_ceil(10));
+        assert!(batches.iter().all(|batch| batch.num_rows() <= 10));
+        assert_eq!(batches.iter().map(|batch| batch.num_rows()).sum::<usize>(), n_spectra);
+
+        let total_n_peaks: usize = batches.iter().map(|batch| batch.column_by_name("mz").unwrap().to_data().child_data()[0].len()).sum();
+        assert_eq!(total_n_peaks, 47971, "inconsistency between expected and obtained total number of peaks");
+
+        let ms2_batches: Vec<_> = streamer.spectra().with_ms_levels(&[2]).record_batches(1000).collect();
+        assert_eq!(ms2_batches.len(), 1);
+    }
+
+    #[test]
+    fn get_tic_and_bpc() {
+        configure_mono();
+
+        let raw_file_path = copy_test_raw_file("get_tic_and_bpc");
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+        let indexed_streamer = RawFileStreamer::new_with_scan_index(&raw_file_path).expect("streamer creation failed");
+        assert!(matches!(indexed_streamer.get_index_cache_status(), Some(IndexCacheStatus::Created | IndexCacheStatus::Rebuilt(_))));
+        let n_spectra = (streamer.get_last_scan_number() - streamer.get_first_scan_number() + 1) as usize;
+
+        let tic = streamer.tic().expect("tic failed");
+        assert_eq!(tic.len(), n_spectra);
+        assert_eq!(tic, indexed_streamer.tic().unwrap(), "native and indexed TIC should be the same");
+
+        let ms1_bpc = streamer.get_chromatogram(ChromatogramKind::BasePeak, Some(&[1])).expect("bpc failed");
+        assert!(ms1_bpc.len() < n_spectra);
+        assert_eq!(ms1_bpc.scan_numbers.first(), Some(&1));
+        assert_eq!(ms1_bpc, indexed_streamer.get_chromatogram(ChromatogramKind::BasePeak, Some(&[1])).unwrap());
+        assert_eq!(streamer.bpc().unwrap().len(), n_spectra);
+
+        assert!(!streamer.has_srm_scans().unwrap());
+        assert!(streamer.srm_chromatograms().unwrap().is_empty());
+
+        // The index persisted next to the RAW file is reused
+        let reopened_streamer = RawFileStreamer::new_with_scan_index(&raw_file_path).expect("streamer creation failed");
+        assert_eq!(reopened_streamer.get_index_cache_status(), Some(&IndexCacheStatus::Reused));
+        assert_eq!(reopened_streamer.get_scan_index(), indexed_streamer.get_scan_index());
+
+        // A cache which can't be written doesn't prevent the opening
+        let unwritable_cache_location = IndexCacheLocation::Directory(std::path::PathBuf::from(&raw_file_path));
+        let unindexed_streamer = RawFileStreamer::new_with_index_cache(&raw_file_path, &unwritable_cache_location).expect("streamer creation failed");
+        assert!(matches!(unindexed_streamer.get_index_cache_status(), Some(IndexCacheStatus::NotWritten(_))));
+        assert!(unindexed_streamer.get_scan_index().is_some());
+    }
+
+    #[test]
+    fn get_xics() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+        let ms1_tic = streamer.get_chromatogram(ChromatogramKind::TotalIonCurrent, Some(&[1])).unwrap();
+        let base_peak_mz = streamer.get_native_scan_header(1).unwrap().base_peak_mass;
+
+        let xics = streamer.extract_xics(&[
+            XicRequest::mz(base_peak_mz, MzTolerance::Ppm(10.0)),
+            XicRequest::mz_range(0.0, 1e5),
+            XicRequest::mz(base_peak_mz, MzTolerance::Da(0.5)).with_rt_range(0.0, ms1_tic.times[1]),
+            XicRequest::peptide("PEPTIDE", 2, MzTolerance::Ppm(10.0)),
+        ]).expect("XIC extraction failed");
+
+        assert_eq!(xics.len(), 4);
+        assert_eq!(xics[0].scan_numbers, ms1_tic.scan_numbers);
+        assert!(xics[0].intensities[0] > 0.0);
+        assert!(xics[1].intensities.iter().zip(&xics[0].intensities).all(|(i_all, i_xic)| i_all >= i_xic));
+        assert_eq!(xics[2].len(), 2);
+        let (peptide_mz_start, peptide_mz_end) = xics[3].mz_range.unwrap();
+        assert!(peptide_mz_start < 400.687 && peptide_mz_end > 400.687, "PEPTIDE 2+ m/z is 400.687");
+
+        assert!(streamer.extract_xics(&[XicRequest::mz_range(2.0, 1.0)]).is_err());
+    }
+
+    #[test]
+    fn get_xic_batch() {
+        configure_mono();
+
+        let streamer = RawFileStreamer::new_with_scan_index(&copy_test_raw_file("get_xic_batch")).expect("streamer creation failed");
+        let first_scan = streamer.get_native_scan(1).unwrap();
+
+        // Targets taken from the peaks of the first scan, in decreasing m/z order
+        let requests: Vec<XicRequest> = first_scan.masses.iter().rev().step_by(50).map(|mz| XicRequest::mz(*mz, MzTolerance::Ppm(5.0))).collect();
+        let results = XicBatch::new(requests.clone()).extract(&streamer).expect("XIC batch extraction failed");
+
+        assert_eq!(results.len(), requests.len());
+        for (result, request) in results.iter().zip(requests.iter()) {
+            assert_eq!(&result.request, request);
+            let peak = result.peak.expect("the targets should be observed");
+            assert!(peak.points_across_peak >= 1 && peak.area >= 0.0);
+        }
+        assert_eq!(results[0].chromatogram, streamer.extract_xics(&requests[..1]).unwrap()[0]);
+    }
+
+    #[test]
+    fn get_spectra_from_attached_threads() {
+        // Note: the thread attachment is enabled by the fixture
+        configure_mono();
+
+        let total_n_peaks_per_thread: Vec<usize> = std::thread::scope(|thread_scope| {
+            let worker_threads: Vec<_> = (0..4).map(|_| {
+                thread_scope.spawn(|| {
+                    let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+
+                    (streamer.get_first_scan_number() ..= streamer.get_last_scan_number()).map(|s_num| {
+                        streamer.get_spectrum_data(s_num).expect("get_spectrum failed").mz_list.len()
+                    }).sum()
+                })
+            }).collect();
+
+            worker_threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+
+        for total_n_peaks in total_n_peaks_per_thread {
+            assert_eq!(total_n_peaks, 47971, "inconsistency between expected and obtained total number of peaks");
+        }
+    }
+
+    #[test]
+    fn open_invalid_raw_files() {
+        configure_mono();
+
+        let tmp_dir = std::env::temp_dir().join("open_invalid_raw_files");
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+
+        let raw_file_bytes = std::fs::read("./resources/small.RAW").unwrap();
+        let truncated_file_path = tmp_dir.join("truncated.RAW");
+        std::fs::write(&truncated_file_path, &raw_file_bytes[..raw_file_bytes.len() / 10]).unwrap();
+
+        let text_file_path = tmp_dir.join("not_a_raw_file.RAW");
+        std::fs::write(&text_file_path, "this is not a Thermo RAW file").unwrap();
+
+        // The truncated file has a valid header, thus it is rejected by ThermoRawFileParser
+        let err = RawFileStreamer::new(truncated_file_path.to_str().unwrap()).expect_err("truncated RAW file opened");
+        match &err {
+            StreamerError::DotNetException { exception_type, message, .. } => {
+                assert!(!exception_type.is_empty() && !message.is_empty());
+            }
+            _ => panic!("unexpected error for the truncated RAW file: {}", err),
+        }
+
+        // While the other file is rejected by the pre-flight check
+        let err = RawFileStreamer::new(text_file_path.to_str().unwrap()).expect_err("invalid RAW file opened");
+        assert!(matches!(err, StreamerError::NotARawFile { .. }));
+
+        // The runtime is still usable after the exceptions
+        let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+        assert!(streamer.get_spectrum_data(1).is_ok());
+        assert!(matches!(streamer.get_spectrum_data(0), Err(StreamerError::ScanOutOfRange { scan_number: 0, .. })));
+
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
 }
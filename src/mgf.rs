@@ -0,0 +1,339 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::StreamerError;
+use crate::mzml_spectrum::MzMLSpectrum;
+use crate::streamer::RawFileStreamer;
+
+/// Template of the TITLE lines, made of literal text and of the following placeholders:
+/// `{file}` (RAW file name without extension), `{scan}`, `{charge}` (0 if unknown), `{rt}` (seconds) and `{index}` (0-based entry index).
+///
+/// The default template `{file}.{scan}.{scan}.{charge}` follows the TPP convention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TitleFormat {
+    tokens: Vec<TitleToken>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TitleToken {
+    Text(String),
+    File,
+    Scan,
+    Charge,
+    RetentionTime,
+    Index,
+}
+
+impl Default for TitleFormat {
+    fn default() -> Self {
+        "{file}.{scan}.{scan}.{charge}".parse().unwrap()
+    }
+}
+
+impl FromStr for TitleFormat {
+    type Err = Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut remaining = template;
+
+        while let Some(placeholder_start) = remaining.find('{') {
+            if placeholder_start > 0 {
+                tokens.push(TitleToken::Text(remaining[..placeholder_start].to_string()));
+            }
+
+            let placeholder_len = remaining[placeholder_start..].find('}')
+                .ok_or_else(|| anyhow!("unclosed placeholder in MGF title format '{}'", template))?;
+            let placeholder = &remaining[placeholder_start + 1 .. placeholder_start + placeholder_len];
+
+            tokens.push(match placeholder {
+                "file" => TitleToken::File,
+                "scan" => TitleToken::Scan,
+                "charge" => TitleToken::Charge,
+                "rt" => TitleToken::RetentionTime,
+                "index" => TitleToken::Index,
+                _ => bail!("unknown placeholder '{{{}}}' in MGF title format '{}'", placeholder, template),
+            });
+
+            remaining = &remaining[placeholder_start + placeholder_len + 1..];
+        }
+
+        if !remaining.is_empty() {
+            tokens.push(TitleToken::Text(remaining.to_string()));
+        }
+
+        if tokens.iter().any(|t| matches!(t, TitleToken::Text(text) if text.contains('}'))) {
+            bail!("unexpected '}}' in MGF title format '{}'", template);
+        }
+
+        Ok(Self { tokens })
+    }
+}
+
+impl fmt::Display for TitleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens.iter() {
+            match token {
+                TitleToken::Text(text) => f.write_str(text)?,
+                TitleToken::File => f.write_str("{file}")?,
+                TitleToken::Scan => f.write_str("{scan}")?,
+                TitleToken::Charge => f.write_str("{charge}")?,
+                TitleToken::RetentionTime => f.write_str("{rt}")?,
+                TitleToken::Index => f.write_str("{index}")?,
+            }
+        }
+
+        fmt::Result::Ok(())
+    }
+}
+
+/// Writes the MSn spectra of a RAW file as MGF entries.
+///
+/// By default only the MS2 spectra are written, and entries without a known charge have no CHARGE line.
+/// Spectra without precursor m/z are always skipped.
+pub struct MgfWriter<W: Write> {
+    writer: BufWriter<W>,
+    title_format: TitleFormat,
+    file_name: String,
+    ms_levels: Vec<u8>,
+    min_peak_count: usize,
+    unknown_charges: Vec<i8>,
+    entry_count: usize,
+}
+
+impl<W: Write> MgfWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            title_format: TitleFormat::default(),
+            file_name: String::new(),
+            ms_levels: vec![2],
+            min_peak_count: 0,
+            unknown_charges: vec![],
+            entry_count: 0,
+        }
+    }
+
+    pub fn with_title_format(mut self, title_format: TitleFormat) -> Self {
+        self.title_format = title_format;
+        self
+    }
+
+    /// Sets the value of the `{file}` placeholder (set from the RAW file name by `write_streamer` if empty).
+    pub fn with_file_name(mut self, file_name: &str) -> Self {
+        self.file_name = file_name.to_string();
+        self
+    }
+
+    pub fn with_ms_levels(mut self, ms_levels: &[u8]) -> Self {
+        self.ms_levels = ms_levels.to_vec();
+        self
+    }
+
+    /// Skips the spectra having less peaks than the given count.
+    pub fn with_min_peak_count(mut self, min_peak_count: usize) -> Self {
+        self.min_peak_count = min_peak_count;
+        self
+    }
+
+    /// Charges written on the CHARGE line (e.g. "2+ and 3+") when the precursor charge is unknown.
+    pub fn with_unknown_charges(mut self, unknown_charges: &[i8]) -> Self {
+        self.unknown_charges = unknown_charges.to_vec();
+        self
+    }
+
+    pub fn get_entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    /// Writes the selected spectra of the streamer and returns the number of written entries.
+    pub fn write_streamer(&mut self, streamer: &RawFileStreamer) -> Result<usize, StreamerError> {
+        self._write_streamer(streamer).map_err(|e| StreamerError::export("MGF", e))
+    }
+
+    /// Writes the spectrum if it matches the filters, and returns true if it has been written.
+    pub fn write_spectrum(&mut self, spectrum: &MzMLSpectrum) -> Result<bool, StreamerError> {
+        self._write_spectrum(spectrum).map_err(|e| StreamerError::export("MGF", e))
+    }
+
+    pub fn finish(self) -> Result<W, StreamerError> {
+        self.writer.into_inner().map_err(|e| StreamerError::io("can't flush the MGF writer", e.into_error()))
+    }
+
+    fn _write_streamer(&mut self, streamer: &RawFileStreamer) -> Result<usize> {
+        if self.file_name.is_empty() {
+            self.file_name = Path::new(streamer.get_raw_file_path()).file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+
+        let initial_entry_count = self.entry_count;
+        for spectrum_res in streamer.spectra().with_ms_levels(&self.ms_levels) {
+            self._write_spectrum(&spectrum_res?)?;
+        }
+
+        Ok(self.entry_count - initial_entry_count)
+    }
+
+    fn _write_spectrum(&mut self, spectrum: &MzMLSpectrum) -> Result<bool> {
+        let mz_list = spectrum.get_mz_list();
+        let intensity_list = spectrum.get_intensity_list();
+
+        if !self.ms_levels.contains(&spectrum.get_ms_level()) || mz_list.len() < self.min_peak_count {
+            return Ok(false);
+        }
+
+        let (prec_mz_opt, prec_charge_opt) = spectrum.get_precursor_mz_and_charge();
+        let prec_mz = match prec_mz_opt {
+            Some(prec_mz) => prec_mz,
+            None => return Ok(false),
+        };
+        // ThermoRawFileParser reports a zero charge state when it is unknown
+        let prec_charge_opt = prec_charge_opt.filter(|charge| *charge != 0);
+
+        let scan_number = spectrum.get_scan_number().ok_or_else(|| anyhow!("can't parse scan number of spectrum '{}'", spectrum.metadata.id))?;
+        let rt_in_seconds_opt = spectrum.get_first_scan_start_time().map(|rt| rt * 60.0);
+
+        let title = self._format_title(scan_number, prec_charge_opt.unwrap_or(0), rt_in_seconds_opt.unwrap_or(0.0));
+        let charges = prec_charge_opt.map(|charge| vec![charge]).unwrap_or_else(|| self.unknown_charges.clone());
+
+        let w = &mut self.writer;
+        writeln!(w, "BEGIN IONS")?;
+        writeln!(w, "TITLE={}", title)?;
+        if let Some(rt_in_seconds) = rt_in_seconds_opt {
+            writeln!(w, "RTINSECONDS={}", rt_in_seconds)?;
+        }
+        writeln!(w, "PEPMASS={}", prec_mz)?;
+        if !charges.is_empty() {
+            writeln!(w, "CHARGE={}", charges.iter().map(|c| _format_charge(*c)).collect::<Vec<String>>().join(" and "))?;
+        }
+        writeln!(w, "SCANS={}", scan_number)?;
+        for (mz, intensity) in mz_list.iter().zip(intensity_list) {
+            writeln!(w, "{} {}", mz, intensity)?;
+        }
+        writeln!(w, "END IONS")?;
+        writeln!(w)?;
+
+        self.entry_count += 1;
+
+        Ok(true)
+    }
+
+    fn _format_title(&self, scan_number: u32, charge: i8, rt_in_seconds: f64) -> String {
+        let mut title = String::new();
+
+        for token in self.title_format.tokens.iter() {
+            match token {
+                TitleToken::Text(text) => title.push_str(text),
+                TitleToken::File => title.push_str(&self.file_name),
+                TitleToken::Scan => title.push_str(&scan_number.to_string()),
+                TitleToken::Charge => title.push_str(&charge.to_string()),
+                TitleToken::RetentionTime => title.push_str(&rt_in_seconds.to_string()),
+                TitleToken::Index => title.push_str(&self.entry_count.to_string()),
+            }
+        }
+
+        title
+    }
+}
+
+// MGF charges are written with a trailing sign (e.g. "2+")
+fn _format_charge(charge: i8) -> String {
+    if charge < 0 { format!("{}-", -(charge as i16)) } else { format!("{}+", charge) }
+}
+
+/// MGF entry (BEGIN IONS / END IONS block) as read by `parse_mgf`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MgfEntry {
+    pub title: String,
+    pub precursor_mz: f64,
+    pub charges: Vec<i8>,
+    /// Retention time in seconds
+    pub retention_time: Option<f64>,
+    pub scans: Option<String>,
+    pub mz_list: Vec<f64>,
+    pub intensity_list: Vec<f64>,
+}
+
+/// Parses the entries of an MGF file content. Unsupported header lines are ignored.
+pub fn parse_mgf(mgf_content: &str) -> Result<Vec<MgfEntry>, StreamerError> {
+    _parse_mgf(mgf_content).map_err(|e| StreamerError::invalid_input("MGF content", e))
+}
+
+fn _parse_mgf(mgf_content: &str) -> Result<Vec<MgfEntry>> {
+    let mut entries = Vec::new();
+    let mut current_entry_opt: Option<MgfEntry> = None;
+
+    for (line_idx, line) in mgf_content.lines().enumerate() {
+        let line = line.trim();
+        let line_number = line_idx + 1;
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line == "BEGIN IONS" {
+            if current_entry_opt.is_some() {
+                bail!("unexpected BEGIN IONS at line {} (missing END IONS)", line_number);
+            }
+            current_entry_opt = Some(MgfEntry::default());
+            continue;
+        }
+
+        let entry = match current_entry_opt.as_mut() {
+            Some(entry) => entry,
+            // Global parameters
+            None => continue,
+        };
+
+        if line == "END IONS" {
+            entries.push(current_entry_opt.take().unwrap());
+        } else if let Some((key, value)) = line.split_once('=') {
+            match key {
+                "TITLE" => entry.title = value.to_string(),
+                "PEPMASS" => {
+                    // The precursor intensity may follow the m/z value
+                    let prec_mz_str = value.split_whitespace().next().unwrap_or_default();
+                    entry.precursor_mz = prec_mz_str.parse().with_context(|| format!("invalid PEPMASS at line {}", line_number))?;
+                }
+                "CHARGE" => {
+                    entry.charges = value.split(" and ").flat_map(|v| v.split(','))
+                        .map(|c| _parse_charge(c.trim()).ok_or_else(|| anyhow!("invalid CHARGE at line {}", line_number)))
+                        .collect::<Result<Vec<i8>>>()?;
+                }
+                "RTINSECONDS" => entry.retention_time = Some(value.parse().with_context(|| format!("invalid RTINSECONDS at line {}", line_number))?),
+                "SCANS" => entry.scans = Some(value.to_string()),
+                _ => {}
+            }
+        } else {
+            let mut peak_values = line.split_whitespace();
+            let mz_opt = peak_values.next().and_then(|v| v.parse::<f64>().ok());
+            let intensity_opt = peak_values.next().and_then(|v| v.parse::<f64>().ok());
+            match (mz_opt, intensity_opt) {
+                (Some(mz), Some(intensity)) => {
+                    entry.mz_list.push(mz);
+                    entry.intensity_list.push(intensity);
+                }
+                _ => bail!("invalid peak at line {}: '{}'", line_number, line),
+            }
+        }
+    }
+
+    if current_entry_opt.is_some() {
+        bail!("the last MGF entry is not terminated by END IONS");
+    }
+
+    Ok(entries)
+}
+
+fn _parse_charge(charge_str: &str) -> Option<i8> {
+    if let Some(abs_charge) = charge_str.strip_suffix('-') {
+        abs_charge.parse::<i8>().ok().map(|c| -c)
+    } else {
+        charge_str.strip_suffix('+').unwrap_or(charge_str).parse::<i8>().ok()
+    }
+}
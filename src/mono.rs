@@ -1,21 +1,95 @@
 
-use anyhow::*;
-use std::ffi::{c_char, CString};
+use anyhow::{anyhow, Context, Error, Result};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
 use std::path::Path;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use path_absolutize::Absolutize;
 
 use crate::bindings::*;
+use crate::discovery::{DiscoveryReport, MonoDiscovery};
+use crate::error::StreamerError;
+use crate::native_scan::_take_string;
 
 lazy_static! {
     pub static ref MONO_EMBEDDINATOR: Mutex<MonoEmbeddinator> = Mutex::new(MonoEmbeddinator::new());
 }
 
+// Number of living `MonoRuntime` guards (only incremented while MONO_EMBEDDINATOR is locked)
+static MONO_RUNTIME_GUARD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Registration of the current OS thread to the Mono runtime (detached when the thread ends)
+    static MONO_THREAD_ATTACHMENT: RefCell<Option<MonoThreadAttachment>> = const { RefCell::new(None) };
+
+    // Error reported by the Embeddinator error hook during the current glue call of this thread
+    static PENDING_GLUE_ERROR: RefCell<Option<StreamerError>> = const { RefCell::new(None) };
+}
+
+struct MonoThreadAttachment {
+    mono_thread: *mut MonoThread,
+}
+
+impl Drop for MonoThreadAttachment {
+    fn drop(&mut self) {
+        unsafe {
+            // The runtime may have been cleaned up before the end of this thread
+            if !mono_get_root_domain().is_null() {
+                mono_thread_detach(self.mono_thread);
+            }
+        }
+    }
+}
+
+/// Guard keeping the Mono runtime alive: `MonoEmbeddinator::dispose` fails as long as a guard exists.
+///
+/// Each `RawFileStreamer` owns a guard, which is dropped after its .NET objects have been disposed.
+/// Thus these objects can't outlive the runtime, whatever the drop order of the streamers is.
+#[derive(Debug)]
+pub struct MonoRuntime {
+    // Prevents the creation of guards outside of `acquire`
+    _private: (),
+}
+
+impl MonoRuntime {
+    /// Returns a new guard if the runtime is available from the current thread (see `MonoEmbeddinator::check_availability`).
+    pub fn acquire() -> Result<MonoRuntime, StreamerError> {
+        let mono_embeddinator = MONO_EMBEDDINATOR.lock().unwrap();
+        mono_embeddinator.check_availability()?;
+
+        // Note: the lock is held to prevent a concurrent disposal of the runtime
+        MONO_RUNTIME_GUARD_COUNT.fetch_add(1, Ordering::SeqCst);
+
+        Ok(MonoRuntime { _private: () })
+    }
+
+    /// Returns the number of living guards.
+    pub fn get_guard_count() -> usize {
+        MONO_RUNTIME_GUARD_COUNT.load(Ordering::SeqCst)
+    }
+}
+
+impl Clone for MonoRuntime {
+    fn clone(&self) -> Self {
+        // The runtime can't be disposed while this guard exists, so no lock is needed
+        MONO_RUNTIME_GUARD_COUNT.fetch_add(1, Ordering::SeqCst);
+        MonoRuntime { _private: () }
+    }
+}
+
+impl Drop for MonoRuntime {
+    fn drop(&mut self) {
+        MONO_RUNTIME_GUARD_COUNT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MonoEmbeddinator {
     configured: bool,
     disposed: bool,
+    thread_attachment_enabled: bool,
     init_thread_id: u64,
     assembly_path: Option<CString>,
     runtime_assembly_path: Option<CString>
@@ -35,18 +109,20 @@ impl MonoEmbeddinator {
         Self {
             configured: false,
             disposed: false,
+            thread_attachment_enabled: false,
             init_thread_id: 0,
             assembly_path: None,
             runtime_assembly_path: None
         }
     }
 
-    pub fn get_current_thread_id_as_u64() -> Result<u64> {
+    pub fn get_current_thread_id_as_u64() -> Result<u64, StreamerError> {
         let raw_thread_id = format!("{:?}", std::thread::current().id());
         //dbg!("raw_thread_id={} {}", raw_thread_id.clone(), std::thread::current().id());
         let parsed_thread_id = raw_thread_id.trim_start_matches("ThreadId(")
             .trim_end_matches(")")
-            .parse::<u64>()?;
+            .parse::<u64>()
+            .with_context(|| format!("can't parse thread id '{}'", raw_thread_id))?;
 
         Ok(parsed_thread_id)
     }
@@ -59,83 +135,155 @@ impl MonoEmbeddinator {
         self.disposed
     }*/
 
-    pub fn check_availability(&self) -> Result<()> {
+    pub fn check_availability(&self) -> Result<(), StreamerError> {
         if !self.configured {
-            bail!("Mono Embeddinator is not configured");
+            return Err(StreamerError::MonoNotConfigured);
         }
         if self.disposed {
-            bail!("Mono Embeddinator is disposed");
+            return Err(StreamerError::MonoDisposed);
         }
 
         let cur_thread_id = MonoEmbeddinator::get_current_thread_id_as_u64()?;
         if self.init_thread_id != cur_thread_id {
-            bail!("forbidden operation: Mono Embeddinator was initiated in thread '{}' but is now used from thread '{}'", self.init_thread_id, cur_thread_id);
+            if !self.thread_attachment_enabled {
+                return Err(StreamerError::WrongThread { init_thread_id: self.init_thread_id, current_thread_id: cur_thread_id });
+            }
+
+            MonoEmbeddinator::_attach_current_thread()?;
         }
 
         Ok(())
     }
 
-    pub fn configure(&mut self, raw_file_parser_directory: &str) -> Result<()> {
-        if self.disposed {
-            bail!("can't configure Mono Embeddinator since it has been disposed");
+    pub fn is_thread_attachment_enabled(&self) -> bool {
+        self.thread_attachment_enabled
+    }
+
+    /// Opt-in mode where threads other than the configuring one are registered to the Mono runtime
+    /// (on their first call to `check_availability`) instead of being refused.
+    /// Attached threads are automatically detached from Mono when they end.
+    ///
+    /// Must be called from the configuring thread, since the Mono runtime is initialized here
+    /// to prevent several threads from racing on its lazy initialization.
+    pub fn enable_thread_attachment(&mut self) -> Result<(), StreamerError> {
+        self.check_availability()?;
+
+        unsafe {
+            // Any call to the glue code initializes the Mono runtime and loads the ThermoRawFileParser assembly
+            _take_string(call_glue(|| ThermoRawFileParser_MainClass_get_Version())?);
         }
 
-        if self.configured {
-            bail!("Mono Embeddinator can't be configured twice!");
+        self.thread_attachment_enabled = true;
+
+        Ok(())
+    }
+
+    fn _attach_current_thread() -> Result<(), StreamerError> {
+        MONO_THREAD_ATTACHMENT.with(|attachment_cell| {
+            let mut attachment_opt = attachment_cell.borrow_mut();
+            if attachment_opt.is_some() {
+                return Ok(());
+            }
+
+            unsafe {
+                let root_domain = mono_get_root_domain();
+                if root_domain.is_null() {
+                    return Err(_thread_attachment_error("the Mono runtime has not been initialized"));
+                }
+
+                let mono_thread = mono_thread_attach(root_domain);
+                if mono_thread.is_null() {
+                    return Err(_thread_attachment_error("mono_thread_attach failed"));
+                }
+
+                *attachment_opt = Some(MonoThreadAttachment { mono_thread });
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Configures Mono with the given directory of the ThermoRawFileParser assemblies, the Mono runtime being discovered
+    /// (see `MonoEmbeddinator::configure_with_discovery`).
+    pub fn configure(&mut self, raw_file_parser_directory: &str) -> Result<(), StreamerError> {
+        if !self.disposed && !self.configured && !Path::new(raw_file_parser_directory).is_dir() {
+            return Err(StreamerError::MissingDirectory { description: "the ThermoRawFileParser", path: raw_file_parser_directory.to_string() });
         }
 
-        let raw_file_parser_path = Path::new(&raw_file_parser_directory);
+        self.configure_with_discovery(&MonoDiscovery::new().with_assembly_dir(raw_file_parser_directory))?;
+
+        Ok(())
+    }
+
+    /// Searches the ThermoRawFileParser assemblies and the Mono runtime (see `MonoDiscovery`), then configures Mono.
+    ///
+    /// Returns the discovery report, which is also carried by the `StreamerError::DiscoveryFailed` error.
+    pub fn configure_with_discovery(&mut self, discovery: &MonoDiscovery) -> Result<DiscoveryReport, StreamerError> {
+        if self.disposed {
+            return Err(StreamerError::MonoDisposed);
+        }
 
-        if raw_file_parser_path.is_dir() == false {
-            bail!("can't find the ThermoRawFileParser at '{}'", raw_file_parser_directory);
+        if self.configured {
+            return Err(StreamerError::MonoAlreadyConfigured);
         }
 
+        let report = discovery.discover();
+        let (Some(raw_file_parser_path), Some(mono_runtime_path)) = (report.get_assembly_dir(), report.get_mono_runtime_dir()) else {
+            return Err(StreamerError::DiscoveryFailed { report });
+        };
+
         // Define Mono runtime location (note: missing ending slash prevents Mono to load properly)
-        let raw_file_parser_abs_dir = raw_file_parser_path.absolutize()?
-            .to_string_lossy()
-            .to_string()
-            .replace('\\', "/") + "/";
+        let raw_file_parser_abs_dir = _to_assembly_path_string(raw_file_parser_path)?;
 
-        self.assembly_path = Some(CString::new(raw_file_parser_abs_dir.clone())?);
+        self.assembly_path = Some(CString::new(raw_file_parser_abs_dir.clone()).map_err(Error::from)?);
 
         unsafe {
             mono_embeddinator_set_assembly_path(self.assembly_path.as_ref().unwrap().as_ptr() as *const c_char);
         }
 
-        let runtime_assembly_path = if cfg!(windows) {raw_file_parser_abs_dir}
-        else {
-            MonoEmbeddinator::_get_mono_runtime_assembly_path_on_linux() + "/"
-        };
-
-        let mono_directory_path = Path::new(&runtime_assembly_path).join("mono");
-        if !mono_directory_path.is_dir() {
-            bail!("can't find Mono runtime at '{:?}'", mono_directory_path)
-        }
+        let runtime_assembly_path = _to_assembly_path_string(mono_runtime_path)?;
 
-        self.runtime_assembly_path = Some(CString::new(runtime_assembly_path)?);
+        self.runtime_assembly_path = Some(CString::new(runtime_assembly_path).map_err(Error::from)?);
 
         // Set Mono runtime location
         unsafe { mono_embeddinator_set_runtime_assembly_path(self.runtime_assembly_path.as_ref().unwrap().as_ptr() as *const c_char) }
 
+        // Replace the default hook of Embeddinator, which aborts the process on any .NET exception
+        unsafe { mono_embeddinator_install_error_report_hook(Some(_report_glue_error)); }
+
         self.configured = true;
 
         self.init_thread_id = MonoEmbeddinator::get_current_thread_id_as_u64()?;
 
-        Ok(())
+        Ok(report)
     }
 
-    pub fn dispose(&mut self) -> Result<()> {
+    /// Shuts the Mono runtime down, which can't be restarted afterwards.
+    ///
+    /// Fails if some `MonoRuntime` guards are still alive (i.e. some `RawFileStreamer` instances have not been dropped),
+    /// or if called from another thread than the configuring one.
+    pub fn dispose(&mut self) -> Result<(), StreamerError> {
         if !self.configured {
-            bail!("can't dispose Mono Embeddinator since it has not been previously configured");
+            return Err(StreamerError::MonoNotConfigured);
         }
 
         if self.disposed {
-            bail!("Mono Embeddinator has been already disposed");
+            return Err(StreamerError::MonoDisposed);
+        }
+
+        let cur_thread_id = MonoEmbeddinator::get_current_thread_id_as_u64()?;
+        if self.init_thread_id != cur_thread_id {
+            return Err(StreamerError::WrongThread { init_thread_id: self.init_thread_id, current_thread_id: cur_thread_id });
+        }
+
+        let guard_count = MonoRuntime::get_guard_count();
+        if guard_count > 0 {
+            return Err(StreamerError::MonoRuntimeInUse { guard_count });
         }
 
         unsafe {
             if mono_embeddinator_get_context().is_null() || mono_domain_get().is_null()  {
-                bail!("Mono JIT runtime has been initiated yet.");
+                return Err(anyhow!("Mono JIT runtime has not been initiated yet").into());
             }
 
             // There are alternatives but it's not clear to me what is the best one
@@ -163,16 +311,83 @@ impl MonoEmbeddinator {
             Ok(())
         }
     }
+}
 
-    // And this function only gets compiled if the target OS is *not* linux
-    fn _get_mono_runtime_assembly_path_on_linux() -> String {
-        // MONO_PATH should refer to a fully defined and absolute path to MONO (like "/usr/lib/mono/4.0")
-        match std::env::var("MONO_PATH") {
-            Result::Ok(mono_path) => {
-                Some(mono_path.split("mono").next().unwrap_or(&mono_path)).unwrap_or(&mono_path).to_string()
-            }
-            Err(_) => "/usr/lib/".to_string(),
-        }
+// Absolute path with forward slashes and an ending slash, as expected by Embeddinator
+fn _to_assembly_path_string(dir_path: &Path) -> Result<String, StreamerError> {
+    let abs_dir_path = dir_path.absolutize().map_err(Error::from)?
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    Ok(abs_dir_path.trim_end_matches('/').to_string() + "/")
+}
+
+
+/// Calls a function of the ThermoRawFileParser glue, and returns the .NET exception it has thrown, if any.
+///
+/// The glue returns a null object (or a zero value) when an exception is thrown, thus the result must not be
+/// used in that case.
+pub(crate) unsafe fn call_glue<T>(glue_fn: impl FnOnce() -> T) -> Result<T, StreamerError> {
+    PENDING_GLUE_ERROR.with(|pending_error| pending_error.borrow_mut().take());
+
+    let result = glue_fn();
+
+    match PENDING_GLUE_ERROR.with(|pending_error| pending_error.borrow_mut().take()) {
+        Some(glue_error) => Err(glue_error),
+        None => Ok(result),
     }
 }
 
+// Called by the glue on the thread where the error occurred, which then continues its execution
+unsafe extern "C" fn _report_glue_error(error: mono_embeddinator_error_t) {
+    let streamer_error = if error.type_ == mono_embeddinator_error_type_t_MONO_EMBEDDINATOR_EXCEPTION_THROWN && !error.exception.is_null() {
+        _read_dotnet_exception(error.exception as *mut MonoObject)
+    } else {
+        // Messages of the other error types are static strings
+        let message = CStr::from_ptr(mono_embeddinator_error_to_string(error)).to_string_lossy().into_owned();
+        StreamerError::Embeddinator { message }
+    };
+
+    PENDING_GLUE_ERROR.with(|pending_error| *pending_error.borrow_mut() = Some(streamer_error));
+}
+
+unsafe fn _read_dotnet_exception(exception: *mut MonoObject) -> StreamerError {
+    let exception_class = mono_object_get_class(exception);
+    let exception_namespace = CStr::from_ptr(mono_class_get_namespace(exception_class)).to_string_lossy();
+    let exception_name = CStr::from_ptr(mono_class_get_name(exception_class)).to_string_lossy();
+
+    StreamerError::DotNetException {
+        exception_type: format!("{}.{}", exception_namespace, exception_name),
+        message: _read_string_property(exception, exception_class, "Message").unwrap_or_default(),
+        stack_trace: _read_string_property(exception, exception_class, "StackTrace"),
+    }
+}
+
+// Reads a string property of a managed object (None if missing, null or throwing)
+unsafe fn _read_string_property(object: *mut MonoObject, class: *mut MonoClass, property_name: &str) -> Option<String> {
+    let property_name_cstr = CString::new(property_name).ok()?;
+    let property = mono_class_get_property_from_name(class, property_name_cstr.as_ptr());
+    if property.is_null() {
+        return None;
+    }
+
+    let mut property_exception: *mut MonoObject = std::ptr::null_mut();
+    let value = mono_property_get_value(property, object as *mut c_void, std::ptr::null_mut(), &mut property_exception);
+    if value.is_null() || !property_exception.is_null() {
+        return None;
+    }
+
+    _take_string(mono_string_to_utf8(value as *mut MonoString))
+}
+
+fn _thread_attachment_error(reason: &'static str) -> StreamerError {
+    StreamerError::ThreadAttachment { thread: format!("{:?}", std::thread::current().id()), reason }
+}
+
+// Add functions missing in the automatically generated code (declared in mono/metadata/threads.h)
+#[link(name = "monosgen-2.0")] extern "C" {
+    fn mono_thread_attach(domain: *mut MonoDomain) -> *mut MonoThread;
+}
+#[link(name = "monosgen-2.0")] extern "C" {
+    fn mono_thread_detach(thread: *mut MonoThread);
+}
@@ -4,16 +4,119 @@ use anyhow::*;
 use quick_xml;
 use serde::{Serialize, Deserialize};
 
+use crate::error::StreamerError;
+
 pub const MS_LEVEL_CV_ACCESSION: &'static str = "MS:1000511";
 pub const CHARGE_STATE_CV_ACCESSION: &'static str = "MS:1000041";
 pub const SCAN_START_TIME_CV_ACCESSION: &'static str = "MS:1000016";
 pub const SELECTED_ION_MZ_CV_ACCESSION: &'static str = "MS:1000744";
+pub const TOTAL_ION_CURRENT_CV_ACCESSION: &'static str = "MS:1000285";
+pub const BASE_PEAK_MZ_CV_ACCESSION: &'static str = "MS:1000504";
+pub const BASE_PEAK_INTENSITY_CV_ACCESSION: &'static str = "MS:1000505";
+pub const FILTER_STRING_CV_ACCESSION: &'static str = "MS:1000512";
+pub const SCAN_WINDOW_LOWER_LIMIT_CV_ACCESSION: &'static str = "MS:1000501";
+pub const SCAN_WINDOW_UPPER_LIMIT_CV_ACCESSION: &'static str = "MS:1000500";
+pub const CENTROID_SPECTRUM_CV_ACCESSION: &'static str = "MS:1000127";
+pub const PROFILE_SPECTRUM_CV_ACCESSION: &'static str = "MS:1000128";
+pub const PEAK_PICKING_CV_ACCESSION: &'static str = "MS:1000035";
+pub const MZ_ARRAY_CV_ACCESSION: &'static str = "MS:1000514";
+pub const INTENSITY_ARRAY_CV_ACCESSION: &'static str = "MS:1000515";
+pub const FLOAT_32_BIT_CV_ACCESSION: &'static str = "MS:1000521";
+pub const FLOAT_64_BIT_CV_ACCESSION: &'static str = "MS:1000523";
+pub const ZLIB_COMPRESSION_CV_ACCESSION: &'static str = "MS:1000574";
+pub const NO_COMPRESSION_CV_ACCESSION: &'static str = "MS:1000576";
+pub const TIME_ARRAY_CV_ACCESSION: &'static str = "MS:1000595";
+pub const TIC_CHROMATOGRAM_CV_ACCESSION: &'static str = "MS:1000235";
+pub const BASEPEAK_CHROMATOGRAM_CV_ACCESSION: &'static str = "MS:1000628";
+pub const SIC_CHROMATOGRAM_CV_ACCESSION: &'static str = "MS:1000627";
+pub const SRM_CHROMATOGRAM_CV_ACCESSION: &'static str = "MS:1001473";
+pub const ISOLATION_WINDOW_TARGET_MZ_CV_ACCESSION: &'static str = "MS:1000827";
+pub const ISOLATION_WINDOW_LOWER_OFFSET_CV_ACCESSION: &'static str = "MS:1000828";
+pub const ISOLATION_WINDOW_UPPER_OFFSET_CV_ACCESSION: &'static str = "MS:1000829";
+pub const COLLISION_ENERGY_CV_ACCESSION: &'static str = "MS:1000045";
+pub const CID_CV_ACCESSION: &'static str = "MS:1000133";
+pub const HCD_CV_ACCESSION: &'static str = "MS:1000422";
+pub const ETD_CV_ACCESSION: &'static str = "MS:1000598";
+pub const ECD_CV_ACCESSION: &'static str = "MS:1000250";
+pub const PQD_CV_ACCESSION: &'static str = "MS:1000599";
+pub const IRMPD_CV_ACCESSION: &'static str = "MS:1000262";
+pub const UVPD_CV_ACCESSION: &'static str = "MS:1003246";
+pub const SUPPLEMENTAL_HCD_CV_ACCESSION: &'static str = "MS:1002678";
+pub const SUPPLEMENTAL_CID_CV_ACCESSION: &'static str = "MS:1002679";
+pub const POSITIVE_SCAN_CV_ACCESSION: &'static str = "MS:1000130";
+pub const NEGATIVE_SCAN_CV_ACCESSION: &'static str = "MS:1000129";
+pub const LOWEST_OBSERVED_MZ_CV_ACCESSION: &'static str = "MS:1000528";
+pub const HIGHEST_OBSERVED_MZ_CV_ACCESSION: &'static str = "MS:1000527";
+pub const ION_INJECTION_TIME_CV_ACCESSION: &'static str = "MS:1000927";
+pub const FAIMS_COMPENSATION_VOLTAGE_CV_ACCESSION: &'static str = "MS:1001581";
+pub const INSTRUMENT_SERIAL_NUMBER_CV_ACCESSION: &'static str = "MS:1000529";
+pub const SAMPLE_NAME_CV_ACCESSION: &'static str = "MS:1000002";
+pub const SAMPLE_VOLUME_CV_ACCESSION: &'static str = "MS:1000005";
+pub const SAMPLE_VIAL_CV_ACCESSION: &'static str = "NCIT:C41275";
+pub const SAMPLE_ROW_CV_ACCESSION: &'static str = "NCIT:C43378";
+pub const INJECTION_VOLUME_CV_ACCESSION: &'static str = "AFR:0001577";
+pub const DILUTION_FACTOR_CV_ACCESSION: &'static str = "AFQ:0000178";
+pub const NUMBER_OF_DETECTOR_COUNTS_UNIT_ACCESSION: &'static str = "MS:1000131";
+pub const MZ_UNIT_ACCESSION: &'static str = "MS:1000040";
+pub const ELECTRONVOLT_UNIT_ACCESSION: &'static str = "UO:0000266";
+pub const MINUTE_UNIT_ACCESSION: &'static str = "UO:0000031";
+pub const SECOND_UNIT_ACCESSION: &'static str = "UO:0000010";
+pub const MILLISECOND_UNIT_ACCESSION: &'static str = "UO:0000028";
+pub const THERMO_TRAILER_EXTRA_PREFIX: &'static str = "[Thermo Trailer Extra]";
+pub const MONOISOTOPIC_MZ_USER_PARAM_NAME: &'static str = "[Thermo Trailer Extra]Monoisotopic M/Z:";
+
+/// Unit of a retention time or of a chromatogram time array.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeUnit {
+    #[default]
+    Minute,
+    Second,
+}
+
+impl TimeUnit {
+    pub fn from_unit_accession(unit_accession: &str) -> Option<Self> {
+        match unit_accession {
+            MINUTE_UNIT_ACCESSION => Some(TimeUnit::Minute),
+            SECOND_UNIT_ACCESSION => Some(TimeUnit::Second),
+            _ => None,
+        }
+    }
+
+    pub fn get_unit_accession(&self) -> &'static str {
+        match self {
+            TimeUnit::Minute => MINUTE_UNIT_ACCESSION,
+            TimeUnit::Second => SECOND_UNIT_ACCESSION,
+        }
+    }
+
+    pub fn get_unit_name(&self) -> &'static str {
+        match self {
+            TimeUnit::Minute => "minute",
+            TimeUnit::Second => "second",
+        }
+    }
+
+    pub fn to_minutes(&self, time: f64) -> f64 {
+        match self {
+            TimeUnit::Minute => time,
+            TimeUnit::Second => time / 60.0,
+        }
+    }
+
+    pub fn to_seconds(&self, time: f64) -> f64 {
+        match self {
+            TimeUnit::Minute => time * 60.0,
+            TimeUnit::Second => time,
+        }
+    }
+}
 
-pub fn parse_mzml_metadata(mzml_header: &str) -> Result<MzMLMetaData> {
+pub fn parse_mzml_metadata(mzml_header: &str) -> Result<MzMLMetaData, StreamerError> {
 
-    let parsed_mzml_header: MzMLMetaData = quick_xml::de::from_str(mzml_header)?;
+    let parsed_mzml_header: MzMLMetaData = quick_xml::de::from_str(mzml_header)
+        .map_err(|e| StreamerError::xml_parse(e, mzml_header))?;
 
-    Ok(parsed_mzml_header)
+    Result::Ok(parsed_mzml_header)
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,16 +150,23 @@ pub struct CvParam {
     pub accession: String,
     #[serde(rename = "@name")]
     pub name: String,
-    #[serde(rename = "@value")]
+    #[serde(rename = "@value", skip_serializing_if = "Option::is_none")]
     pub value: Option<String>,
-    #[serde(rename = "@unitCvRef")]
+    #[serde(rename = "@unitCvRef", skip_serializing_if = "Option::is_none")]
     pub unit_cv_ref: Option<String>,
-    #[serde(rename = "@unitAccession")]
+    #[serde(rename = "@unitAccession", skip_serializing_if = "Option::is_none")]
     pub unit_accession: Option<String>,
-    #[serde(rename = "@unitName")]
+    #[serde(rename = "@unitName", skip_serializing_if = "Option::is_none")]
     pub unit_name: Option<String>,
 }
 
+impl CvParam {
+    /// Parses the value, returning None if it is missing or invalid.
+    pub fn parse_value<T: std::str::FromStr>(&self) -> Option<T> {
+        self.value.as_ref().and_then(|value| value.parse::<T>().ok())
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct UserParam {
     #[serde(rename = "@name")]
@@ -200,8 +310,11 @@ pub struct ReferenceableParamGroupRef {
 pub struct ComponentList {
     #[serde(rename = "@count")]
     pub count: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<Source>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub analyzer:  Option<Analyzer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub detector:  Option<Detector>,
 }
 
@@ -253,6 +366,8 @@ pub struct ProcessingMethod {
     pub software_ref: String,
     #[serde(rename = "cvParam", default)]
     pub cv_params: Vec<CvParam>,
+    #[serde(rename = "userParam", default)]
+    pub user_params: Vec<UserParam>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
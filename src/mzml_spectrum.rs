@@ -4,13 +4,17 @@
 use anyhow::*;
 use quick_xml;
 use serde::{Serialize, Deserialize};
+use crate::error::StreamerError;
 use crate::mzml::*;
+use crate::scan_filter::ScanFilter;
+use crate::trailer_extra::TrailerExtra;
 
-pub fn parse_mzml_spectrum_metadata(spectrum_header: &str) -> Result<MzMLSpectrumMetaData> {
+pub fn parse_mzml_spectrum_metadata(spectrum_header: &str) -> Result<MzMLSpectrumMetaData, StreamerError> {
 
-    let parsed_mzml_header: MzMLSpectrumMetaData = quick_xml::de::from_str(spectrum_header)?;
+    let parsed_mzml_header: MzMLSpectrumMetaData = quick_xml::de::from_str(spectrum_header)
+        .map_err(|e| StreamerError::xml_parse(e, spectrum_header))?;
 
-    Ok(parsed_mzml_header)
+    Result::Ok(parsed_mzml_header)
 }
 
 // TODO: use the mzcore API when ready
@@ -77,7 +81,7 @@ impl MzMLSpectrum {
                 .map(|cv_param| cv_param.value.as_ref().map(|value| value.parse::<i8>().unwrap_or(0 ))).flatten();
 
             let thermo_trailer_param_opt = self.metadata.scan_list.scans.first().map(|s| {
-                s.user_params.iter().find(|user_param| user_param.name == "[Thermo Trailer Extra]Monoisotopic M/Z:")
+                s.user_params.iter().find(|user_param| user_param.name == MONOISOTOPIC_MZ_USER_PARAM_NAME)
             }).flatten();
 
             prec_mz_opt = thermo_trailer_param_opt.map(|trailer_param|  trailer_param.value.parse::<f64>().ok() ).flatten().or_else( || {
@@ -90,12 +94,270 @@ impl MzMLSpectrum {
         (prec_mz_opt, prec_charge_opt)
     }
 
+    /// Returns the spectrum representation declared in the meta-data (MS:1000127 or MS:1000128).
+    pub fn get_representation(&self) -> Option<SpectrumRepresentation> {
+        self.metadata.cv_params.iter().find_map(|cvp| SpectrumRepresentation::from_cv_accession(&cvp.accession))
+    }
+
+    /// Returns the scan number parsed from the Thermo native ID (e.g. "controllerType=0 controllerNumber=1 scan=42").
+    pub fn get_scan_number(&self) -> Option<u32> {
+        self.metadata.id.split(' ').find_map(|id_part| id_part.strip_prefix("scan=")).and_then(|scan| scan.parse::<u32>().ok())
+    }
+
+    /// Returns the start time of the first scan in minutes.
     pub fn get_first_scan_start_time(&self) -> Option<f64> {
-        self.metadata.scan_list.scans.first().map(|fs| {
-            fs.cv_params.iter().find(|cvp| cvp.accession == SCAN_START_TIME_CV_ACCESSION).map(|start_time_cv| {
-                start_time_cv.value.as_ref().map(|value| value.parse::<f64>().unwrap_or(0.0 ))
-            }).flatten()
-        }).flatten()
+        self.get_scan_start_time(TimeUnit::Minute)
+    }
+
+    /// Returns the start time of the first scan in the given unit, converted from its declared unit (minutes if not declared).
+    pub fn get_scan_start_time(&self, time_unit: TimeUnit) -> Option<f64> {
+        let start_time_cv = _find_cv_param(&self.metadata.scan_list.scans.first()?.cv_params, SCAN_START_TIME_CV_ACCESSION)?;
+        let start_time = start_time_cv.parse_value::<f64>().unwrap_or(0.0);
+        let declared_time_unit = start_time_cv.unit_accession.as_deref().and_then(TimeUnit::from_unit_accession).unwrap_or_default();
+
+        Some(match time_unit {
+            TimeUnit::Minute => declared_time_unit.to_minutes(start_time),
+            TimeUnit::Second => declared_time_unit.to_seconds(start_time),
+        })
+    }
+
+    /// Returns the scan polarity (MS:1000130 or MS:1000129).
+    pub fn get_polarity(&self) -> Option<Polarity> {
+        self.metadata.cv_params.iter().find_map(|cvp| Polarity::from_cv_accession(&cvp.accession))
+    }
+
+    pub fn get_total_ion_current(&self) -> Option<f64> {
+        self._get_spectrum_cv_value(TOTAL_ION_CURRENT_CV_ACCESSION)
+    }
+
+    pub fn get_base_peak_mz(&self) -> Option<f64> {
+        self._get_spectrum_cv_value(BASE_PEAK_MZ_CV_ACCESSION)
+    }
+
+    pub fn get_base_peak_intensity(&self) -> Option<f64> {
+        self._get_spectrum_cv_value(BASE_PEAK_INTENSITY_CV_ACCESSION)
+    }
+
+    pub fn get_lowest_observed_mz(&self) -> Option<f64> {
+        self._get_spectrum_cv_value(LOWEST_OBSERVED_MZ_CV_ACCESSION)
+    }
+
+    pub fn get_highest_observed_mz(&self) -> Option<f64> {
+        self._get_spectrum_cv_value(HIGHEST_OBSERVED_MZ_CV_ACCESSION)
+    }
+
+    /// Returns the Thermo filter string of the first scan.
+    pub fn get_filter_string(&self) -> Option<&str> {
+        _find_cv_param(&self.metadata.scan_list.scans.first()?.cv_params, FILTER_STRING_CV_ACCESSION)?.value.as_deref()
+    }
+
+    /// Returns the parsed filter string of the first scan, or None if it is missing or can't be parsed.
+    pub fn get_scan_filter(&self) -> Option<ScanFilter> {
+        self.get_filter_string()?.parse().ok()
+    }
+
+    /// Returns the ion injection time of the first scan in milliseconds, converted from its declared unit (milliseconds if not declared).
+    pub fn get_ion_injection_time(&self) -> Option<f64> {
+        let injection_time_cv = _find_cv_param(&self.metadata.scan_list.scans.first()?.cv_params, ION_INJECTION_TIME_CV_ACCESSION)?;
+        let injection_time = injection_time_cv.parse_value::<f64>()?;
+
+        match injection_time_cv.unit_accession.as_deref() {
+            Some(SECOND_UNIT_ACCESSION) => Some(injection_time * 1000.0),
+            Some(MINUTE_UNIT_ACCESSION) => Some(injection_time * 60_000.0),
+            _ => Some(injection_time),
+        }
+    }
+
+    /// Returns the FAIMS compensation voltage of the first scan in volts.
+    pub fn get_faims_compensation_voltage(&self) -> Option<f64> {
+        _find_cv_param(&self.metadata.scan_list.scans.first()?.cv_params, FAIMS_COMPENSATION_VOLTAGE_CV_ACCESSION)?.parse_value()
+    }
+
+    /// Returns the lower and upper limits of the first scan window.
+    pub fn get_scan_window(&self) -> Option<(f64, f64)> {
+        let scan_window = self.metadata.scan_list.scans.first()?.scan_window_list.scan_windows.first()?;
+        let lower_limit = _find_cv_param(&scan_window.cv_params, SCAN_WINDOW_LOWER_LIMIT_CV_ACCESSION)?.parse_value()?;
+        let upper_limit = _find_cv_param(&scan_window.cv_params, SCAN_WINDOW_UPPER_LIMIT_CV_ACCESSION)?.parse_value()?;
+
+        Some((lower_limit, upper_limit))
+    }
+
+    /// Returns the isolation window of the first precursor.
+    pub fn get_isolation_window(&self) -> Option<PrecursorIsolation> {
+        let isolation_window_cv_params = &self._get_first_precursor()?.isolation_window.as_ref()?.cv_params;
+
+        Some(PrecursorIsolation {
+            target_mz: _find_cv_param(isolation_window_cv_params, ISOLATION_WINDOW_TARGET_MZ_CV_ACCESSION)?.parse_value()?,
+            lower_offset: _find_cv_param(isolation_window_cv_params, ISOLATION_WINDOW_LOWER_OFFSET_CV_ACCESSION).and_then(|cvp| cvp.parse_value()),
+            upper_offset: _find_cv_param(isolation_window_cv_params, ISOLATION_WINDOW_UPPER_OFFSET_CV_ACCESSION).and_then(|cvp| cvp.parse_value()),
+        })
+    }
+
+    /// Returns the activation methods of the first precursor (e.g. ETD followed by a supplemental HCD activation).
+    pub fn get_activation_methods(&self) -> Vec<ActivationMethod> {
+        self._get_first_precursor()
+            .map(|prec| prec.activation.cv_params.iter().filter_map(|cvp| ActivationMethod::from_cv_accession(&cvp.accession)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the collision energy of the first precursor in electronvolts.
+    pub fn get_collision_energy(&self) -> Option<f64> {
+        _find_cv_param(&self._get_first_precursor()?.activation.cv_params, COLLISION_ENERGY_CV_ACCESSION)?.parse_value()
+    }
+
+    /// Returns the scan number of the master scan, i.e. the spectrum referenced by the first precursor.
+    pub fn get_master_scan_number(&self) -> Option<u32> {
+        self._get_first_precursor()?.spectrum_ref.split(' ').find_map(|ref_part| ref_part.strip_prefix("scan=")).and_then(|scan| scan.parse::<u32>().ok())
+    }
+
+    /// Returns the trailer extra records of the first scan (the "[Thermo Trailer Extra]" user params).
+    pub fn get_trailer_extra(&self) -> TrailerExtra {
+        self.metadata.scan_list.scans.first().map(|scan| TrailerExtra::from_user_params(&scan.user_params)).unwrap_or_default()
+    }
+
+    fn _get_first_precursor(&self) -> Option<&Precursor> {
+        self.metadata.precursor_list.as_ref()?.precursors.first()
+    }
+
+    fn _get_spectrum_cv_value(&self, accession: &str) -> Option<f64> {
+        _find_cv_param(&self.metadata.cv_params, accession)?.parse_value()
+    }
+}
+
+fn _find_cv_param<'a>(cv_params: &'a [CvParam], accession: &str) -> Option<&'a CvParam> {
+    cv_params.iter().find(|cvp| cvp.accession == accession)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Polarity {
+    /// Positive scan (MS:1000130)
+    Positive,
+    /// Negative scan (MS:1000129)
+    Negative,
+}
+
+impl Polarity {
+    pub fn from_cv_accession(accession: &str) -> Option<Self> {
+        match accession {
+            POSITIVE_SCAN_CV_ACCESSION => Some(Polarity::Positive),
+            NEGATIVE_SCAN_CV_ACCESSION => Some(Polarity::Negative),
+            _ => None,
+        }
+    }
+
+    pub fn get_cv_accession(&self) -> &'static str {
+        match self {
+            Polarity::Positive => POSITIVE_SCAN_CV_ACCESSION,
+            Polarity::Negative => NEGATIVE_SCAN_CV_ACCESSION,
+        }
+    }
+}
+
+/// Dissociation method of the precursor ions, as written by ThermoRawFileParser.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivationMethod {
+    /// Collision-induced dissociation (MS:1000133)
+    Cid,
+    /// Beam-type collision-induced dissociation (MS:1000422)
+    Hcd,
+    /// Electron transfer dissociation (MS:1000598)
+    Etd,
+    /// Electron capture dissociation (MS:1000250)
+    Ecd,
+    /// Pulsed q dissociation (MS:1000599)
+    Pqd,
+    /// Infrared multiphoton dissociation (MS:1000262)
+    Irmpd,
+    /// Ultraviolet photodissociation (MS:1003246)
+    Uvpd,
+    /// Supplemental beam-type collision-induced dissociation (MS:1002678), e.g. the HCD part of EThcD
+    SupplementalHcd,
+    /// Supplemental collision-induced dissociation (MS:1002679), e.g. the CID part of ETciD
+    SupplementalCid,
+}
+
+impl ActivationMethod {
+    pub fn from_cv_accession(accession: &str) -> Option<Self> {
+        match accession {
+            CID_CV_ACCESSION => Some(ActivationMethod::Cid),
+            HCD_CV_ACCESSION => Some(ActivationMethod::Hcd),
+            ETD_CV_ACCESSION => Some(ActivationMethod::Etd),
+            ECD_CV_ACCESSION => Some(ActivationMethod::Ecd),
+            PQD_CV_ACCESSION => Some(ActivationMethod::Pqd),
+            IRMPD_CV_ACCESSION => Some(ActivationMethod::Irmpd),
+            UVPD_CV_ACCESSION => Some(ActivationMethod::Uvpd),
+            SUPPLEMENTAL_HCD_CV_ACCESSION => Some(ActivationMethod::SupplementalHcd),
+            SUPPLEMENTAL_CID_CV_ACCESSION => Some(ActivationMethod::SupplementalCid),
+            _ => None,
+        }
+    }
+
+    pub fn get_cv_accession(&self) -> &'static str {
+        match self {
+            ActivationMethod::Cid => CID_CV_ACCESSION,
+            ActivationMethod::Hcd => HCD_CV_ACCESSION,
+            ActivationMethod::Etd => ETD_CV_ACCESSION,
+            ActivationMethod::Ecd => ECD_CV_ACCESSION,
+            ActivationMethod::Pqd => PQD_CV_ACCESSION,
+            ActivationMethod::Irmpd => IRMPD_CV_ACCESSION,
+            ActivationMethod::Uvpd => UVPD_CV_ACCESSION,
+            ActivationMethod::SupplementalHcd => SUPPLEMENTAL_HCD_CV_ACCESSION,
+            ActivationMethod::SupplementalCid => SUPPLEMENTAL_CID_CV_ACCESSION,
+        }
+    }
+
+    pub fn get_cv_name(&self) -> &'static str {
+        match self {
+            ActivationMethod::Cid => "collision-induced dissociation",
+            ActivationMethod::Hcd => "beam-type collision-induced dissociation",
+            ActivationMethod::Etd => "electron transfer dissociation",
+            ActivationMethod::Ecd => "electron capture dissociation",
+            ActivationMethod::Pqd => "pulsed q dissociation",
+            ActivationMethod::Irmpd => "infrared multiphoton dissociation",
+            ActivationMethod::Uvpd => "ultraviolet photodissociation",
+            ActivationMethod::SupplementalHcd => "supplemental beam-type collision-induced dissociation",
+            ActivationMethod::SupplementalCid => "supplemental collision-induced dissociation",
+        }
+    }
+}
+
+/// Isolation window of a precursor (m/z values).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrecursorIsolation {
+    pub target_mz: f64,
+    pub lower_offset: Option<f64>,
+    pub upper_offset: Option<f64>,
+}
+
+impl PrecursorIsolation {
+    /// Returns the isolated m/z range, the missing offsets being considered as null.
+    pub fn get_mz_range(&self) -> (f64, f64) {
+        (self.target_mz - self.lower_offset.unwrap_or(0.0), self.target_mz + self.upper_offset.unwrap_or(0.0))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpectrumRepresentation {
+    /// Centroid spectrum (MS:1000127)
+    Centroid,
+    /// Profile spectrum (MS:1000128)
+    Profile,
+}
+
+impl SpectrumRepresentation {
+    pub fn from_cv_accession(accession: &str) -> Option<Self> {
+        match accession {
+            CENTROID_SPECTRUM_CV_ACCESSION => Some(SpectrumRepresentation::Centroid),
+            PROFILE_SPECTRUM_CV_ACCESSION => Some(SpectrumRepresentation::Profile),
+            _ => None,
+        }
+    }
+
+    pub fn get_cv_accession(&self) -> &'static str {
+        match self {
+            SpectrumRepresentation::Centroid => CENTROID_SPECTRUM_CV_ACCESSION,
+            SpectrumRepresentation::Profile => PROFILE_SPECTRUM_CV_ACCESSION,
+        }
     }
 }
 
@@ -118,7 +380,7 @@ pub struct MzMLSpectrumMetaData {
     pub cv_params: Vec<CvParam>,
     #[serde(rename = "scanList")]
     pub scan_list: ScanList,
-    #[serde(rename = "precursorList")]
+    #[serde(rename = "precursorList", skip_serializing_if = "Option::is_none")]
     pub precursor_list: Option<PrecursorList>,
 }
 
@@ -170,7 +432,7 @@ pub struct PrecursorList {
 pub struct Precursor {
     #[serde(rename = "@spectrumRef")]
     pub spectrum_ref: String,
-    #[serde(rename = "isolationWindow")]
+    #[serde(rename = "isolationWindow", skip_serializing_if = "Option::is_none")]
     pub isolation_window: Option<IsolationWindow>,
     #[serde(rename = "selectedIonList")]
     pub selected_ion_list: SelectedIonList,
@@ -202,3 +464,21 @@ pub struct Activation {
     #[serde(rename = "cvParam", default)]
     pub cv_params: Vec<CvParam>,
 }
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BinaryDataArrayList {
+    #[serde(rename = "@count")]
+    pub count: String,
+    #[serde(rename = "binaryDataArray", default)]
+    pub binary_data_arrays: Vec<BinaryDataArray>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BinaryDataArray {
+    #[serde(rename = "@encodedLength")]
+    pub encoded_length: String,
+    #[serde(rename = "cvParam", default)]
+    pub cv_params: Vec<CvParam>,
+    /// Base64 encoded (and possibly compressed) values
+    pub binary: String,
+}
@@ -0,0 +1,429 @@
+use anyhow::*;
+use base64::Engine;
+use flate2::write::ZlibEncoder;
+use quick_xml::escape::escape;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::chromatogram::{Chromatogram, ChromatogramKind, MzMLChromatogram};
+use crate::error::StreamerError;
+use crate::mzml::*;
+use crate::mzml_spectrum::*;
+use crate::streamer::RawFileStreamer;
+
+const MZML_NAMESPACE: &str = "http://psi.hupo.org/ms/mzml";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const MZML_SCHEMA_LOCATION: &str = "http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.0.xsd";
+const INDEXED_MZML_SCHEMA_LOCATION: &str = "http://psi.hupo.org/ms/mzml http://psidev.info/files/ms/mzML/xsd/mzML1.1.2_idx.xsd";
+
+// Width of the zero-padded spectra count written by `MzMLWriter::write_spectra`, before being overwritten
+const SPECTRUM_COUNT_PLACEHOLDER_WIDTH: usize = 10;
+
+/// Precision of the values encoded in the binary data arrays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryPrecision {
+    Float32,
+    #[default]
+    Float64,
+}
+
+/// Writes an indexed mzML file (`indexedmzML` wrapper, spectrum and chromatogram offsets, SHA-1 checksum).
+///
+/// The writer can be used in a streaming way (`write_header`, then `write_spectrum` for each spectrum,
+/// then optionally `write_chromatograms`, and finally `finish`),
+/// or through `write_spectra` which allows the spectra to be transformed or filtered out between read and write.
+/// Since the spectra count is written in the header, it must be known before writing the first spectrum when the writer
+/// is used in a streaming way (`write_spectra` overwrites it once the spectra have been written, thus requires a seekable writer).
+pub struct MzMLWriter<W: Write> {
+    writer: DigestWriter<BufWriter<W>>,
+    zlib_compression: bool,
+    mz_precision: BinaryPrecision,
+    intensity_precision: BinaryPrecision,
+    declared_spectrum_count: Option<usize>,
+    default_data_processing_ref: String,
+    // Spectrum id and byte offset of each written spectrum
+    spectrum_offsets: Vec<(String, u64)>,
+    // Chromatogram id and byte offset of each written chromatogram (None until the chromatogram list is written)
+    chromatogram_offsets: Option<Vec<(String, u64)>>,
+}
+
+impl<W: Write> MzMLWriter<W> {
+    /// Creates a writer using zlib compression, 64-bit m/z values and 32-bit intensities.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: DigestWriter::new(BufWriter::new(writer)),
+            zlib_compression: true,
+            mz_precision: BinaryPrecision::Float64,
+            intensity_precision: BinaryPrecision::Float32,
+            declared_spectrum_count: None,
+            default_data_processing_ref: String::new(),
+            spectrum_offsets: Vec::new(),
+            chromatogram_offsets: None,
+        }
+    }
+
+    pub fn with_zlib_compression(mut self, zlib_compression: bool) -> Self {
+        self.zlib_compression = zlib_compression;
+        self
+    }
+
+    pub fn with_mz_precision(mut self, mz_precision: BinaryPrecision) -> Self {
+        self.mz_precision = mz_precision;
+        self
+    }
+
+    pub fn with_intensity_precision(mut self, intensity_precision: BinaryPrecision) -> Self {
+        self.intensity_precision = intensity_precision;
+        self
+    }
+
+    /// Writes all the spectra of the streamer, followed by its TIC chromatogram (as done by ThermoRawFileParser) and its SRM chromatograms if any.
+    pub fn write_streamer(self, streamer: &RawFileStreamer) -> Result<W, StreamerError> {
+        self._write_streamer(streamer).map_err(|e| StreamerError::export("mzML", e))
+    }
+
+    fn _write_streamer(mut self, streamer: &RawFileStreamer) -> Result<W> {
+        let spectra = streamer.spectra().into_iter();
+        self._write_header(streamer.get_metadata(), spectra.len())?;
+
+        for spectrum_res in spectra {
+            self._write_spectrum(&spectrum_res?)?;
+        }
+
+        let scan_entries = streamer._get_scan_entries()?;
+        let mut chromatograms = vec![Chromatogram::from_scan_entries(ChromatogramKind::TotalIonCurrent, None, scan_entries.iter())];
+        chromatograms.extend(streamer._get_srm_chromatograms(&scan_entries)?);
+        self._write_chromatograms(&chromatograms)?;
+
+        self._finish()
+    }
+
+    pub fn write_header(&mut self, metadata: &MzMLMetaData, spectrum_count: usize) -> Result<(), StreamerError> {
+        self._write_header(metadata, spectrum_count).map_err(|e| StreamerError::export("mzML", e))
+    }
+
+    pub fn write_spectrum(&mut self, spectrum: &MzMLSpectrum) -> Result<(), StreamerError> {
+        self._write_spectrum(spectrum).map_err(|e| StreamerError::export("mzML", e))
+    }
+
+    /// Closes the spectrum list and writes the chromatogram list, once all the spectra have been written.
+    pub fn write_chromatograms(&mut self, chromatograms: &[Chromatogram]) -> Result<(), StreamerError> {
+        self._write_chromatograms(chromatograms).map_err(|e| StreamerError::export("mzML", e))
+    }
+
+    /// Closes the spectrum list (if no chromatogram was written), writes the index and the file checksum, and returns the underlying writer.
+    pub fn finish(self) -> Result<W, StreamerError> {
+        self._finish().map_err(|e| StreamerError::export("mzML", e))
+    }
+
+    fn _write_header(&mut self, metadata: &MzMLMetaData, spectrum_count: usize) -> Result<()> {
+        self._write_padded_header(metadata, spectrum_count, 0)?;
+
+        Ok(())
+    }
+
+    // Writes the header with a spectra count zero-padded to the given width, and returns the digest state and the
+    // position just before the count
+    fn _write_padded_header(&mut self, metadata: &MzMLMetaData, spectrum_count: usize, count_width: usize) -> Result<(Sha1, u64)> {
+        if self.declared_spectrum_count.is_some() {
+            bail!("the mzML header has already been written");
+        }
+
+        let w = &mut self.writer;
+        writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(w, r#"<indexedmzML xmlns="{}" xmlns:xsi="{}" xsi:schemaLocation="{}">"#, MZML_NAMESPACE, XSI_NAMESPACE, INDEXED_MZML_SCHEMA_LOCATION)?;
+        writeln!(w, r#"<mzML xmlns="{}" xmlns:xsi="{}" xsi:schemaLocation="{}" id="{}" version="{}">"#,
+            MZML_NAMESPACE, XSI_NAMESPACE, MZML_SCHEMA_LOCATION, escape(&metadata.id), escape(&metadata.version))?;
+
+        _write_element(w, "cvList", &metadata.cv_list)?;
+        _write_element(w, "fileDescription", &metadata.file_description)?;
+        _write_element(w, "referenceableParamGroupList", &metadata.referenceable_param_group_list)?;
+        _write_element(w, "sampleList", &metadata.sample_list)?;
+        _write_element(w, "softwareList", &metadata.software_list)?;
+        _write_element(w, "instrumentConfigurationList", &metadata.instrument_configuration_list)?;
+        _write_element(w, "dataProcessingList", &metadata.data_processing_list)?;
+
+        // The run and spectrumList elements are left open, since they contain the spectra
+        let run = &metadata.run;
+        writeln!(w, r#"<run id="{}" defaultInstrumentConfigurationRef="{}" startTimeStamp="{}" defaultSourceFileRef="{}" sampleRef="{}">"#,
+            escape(&run.id), escape(&run.default_instrument_configuration_ref), escape(&run.start_time_stamp),
+            escape(&run.default_source_file_ref), escape(&run.sample_ref))?;
+
+        let default_data_processing_ref = metadata.data_processing_list.data_processings.first().map(|dp| dp.id.clone()).unwrap_or_default();
+        write!(w, r#"<spectrumList count=""#)?;
+        let count_digest_state = (w.sha1.clone(), w.position);
+        writeln!(w, r#"{:0width$}" defaultDataProcessingRef="{}">"#, spectrum_count, escape(&default_data_processing_ref), width = count_width)?;
+
+        self.declared_spectrum_count = Some(spectrum_count);
+        self.default_data_processing_ref = default_data_processing_ref;
+
+        Ok(count_digest_state)
+    }
+
+    fn _write_spectrum(&mut self, spectrum: &MzMLSpectrum) -> Result<()> {
+        let declared_spectrum_count = self.declared_spectrum_count.ok_or_else(|| anyhow!("the mzML header has to be written before the spectra"))?;
+        if self.spectrum_offsets.len() >= declared_spectrum_count {
+            bail!("can't write more spectra than declared in the mzML header ({})", declared_spectrum_count);
+        }
+        if self.chromatogram_offsets.is_some() {
+            bail!("the spectra have to be written before the chromatograms");
+        }
+
+        let spectrum_as_xml = self._spectrum_to_xml(spectrum, self.spectrum_offsets.len())?;
+        self.spectrum_offsets.push((spectrum.metadata.id.clone(), self.writer.position));
+        writeln!(self.writer, "{}", spectrum_as_xml)?;
+
+        Ok(())
+    }
+
+    fn _spectrum_to_xml(&self, spectrum: &MzMLSpectrum, index: usize) -> Result<String> {
+        let metadata = &spectrum.metadata;
+        let data = &spectrum.data;
+        if data.mz_list.len() != data.intensity_list.len() {
+            bail!("spectrum '{}' has {} m/z values but {} intensity values", metadata.id, data.mz_list.len(), data.intensity_list.len());
+        }
+
+        let binary_data_arrays = vec![
+            encode_binary_data_array(&data.mz_list, self.mz_precision, self.zlib_compression, new_cv_param(MZ_ARRAY_CV_ACCESSION, "m/z array", Some(("MS:1000040", "m/z"))))?,
+            encode_binary_data_array(&data.intensity_list, self.intensity_precision, self.zlib_compression, new_cv_param(INTENSITY_ARRAY_CV_ACCESSION, "intensity array", Some((NUMBER_OF_DETECTOR_COUNTS_UNIT_ACCESSION, "number of detector counts"))))?,
+        ];
+
+        // The spectra are re-indexed, since some of them may have been filtered out
+        let spectrum_element = SpectrumElement {
+            index,
+            id: &metadata.id,
+            default_array_length: data.mz_list.len(),
+            cv_params: &metadata.cv_params,
+            scan_list: &metadata.scan_list,
+            precursor_list: metadata.precursor_list.as_ref(),
+            binary_data_array_list: BinaryDataArrayList {
+                count: binary_data_arrays.len().to_string(),
+                binary_data_arrays,
+            },
+        };
+
+        Ok(quick_xml::se::to_string_with_root("spectrum", &spectrum_element)?)
+    }
+
+    fn _write_chromatograms(&mut self, chromatograms: &[Chromatogram]) -> Result<()> {
+        if self.chromatogram_offsets.is_some() {
+            bail!("the chromatogram list has already been written");
+        }
+        self._close_spectrum_list()?;
+
+        let mut chromatogram_offsets = Vec::with_capacity(chromatograms.len());
+        writeln!(self.writer, r#"<chromatogramList count="{}" defaultDataProcessingRef="{}">"#, chromatograms.len(), escape(&self.default_data_processing_ref))?;
+        for (index, chromatogram) in chromatograms.iter().enumerate() {
+            let mzml_chromatogram = MzMLChromatogram::from_chromatogram(chromatogram, index, self.zlib_compression, self.intensity_precision)?;
+            chromatogram_offsets.push((mzml_chromatogram.id.clone(), self.writer.position));
+            _write_element(&mut self.writer, "chromatogram", &mzml_chromatogram)?;
+        }
+        writeln!(self.writer, "</chromatogramList>")?;
+
+        self.chromatogram_offsets = Some(chromatogram_offsets);
+
+        Ok(())
+    }
+
+    fn _finish(mut self) -> Result<W> {
+        if self.chromatogram_offsets.is_none() {
+            self._close_spectrum_list()?;
+        }
+
+        let w = &mut self.writer;
+        writeln!(w, "</run>\n</mzML>")?;
+
+        let mut indices = vec![("spectrum", &self.spectrum_offsets)];
+        if let Some(chromatogram_offsets) = self.chromatogram_offsets.as_ref() {
+            indices.push(("chromatogram", chromatogram_offsets));
+        }
+
+        let index_list_offset = w.position;
+        writeln!(w, r#"<indexList count="{}">"#, indices.len())?;
+        for (index_name, offsets) in indices {
+            writeln!(w, r#"<index name="{}">"#, index_name)?;
+            for (id, offset) in offsets.iter() {
+                writeln!(w, r#"<offset idRef="{}">{}</offset>"#, escape(id), offset)?;
+            }
+            writeln!(w, "</index>")?;
+        }
+        writeln!(w, "</indexList>")?;
+        writeln!(w, "<indexListOffset>{}</indexListOffset>", index_list_offset)?;
+
+        // The checksum covers the file content up to and including the opening fileChecksum tag
+        write!(w, "<fileChecksum>")?;
+        let file_checksum = format!("{:x}", w.sha1.clone().finalize());
+        writeln!(w, "{}</fileChecksum>\n</indexedmzML>", file_checksum)?;
+
+        w.flush()?;
+
+        self.writer.inner.into_inner().map_err(|e| e.into_error()).context("can't flush the mzML writer")
+    }
+
+    fn _close_spectrum_list(&mut self) -> Result<()> {
+        let declared_spectrum_count = self.declared_spectrum_count.ok_or_else(|| anyhow!("the mzML header has not been written"))?;
+        if self.spectrum_offsets.len() != declared_spectrum_count {
+            bail!("{} spectra have been written while {} were declared in the mzML header", self.spectrum_offsets.len(), declared_spectrum_count);
+        }
+
+        writeln!(self.writer, "</spectrumList>")?;
+
+        Ok(())
+    }
+}
+
+impl<W: Read + Write + Seek> MzMLWriter<W> {
+    /// Writes the header, the given spectra after applying the transform function on each of them, and the index.
+    ///
+    /// The spectra for which the transform function returns `None` are not written. Since the spectra count is only known
+    /// at the end, a zero-padded count is written in the header, then overwritten once the spectra have been written.
+    /// The bytes following the count are then read back to compute the file checksum, thus a `File` has to be opened
+    /// for reading as well (e.g. with `OpenOptions::new().read(true).write(true).create(true).truncate(true)`).
+    pub fn write_spectra<I, F>(mut self, metadata: &MzMLMetaData, spectra: I, transform: F) -> Result<W, StreamerError>
+    where
+        I: Iterator<Item = Result<MzMLSpectrum, StreamerError>>,
+        F: FnMut(MzMLSpectrum) -> Result<Option<MzMLSpectrum>> {
+
+        self._write_spectra(metadata, spectra, transform).map_err(|e| StreamerError::export("mzML", e))?;
+
+        self.finish()
+    }
+
+    fn _write_spectra<I, F>(&mut self, metadata: &MzMLMetaData, spectra: I, mut transform: F) -> Result<()>
+    where
+        I: Iterator<Item = Result<MzMLSpectrum, StreamerError>>,
+        F: FnMut(MzMLSpectrum) -> Result<Option<MzMLSpectrum>> {
+
+        let (count_sha1, count_position) = self._write_padded_header(metadata, 0, SPECTRUM_COUNT_PLACEHOLDER_WIDTH)?;
+
+        for spectrum_res in spectra {
+            if let Some(spectrum) = transform(spectrum_res?)? {
+                let spectrum_as_xml = self._spectrum_to_xml(&spectrum, self.spectrum_offsets.len())?;
+                self.spectrum_offsets.push((spectrum.metadata.id.clone(), self.writer.position));
+                writeln!(self.writer, "{}", spectrum_as_xml)?;
+            }
+        }
+
+        let spectrum_count = self.spectrum_offsets.len();
+        let count_digits = format!("{:0width$}", spectrum_count, width = SPECTRUM_COUNT_PLACEHOLDER_WIDTH);
+        if count_digits.len() != SPECTRUM_COUNT_PLACEHOLDER_WIDTH {
+            bail!("too many spectra to write their count in the mzML header ({})", spectrum_count);
+        }
+
+        // Overwrite the count placeholder, then hash again the bytes following it
+        self.writer.flush()?;
+        let n_following_bytes = self.writer.position - count_position - SPECTRUM_COUNT_PLACEHOLDER_WIDTH as u64;
+        let inner_writer = self.writer.inner.get_mut();
+        inner_writer.seek(SeekFrom::Current(-((n_following_bytes + SPECTRUM_COUNT_PLACEHOLDER_WIDTH as u64) as i64)))?;
+        inner_writer.write_all(count_digits.as_bytes())?;
+
+        let mut sha1 = count_sha1;
+        sha1.update(count_digits.as_bytes());
+        let n_hashed_bytes = std::io::copy(&mut (&mut *inner_writer).take(n_following_bytes), &mut sha1)
+            .context("can't read back the written spectra to compute the file checksum")?;
+        if n_hashed_bytes != n_following_bytes {
+            bail!("can't read back the written spectra to compute the file checksum ({} bytes read instead of {})", n_hashed_bytes, n_following_bytes);
+        }
+
+        self.writer.sha1 = sha1;
+        self.declared_spectrum_count = Some(spectrum_count);
+
+        Ok(())
+    }
+}
+
+// Same content as MzMLSpectrumMetaData, followed by the binary data arrays
+#[derive(Serialize)]
+struct SpectrumElement<'a> {
+    #[serde(rename = "@index")]
+    index: usize,
+    #[serde(rename = "@id")]
+    id: &'a str,
+    #[serde(rename = "@defaultArrayLength")]
+    default_array_length: usize,
+    #[serde(rename = "cvParam")]
+    cv_params: &'a [CvParam],
+    #[serde(rename = "scanList")]
+    scan_list: &'a ScanList,
+    #[serde(rename = "precursorList", skip_serializing_if = "Option::is_none")]
+    precursor_list: Option<&'a PrecursorList>,
+    #[serde(rename = "binaryDataArrayList")]
+    binary_data_array_list: BinaryDataArrayList,
+}
+
+fn _write_element<T: Serialize, W: Write>(writer: &mut W, name: &str, value: &T) -> Result<()> {
+    let element_as_xml = quick_xml::se::to_string_with_root(name, value)?;
+    writeln!(writer, "{}", element_as_xml)?;
+    Ok(())
+}
+
+/// Encodes the values as a base64 binary data array, with the CV params describing the precision, compression and array type.
+pub(crate) fn encode_binary_data_array(values: &[f64], precision: BinaryPrecision, zlib_compression: bool, array_type_cv_param: CvParam) -> Result<BinaryDataArray> {
+    let bytes: Vec<u8> = match precision {
+        BinaryPrecision::Float32 => values.iter().flat_map(|v| (*v as f32).to_le_bytes()).collect(),
+        BinaryPrecision::Float64 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    };
+
+    let (bytes, compression_cv_param) = if zlib_compression {
+        let mut encoder = ZlibEncoder::new(Vec::with_capacity(bytes.len()), flate2::Compression::default());
+        encoder.write_all(&bytes)?;
+        (encoder.finish()?, new_cv_param(ZLIB_COMPRESSION_CV_ACCESSION, "zlib compression", None))
+    } else {
+        (bytes, new_cv_param(NO_COMPRESSION_CV_ACCESSION, "no compression", None))
+    };
+
+    let precision_cv_param = match precision {
+        BinaryPrecision::Float32 => new_cv_param(FLOAT_32_BIT_CV_ACCESSION, "32-bit float", None),
+        BinaryPrecision::Float64 => new_cv_param(FLOAT_64_BIT_CV_ACCESSION, "64-bit float", None),
+    };
+
+    let binary = base64::engine::general_purpose::STANDARD.encode(bytes);
+
+    Ok(BinaryDataArray {
+        encoded_length: binary.len().to_string(),
+        cv_params: vec![precision_cv_param, compression_cv_param, array_type_cv_param],
+        binary,
+    })
+}
+
+/// Creates a CV param without value, the CV references being deduced from the accession prefixes (e.g. "MS" or "UO").
+pub(crate) fn new_cv_param(accession: &str, name: &str, unit_opt: Option<(&str, &str)>) -> CvParam {
+    let cv_ref_of = |accession: &str| accession.split(':').next().unwrap_or_default().to_string();
+
+    CvParam {
+        cv_ref: cv_ref_of(accession),
+        accession: accession.to_string(),
+        name: name.to_string(),
+        value: Some(String::new()),
+        unit_cv_ref: unit_opt.map(|(unit_accession, _)| cv_ref_of(unit_accession)),
+        unit_accession: unit_opt.map(|(unit_accession, _)| unit_accession.to_string()),
+        unit_name: unit_opt.map(|(_, unit_name)| unit_name.to_string()),
+    }
+}
+
+// Keeps track of the number of written bytes (for the index offsets) and of their SHA-1 digest
+struct DigestWriter<W: Write> {
+    inner: W,
+    position: u64,
+    sha1: Sha1,
+}
+
+impl<W: Write> DigestWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, position: 0, sha1: Sha1::new() }
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n_written = self.inner.write(buf)?;
+        self.sha1.update(&buf[..n_written]);
+        self.position += n_written as u64;
+        std::io::Result::Ok(n_written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+use crate::bindings::*;
+use crate::mono::call_glue;
+use crate::mzml::*;
+use crate::mzml_spectrum::SpectrumRepresentation;
+
+/// Typed scan read directly from the .NET objects of the spectrum writer.
+///
+/// The fields follow the `PScan` class of ThermoRawFileParser. However the glue library only exposes `PScan`
+/// as a data holder (no function returns a populated instance), so the values are read from the `SpectrumType`
+/// object built by the writer.
+///
+/// Limitations:
+/// - the `Charges`, `Resolutions`, `Noises` and `Baselines` arrays of `PScan` are not available, since the
+///   `SpectrumType` object doesn't hold the centroid stream values and the glue doesn't expose them elsewhere
+/// - the spectrum is still built by the .NET writer (`WriteSpectrumNoReturn`), only the copy of the mzML spectrum XML
+///   and its parsing on the Rust side are skipped
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NativeScan {
+    pub scan_number: u32,
+    pub ms_order: u8,
+    /// Retention time in minutes
+    pub retention_time: f64,
+    pub tic: f64,
+    pub base_peak_mass: f64,
+    pub base_peak_intensity: f64,
+    /// Centroid or profile, depending on the peak picking options of the streamer
+    pub representation: Option<SpectrumRepresentation>,
+    /// Scan window lower limit
+    pub low_mass: f64,
+    /// Scan window upper limit
+    pub high_mass: f64,
+    pub filter_string: String,
+    pub precursor_mz: Option<f64>,
+    pub precursor_charge: Option<i8>,
+    pub masses: Vec<f64>,
+    pub intensities: Vec<f64>,
+}
+
+impl NativeScan {
+    /// Reads the spectrum last written by the writer owning the given spectrum wrapper.
+    pub(crate) unsafe fn from_spectrum_wrapper(
+        scan_number: u32,
+        spectrum_wrapper_ptr: *mut ThermoRawFileParser_Writer_SpectrumWrapper,
+        load_data: bool,
+    ) -> Result<Self> {
+        let spectrum = EmbedObject::new(call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_get_Spectrum(spectrum_wrapper_ptr))?)
+            .ok_or_else(|| anyhow!("no spectrum has been written for scan {}", scan_number))?;
+
+        let spectrum_values = _read_cv_param_values(&spectrum, &[
+            MS_LEVEL_CV_ACCESSION,
+            TOTAL_ION_CURRENT_CV_ACCESSION,
+            BASE_PEAK_MZ_CV_ACCESSION,
+            BASE_PEAK_INTENSITY_CV_ACCESSION,
+            CENTROID_SPECTRUM_CV_ACCESSION,
+            PROFILE_SPECTRUM_CV_ACCESSION,
+        ])?;
+
+        let first_scan_opt = match _get_embed_object(|| ThermoRawFileParser_Writer_MzML_SpectrumType_get_scanList(spectrum.0))? {
+            Some(scan_list) => _take_embed_array(|| ThermoRawFileParser_Writer_MzML_ScanListType_get_scan(scan_list.0))?.into_iter().next(),
+            None => None,
+        };
+
+        let precursor_opt = match _get_embed_object(|| ThermoRawFileParser_Writer_MzML_SpectrumType_get_precursorList(spectrum.0))? {
+            Some(precursor_list) => _take_embed_array(|| ThermoRawFileParser_Writer_MzML_PrecursorListType_get_precursor(precursor_list.0))?.into_iter().next(),
+            None => None,
+        };
+
+        let default_ms_order: u8 = if precursor_opt.is_some() {2} else {1};
+        let ms_order = _parse_value(&spectrum_values[0]).unwrap_or(default_ms_order);
+
+        let representation = if spectrum_values[4].is_some() {
+            Some(SpectrumRepresentation::Centroid)
+        } else if spectrum_values[5].is_some() {
+            Some(SpectrumRepresentation::Profile)
+        } else {
+            None
+        };
+
+        let mut scan = NativeScan {
+            scan_number,
+            ms_order,
+            tic: _parse_value(&spectrum_values[1]).unwrap_or(0.0),
+            base_peak_mass: _parse_value(&spectrum_values[2]).unwrap_or(0.0),
+            base_peak_intensity: _parse_value(&spectrum_values[3]).unwrap_or(0.0),
+            representation,
+            ..Default::default()
+        };
+
+        if let Some(first_scan) = first_scan_opt.as_ref() {
+            let scan_values = _read_cv_param_values(first_scan, &[SCAN_START_TIME_CV_ACCESSION, FILTER_STRING_CV_ACCESSION])?;
+            scan.retention_time = _parse_value(&scan_values[0]).unwrap_or(0.0);
+            scan.filter_string = scan_values[1].clone().unwrap_or_default();
+
+            let scan_window_opt = match _get_embed_object(|| ThermoRawFileParser_Writer_MzML_ScanType_get_scanWindowList(first_scan.0))? {
+                Some(scan_window_list) => {
+                    _take_embed_array(|| ThermoRawFileParser_Writer_MzML_ScanWindowListType_get_scanWindow(scan_window_list.0))?.into_iter().next()
+                }
+                None => None,
+            };
+            if let Some(scan_window) = scan_window_opt.as_ref() {
+                let window_values = _read_cv_param_values(scan_window, &[SCAN_WINDOW_LOWER_LIMIT_CV_ACCESSION, SCAN_WINDOW_UPPER_LIMIT_CV_ACCESSION])?;
+                scan.low_mass = _parse_value(&window_values[0]).unwrap_or(0.0);
+                scan.high_mass = _parse_value(&window_values[1]).unwrap_or(0.0);
+            }
+        }
+
+        // Same rules as MzMLSpectrum::get_precursor_mz_and_charge
+        if ms_order > 1 {
+            if let Some(precursor) = precursor_opt.as_ref() {
+                let first_selected_ion_opt = match _get_embed_object(|| ThermoRawFileParser_Writer_MzML_PrecursorType_get_selectedIonList(precursor.0))? {
+                    Some(selected_ion_list) => {
+                        _take_embed_array(|| ThermoRawFileParser_Writer_MzML_SelectedIonListType_get_selectedIon(selected_ion_list.0))?.into_iter().next()
+                    }
+                    None => None,
+                };
+                let selected_ion_values = match first_selected_ion_opt.as_ref() {
+                    Some(selected_ion) => _read_cv_param_values(selected_ion, &[CHARGE_STATE_CV_ACCESSION, SELECTED_ION_MZ_CV_ACCESSION])?,
+                    None => vec![None, None],
+                };
+
+                let monoisotopic_mz_value_opt = match first_scan_opt.as_ref() {
+                    Some(first_scan) => _read_user_param_value(first_scan, MONOISOTOPIC_MZ_USER_PARAM_NAME)?,
+                    None => None,
+                };
+                let monoisotopic_mz_opt = monoisotopic_mz_value_opt.and_then(|value| value.parse::<f64>().ok());
+
+                scan.precursor_charge = selected_ion_values[0].as_ref().map(|value| value.parse::<i8>().unwrap_or(0));
+                scan.precursor_mz = monoisotopic_mz_opt.or_else(|| _parse_value(&selected_ion_values[1]));
+            }
+        }
+
+        if load_data {
+            let (masses, intensities) = _copy_peaks(spectrum_wrapper_ptr)?;
+            scan.masses = masses;
+            scan.intensities = intensities;
+        }
+
+        Ok(scan)
+    }
+}
+
+/// Reads the MS level of the spectrum last written by the writer owning the given spectrum wrapper.
+pub(crate) unsafe fn read_ms_level(scan_number: u32, spectrum_wrapper_ptr: *mut ThermoRawFileParser_Writer_SpectrumWrapper) -> Result<u8> {
+    let spectrum = EmbedObject::new(call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_get_Spectrum(spectrum_wrapper_ptr))?)
+        .ok_or_else(|| anyhow!("no spectrum has been written for scan {}", scan_number))?;
+
+    let ms_level_opt = _parse_value(&_read_cv_param_values(&spectrum, &[MS_LEVEL_CV_ACCESSION])?[0]);
+
+    match ms_level_opt {
+        Some(ms_level) => Ok(ms_level),
+        None => {
+            let precursor_list_opt = _get_embed_object(|| ThermoRawFileParser_Writer_MzML_SpectrumType_get_precursorList(spectrum.0))?;
+            Ok(if precursor_list_opt.is_some() {2} else {1})
+        }
+    }
+}
+
+// Embeddinator object whose GC handle is released when dropped
+struct EmbedObject(*mut MonoEmbedObject);
+
+impl EmbedObject {
+    fn new(object_ptr: *mut MonoEmbedObject) -> Option<Self> {
+        if object_ptr.is_null() { None } else { Some(Self(object_ptr)) }
+    }
+}
+
+impl Drop for EmbedObject {
+    fn drop(&mut self) {
+        unsafe { mono_embeddinator_destroy_object(self.0) }
+    }
+}
+
+// Calls a glue getter returning an object, which is None if the getter returned a null pointer
+unsafe fn _get_embed_object(getter: impl FnOnce() -> *mut MonoEmbedObject) -> Result<Option<EmbedObject>> {
+    Ok(EmbedObject::new(call_glue(getter)?))
+}
+
+// Calls a glue getter returning an Embeddinator array, takes ownership of its objects, then frees the array itself
+unsafe fn _take_embed_array(getter: impl FnOnce() -> MonoEmbedArray) -> Result<Vec<EmbedObject>> {
+    let embed_array = call_glue(getter)?;
+    if embed_array.array.is_null() {
+        return Ok(vec![]);
+    }
+
+    let garray = &*embed_array.array;
+    let object_ptrs = std::slice::from_raw_parts(garray.data as *const *mut MonoEmbedObject, garray.len.max(0) as usize);
+    let objects = object_ptrs.iter().filter_map(|object_ptr| EmbedObject::new(*object_ptr)).collect();
+
+    g_array_free(embed_array.array, 1);
+
+    Ok(objects)
+}
+
+// Strings returned by the glue are allocated by mono_string_to_utf8 and must be released with mono_free
+pub(crate) unsafe fn _take_string(cstr: *const c_char) -> Option<String> {
+    if cstr.is_null() {
+        return None;
+    }
+
+    let string = CStr::from_ptr(cstr).to_string_lossy().into_owned();
+    mono_free(cstr as *mut c_void);
+
+    Some(string)
+}
+
+// Returns the values of the given accessions (in the same order), only reading the values of matching cvParams
+// Note: found cvParams without value are returned as empty strings
+unsafe fn _read_cv_param_values(param_group: &EmbedObject, accessions: &[&str]) -> Result<Vec<Option<String>>> {
+    let mut values = vec![None; accessions.len()];
+
+    for cv_param in _take_embed_array(|| ThermoRawFileParser_Writer_MzML_ParamGroupType_get_cvParam(param_group.0))? {
+        let accession_opt = _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_accession(cv_param.0))?);
+        let idx_opt = accession_opt.and_then(|accession| accessions.iter().position(|a| *a == accession));
+
+        if let Some(idx) = idx_opt {
+            if values[idx].is_none() {
+                values[idx] = Some(_take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_value(cv_param.0))?).unwrap_or_default());
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+unsafe fn _read_user_param_value(param_group: &EmbedObject, name: &str) -> Result<Option<String>> {
+    for user_param in _take_embed_array(|| ThermoRawFileParser_Writer_MzML_ParamGroupType_get_userParam(param_group.0))? {
+        if _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_UserParamType_get_name(user_param.0))?).as_deref() == Some(name) {
+            return Ok(_take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_UserParamType_get_value(user_param.0))?));
+        }
+    }
+
+    Ok(None)
+}
+
+fn _parse_value<T: std::str::FromStr>(value_opt: &Option<String>) -> Option<T> {
+    value_opt.as_ref().and_then(|value| value.parse::<T>().ok())
+}
+
+// Copies the peaks directly into the returned vectors
+unsafe fn _copy_peaks(spectrum_wrapper_ptr: *mut ThermoRawFileParser_Writer_SpectrumWrapper) -> Result<(Vec<f64>, Vec<f64>)> {
+    let peaks_count = call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_getPeaksCount(spectrum_wrapper_ptr))?.max(0) as usize;
+    if peaks_count == 0 {
+        return Ok((vec![], vec![]));
+    }
+
+    let mut masses: Vec<f64> = Vec::with_capacity(peaks_count);
+    let mut intensities: Vec<f64> = Vec::with_capacity(peaks_count);
+
+    call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_CopyDataToPointers(
+        spectrum_wrapper_ptr,
+        masses.as_mut_ptr() as usize as i64,
+        intensities.as_mut_ptr() as usize as i64,
+    ))?;
+
+    masses.set_len(peaks_count);
+    intensities.set_len(peaks_count);
+
+    Ok((masses, intensities))
+}
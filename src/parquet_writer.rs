@@ -0,0 +1,218 @@
+use anyhow::*;
+use arrow::array::{ArrayBuilder, ArrayRef, Float64Builder, UInt32Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::arrow_batches::{scan_fields, ScanColumnsBuilder};
+use crate::error::StreamerError;
+use crate::mzml_spectrum::MzMLSpectrum;
+use crate::scan_index::ScanIndexEntry;
+use crate::streamer::RawFileStreamer;
+
+// Number of rows of the record batches handed to the Parquet writers
+const BATCH_SIZE: usize = 65_536;
+
+/// Compression codec of the Parquet column chunks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParquetCompression {
+    Uncompressed,
+    #[default]
+    Snappy,
+    /// Zstandard with the given level (1 to 22)
+    Zstd(i32),
+}
+
+/// Numbers of rows written by a `ParquetExporter`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParquetExportSummary {
+    pub scan_count: usize,
+    pub peak_count: usize,
+}
+
+/// Exports the spectra of a RAW file as two Parquet tables, without going through the ThermoRawFileParser writers:
+/// - a long-format peaks table (`scan_number`, `mz`, `intensity`), see `peaks_schema`;
+/// - a scans table having one row per spectrum (`ScanIndexEntry` fields), see `scans_schema`.
+pub struct ParquetExporter {
+    row_group_size: usize,
+    compression: ParquetCompression,
+    ms_levels: Option<Vec<u8>>,
+}
+
+impl Default for ParquetExporter {
+    fn default() -> Self {
+        Self {
+            row_group_size: 1024 * 1024,
+            compression: ParquetCompression::default(),
+            ms_levels: None,
+        }
+    }
+}
+
+impl ParquetExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of rows per row group (applies to both tables).
+    pub fn with_row_group_size(mut self, row_group_size: usize) -> Self {
+        self.row_group_size = row_group_size;
+        self
+    }
+
+    pub fn with_compression(mut self, compression: ParquetCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Only exports the spectra of the given MS levels.
+    pub fn with_ms_levels(mut self, ms_levels: &[u8]) -> Self {
+        self.ms_levels = Some(ms_levels.to_vec());
+        self
+    }
+
+    pub fn write_files<P: AsRef<Path>>(&self, streamer: &RawFileStreamer, peaks_file_path: P, scans_file_path: P) -> Result<ParquetExportSummary, StreamerError> {
+        let peaks_file = File::create(peaks_file_path.as_ref())
+            .map_err(|e| StreamerError::io(format!("can't create peaks file '{}'", peaks_file_path.as_ref().display()), e))?;
+        let scans_file = File::create(scans_file_path.as_ref())
+            .map_err(|e| StreamerError::io(format!("can't create scans file '{}'", scans_file_path.as_ref().display()), e))?;
+
+        self.write_streamer(streamer, peaks_file, scans_file)
+    }
+
+    pub fn write_streamer<PW, SW>(&self, streamer: &RawFileStreamer, peaks_writer: PW, scans_writer: SW) -> Result<ParquetExportSummary, StreamerError>
+    where
+        PW: Write + Send,
+        SW: Write + Send {
+
+        let spectra = match self.ms_levels.as_ref() {
+            Some(ms_levels) => streamer.spectra().with_ms_levels(ms_levels),
+            None => streamer.spectra(),
+        };
+
+        self.write_spectra(spectra.into_iter(), peaks_writer, scans_writer)
+    }
+
+    /// Writes the given spectra (not filtered by MS level) and closes both Parquet files.
+    pub fn write_spectra<I, PW, SW>(&self, spectra: I, peaks_writer: PW, scans_writer: SW) -> Result<ParquetExportSummary, StreamerError>
+    where
+        I: Iterator<Item = Result<MzMLSpectrum, StreamerError>>,
+        PW: Write + Send,
+        SW: Write + Send {
+
+        self._write_spectra(spectra, peaks_writer, scans_writer).map_err(|e| StreamerError::export("Parquet", e))
+    }
+
+    fn _write_spectra<I, PW, SW>(&self, spectra: I, peaks_writer: PW, scans_writer: SW) -> Result<ParquetExportSummary>
+    where
+        I: Iterator<Item = Result<MzMLSpectrum, StreamerError>>,
+        PW: Write + Send,
+        SW: Write + Send {
+
+        if self.row_group_size == 0 {
+            bail!("the Parquet row group size must be greater than zero");
+        }
+
+        let mut peaks_writer = ArrowWriter::try_new(peaks_writer, peaks_schema(), Some(self._build_writer_properties()?))?;
+        let mut scans_writer = ArrowWriter::try_new(scans_writer, scans_schema(), Some(self._build_writer_properties()?))?;
+
+        let mut peak_table = PeakTableBuilder::default();
+        let mut scan_columns = ScanColumnsBuilder::default();
+        let mut summary = ParquetExportSummary::default();
+
+        for spectrum_res in spectra {
+            let spectrum = spectrum_res?;
+            let scan_number = spectrum.get_scan_number().ok_or_else(|| anyhow!("can't parse scan number of spectrum '{}'", spectrum.metadata.id))?;
+
+            peak_table.append_spectrum(scan_number, &spectrum)?;
+            scan_columns.append_entry(&ScanIndexEntry::from_spectrum(scan_number, &spectrum));
+
+            summary.scan_count += 1;
+            summary.peak_count += spectrum.data.mz_list.len();
+
+            if peak_table.len() >= BATCH_SIZE {
+                peaks_writer.write(&peak_table.finish()?)?;
+            }
+            if scan_columns.len() >= BATCH_SIZE {
+                scans_writer.write(&RecordBatch::try_new(scans_schema(), scan_columns.finish_columns())?)?;
+            }
+        }
+
+        peaks_writer.write(&peak_table.finish()?)?;
+        scans_writer.write(&RecordBatch::try_new(scans_schema(), scan_columns.finish_columns())?)?;
+
+        peaks_writer.close().context("can't close peaks Parquet file")?;
+        scans_writer.close().context("can't close scans Parquet file")?;
+
+        Ok(summary)
+    }
+
+    fn _build_writer_properties(&self) -> Result<WriterProperties> {
+        let compression = match self.compression {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Zstd(level) => Compression::ZSTD(ZstdLevel::try_new(level)?),
+        };
+
+        Ok(WriterProperties::builder()
+            .set_max_row_group_size(self.row_group_size)
+            .set_compression(compression)
+            .build())
+    }
+}
+
+/// Schema of the long-format peaks table.
+pub fn peaks_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("scan_number", DataType::UInt32, false),
+        Field::new("mz", DataType::Float64, false),
+        Field::new("intensity", DataType::Float64, false),
+    ]))
+}
+
+/// Schema of the scans table (one row per spectrum, retention times in minutes).
+pub fn scans_schema() -> SchemaRef {
+    Arc::new(Schema::new(scan_fields()))
+}
+
+#[derive(Default)]
+struct PeakTableBuilder {
+    scan_numbers: UInt32Builder,
+    mz_values: Float64Builder,
+    intensities: Float64Builder,
+}
+
+impl PeakTableBuilder {
+    fn len(&self) -> usize {
+        self.scan_numbers.len()
+    }
+
+    fn append_spectrum(&mut self, scan_number: u32, spectrum: &MzMLSpectrum) -> Result<()> {
+        let data = &spectrum.data;
+        if data.mz_list.len() != data.intensity_list.len() {
+            bail!("spectrum '{}' has {} m/z values but {} intensity values", spectrum.metadata.id, data.mz_list.len(), data.intensity_list.len());
+        }
+
+        self.scan_numbers.append_value_n(scan_number, data.mz_list.len());
+        self.mz_values.append_slice(&data.mz_list);
+        self.intensities.append_slice(&data.intensity_list);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.scan_numbers.finish()),
+            Arc::new(self.mz_values.finish()),
+            Arc::new(self.intensities.finish()),
+        ];
+
+        Ok(RecordBatch::try_new(peaks_schema(), columns)?)
+    }
+}
@@ -1,4 +1,26 @@
-pub use crate::mono::MONO_EMBEDDINATOR;
+#[cfg(feature = "arrow")]
+pub use crate::arrow_batches::{scan_fields, spectra_schema, spectra_to_record_batch, SpectrumBatchIterator};
+pub use crate::chromatogram::{Chromatogram, ChromatogramKind, ChromatogramList, ChromatogramPrecursor, ChromatogramProduct, MzMLChromatogram};
+pub use crate::discovery::{DiscoveryCandidate, DiscoveryReport, DiscoverySource, MonoDiscovery};
+pub use crate::error::StreamerError;
+pub use crate::host::{HandleSpectrumIterator, HandleSpectrumSelection, MonoHost, RawFileStreamerHandle};
+pub use crate::index_cache::{IndexCache, IndexCacheLocation, IndexCacheStatus, RawFileFingerprint};
+pub use crate::mgf::{parse_mgf, MgfEntry, MgfWriter, TitleFormat};
+pub use crate::mono::{MonoRuntime, MONO_EMBEDDINATOR};
+pub use crate::native_scan::NativeScan;
+#[cfg(feature = "parquet")]
+pub use crate::parquet_writer::{peaks_schema, scans_schema, ParquetCompression, ParquetExportSummary, ParquetExporter};
+pub use crate::raw_file_probe::{parse_raw_file_header, probe_raw_file, RawFileProbe};
+pub use crate::run_info::{RunInfo, SampleInfo};
+pub use crate::scan_filter::{FilterActivation, FilterAnalyzer, FilterFlag, FilterIonization, FilterPrecursor, FilterScanMode, ScanFilter};
+pub use crate::scan_index::{ScanIndex, ScanIndexEntry};
+pub use crate::spectrum_iterator::{SpectrumIterator, SpectrumSelection};
+pub use crate::srm::{parse_srm_filter, SrmTransition};
 pub use crate::streamer::RawFileStreamer;
+pub use crate::streamer_options::{LogFormat, PeakPicking, StreamerOptions, STREAMER_OPTIONS_DATA_PROCESSING_ID};
+pub use crate::trailer_extra::{TrailerExtra, TrailerValue};
+pub use crate::xic::{filter_matches, MzTolerance, XicRequest, XicTarget, DEFAULT_XIC_FILTER};
+pub use crate::xic_batch::{write_xic_summary, XicBatch, XicBatchResult, XicPeak, XicTargetRecord};
 pub use crate::mzml::*;
-pub use crate::mzml_spectrum::*;
\ No newline at end of file
+pub use crate::mzml_spectrum::*;
+pub use crate::mzml_writer::{BinaryPrecision, MzMLWriter};
\ No newline at end of file
@@ -0,0 +1,90 @@
+use serde::{Serialize, Deserialize};
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use path_absolutize::Absolutize;
+
+use crate::error::StreamerError;
+
+// Thermo RAW files start with the 0xA101 magic number, followed by "Finnigan" encoded in UTF-16LE
+const RAW_FILE_MAGIC: [u8; 2] = [0x01, 0xA1];
+const RAW_FILE_SIGNATURE: &str = "Finnigan";
+const RAW_FILE_VERSION_OFFSET: usize = 36;
+const RAW_FILE_CREATION_TIME_OFFSET: usize = 40;
+const RAW_FILE_HEADER_LEN: usize = 48;
+
+// Number of seconds between the Windows FILETIME epoch (1601-01-01) and the Unix epoch
+const FILETIME_UNIX_EPOCH_OFFSET_SECS: u64 = 11_644_473_600;
+
+/// Basic facts about a Thermo RAW file, read from its header without opening it with ThermoRawFileParser.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RawFileProbe {
+    /// Absolute path of the file
+    pub path: String,
+    pub file_size: u64,
+    /// Version of the RAW file format (e.g. 57 for LTQ FT files, 66 for recent Orbitrap files)
+    pub format_version: u32,
+    /// Acquisition start time recorded in the file header
+    pub creation_time: Option<SystemTime>,
+}
+
+/// Checks that the path refers to a readable Thermo RAW file, then returns the facts read from its header.
+///
+/// This is the pre-flight check performed by `RawFileStreamer::new`, see also `RawFileStreamer::probe`.
+pub fn probe_raw_file(raw_file_path: &str) -> Result<RawFileProbe, StreamerError> {
+    let abs_path = Path::new(raw_file_path).absolutize()
+        .map_err(|e| _not_readable(raw_file_path, e))?
+        .display()
+        .to_string();
+
+    let file_metadata = std::fs::metadata(&abs_path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => StreamerError::RawFileNotFound { path: abs_path.clone() },
+        _ => _not_readable(&abs_path, e),
+    })?;
+
+    if file_metadata.is_dir() {
+        return Err(StreamerError::RawFileIsDirectory { path: abs_path });
+    }
+
+    let mut header_bytes = Vec::with_capacity(RAW_FILE_HEADER_LEN);
+    std::fs::File::open(&abs_path)
+        .and_then(|file| file.take(RAW_FILE_HEADER_LEN as u64).read_to_end(&mut header_bytes))
+        .map_err(|e| _not_readable(&abs_path, e))?;
+
+    let (format_version, creation_time) = parse_raw_file_header(&header_bytes)
+        .ok_or_else(|| StreamerError::NotARawFile { path: abs_path.clone() })?;
+
+    Ok(RawFileProbe {
+        path: abs_path,
+        file_size: file_metadata.len(),
+        format_version,
+        creation_time,
+    })
+}
+
+/// Parses the beginning of a Thermo RAW file, returning its format version and its creation time,
+/// or None if the magic number or the signature don't match.
+pub fn parse_raw_file_header(header_bytes: &[u8]) -> Option<(u32, Option<SystemTime>)> {
+    if header_bytes.len() < RAW_FILE_HEADER_LEN || header_bytes[..2] != RAW_FILE_MAGIC {
+        return None;
+    }
+
+    let signature_bytes: Vec<u8> = RAW_FILE_SIGNATURE.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    if !header_bytes[2..].starts_with(&signature_bytes) {
+        return None;
+    }
+
+    let format_version = u32::from_le_bytes(header_bytes[RAW_FILE_VERSION_OFFSET..RAW_FILE_VERSION_OFFSET + 4].try_into().ok()?);
+    let filetime = u64::from_le_bytes(header_bytes[RAW_FILE_CREATION_TIME_OFFSET..RAW_FILE_CREATION_TIME_OFFSET + 8].try_into().ok()?);
+
+    // FILETIME values are expressed in 100-nanosecond intervals
+    let creation_time = (filetime / 10_000_000).checked_sub(FILETIME_UNIX_EPOCH_OFFSET_SECS).map(|unix_secs| {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(unix_secs) + Duration::from_nanos((filetime % 10_000_000) * 100)
+    });
+
+    Some((format_version, creation_time))
+}
+
+fn _not_readable(path: &str, error: std::io::Error) -> StreamerError {
+    StreamerError::RawFileNotReadable { path: path.to_string(), reason: error.to_string() }
+}
@@ -0,0 +1,186 @@
+//! Provenance information of a run (instrument, sample, operator and acquisition date).
+//!
+//! The values are merged from the mzML header written by ThermoRawFileParser and from its TXT metadata file,
+//! which is the only output exposing the operator, the instrument name and the software/firmware versions.
+//! The instrument methods are never read by ThermoRawFileParser, so their text isn't available through its glue.
+
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::ffi::CString;
+use std::path::Path;
+
+use crate::bindings::*;
+use crate::mono::call_glue;
+use crate::mzml::*;
+use crate::native_scan::_take_string;
+
+/// Sample information of the run, as entered in the acquisition sequence.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleInfo {
+    pub name: Option<String>,
+    pub id: Option<String>,
+    pub vial: Option<String>,
+    pub row: Option<String>,
+    pub volume: Option<f64>,
+    pub injection_volume: Option<f64>,
+    pub dilution_factor: Option<f64>,
+}
+
+/// Provenance information of a run, see `RawFileStreamer::get_run_info`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunInfo {
+    /// Start time stamp of the run (ISO 8601 if read from the mzML header)
+    pub acquisition_date: Option<String>,
+    /// Login of the user who acquired the file ("Created by" in the Thermo file header)
+    pub operator: Option<String>,
+    /// Instrument name declared in the RAW file (e.g. "Orbitrap Fusion Lumos")
+    pub instrument_name: Option<String>,
+    /// Instrument model term (e.g. MS:1002732), resolved from the instrument name by ThermoRawFileParser
+    pub instrument_model: Option<CvParam>,
+    pub instrument_serial_number: Option<String>,
+    pub software_version: Option<String>,
+    pub firmware_version: Option<String>,
+    pub sample: SampleInfo,
+}
+
+impl RunInfo {
+    /// Reads the values available in the mzML header (acquisition date, instrument model, serial number and sample).
+    pub fn from_mzml_metadata(meta_data: &MzMLMetaData) -> Self {
+        let instrument_params = meta_data.referenceable_param_group_list.referenceable_param_groups.first()
+            .map(|param_group| param_group.cv_params.as_slice())
+            .unwrap_or_default();
+        let sample_params = meta_data.sample_list.samples.first()
+            .map(|sample| sample.cv_params.as_slice())
+            .unwrap_or_default();
+
+        let acquisition_date = Some(meta_data.run.start_time_stamp.clone()).filter(|time_stamp| !time_stamp.is_empty());
+
+        Self {
+            acquisition_date,
+            // ThermoRawFileParser writes the model term first, followed by the serial number
+            instrument_model: instrument_params.iter().find(|cvp| cvp.accession != INSTRUMENT_SERIAL_NUMBER_CV_ACCESSION).cloned(),
+            instrument_serial_number: _find_cv_value(instrument_params, INSTRUMENT_SERIAL_NUMBER_CV_ACCESSION),
+            sample: SampleInfo {
+                name: _find_cv_value(sample_params, SAMPLE_NAME_CV_ACCESSION),
+                id: None,
+                vial: _find_cv_value(sample_params, SAMPLE_VIAL_CV_ACCESSION),
+                row: _find_cv_value(sample_params, SAMPLE_ROW_CV_ACCESSION),
+                volume: _find_cv_value(sample_params, SAMPLE_VOLUME_CV_ACCESSION).and_then(|v| v.parse().ok()),
+                injection_volume: _find_cv_value(sample_params, INJECTION_VOLUME_CV_ACCESSION).and_then(|v| v.parse().ok()),
+                dilution_factor: _find_cv_value(sample_params, DILUTION_FACTOR_CV_ACCESSION).and_then(|v| v.parse().ok()),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Completes the missing values with the ones of a ThermoRawFileParser TXT metadata file.
+    ///
+    /// The lines are "key=value" pairs, the values of some keys being formatted as "[cvRef, accession, name, value]".
+    pub fn with_thermo_metadata(mut self, metadata_txt: &str) -> Self {
+        for line in metadata_txt.lines().filter(|line| !line.starts_with('#')) {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value = _strip_cv_term(value.trim());
+            if value.is_empty() {
+                continue;
+            }
+
+            let value_opt = Some(value.to_string());
+            match key.trim() {
+                "Creation date" => _fill(&mut self.acquisition_date, value_opt),
+                "Created by" => _fill(&mut self.operator, value_opt),
+                "Instrument name" => _fill(&mut self.instrument_name, value_opt),
+                "Instrument serial number" => _fill(&mut self.instrument_serial_number, value_opt),
+                "Software version" => _fill(&mut self.software_version, value_opt),
+                "Firmware version" => _fill(&mut self.firmware_version, value_opt),
+                "Sample name" => _fill(&mut self.sample.name, value_opt),
+                "Sample id" => _fill(&mut self.sample.id, value_opt),
+                "Sample vial" => _fill(&mut self.sample.vial, value_opt),
+                "Sample row number" => _fill(&mut self.sample.row, value_opt),
+                "Sample volume" => _fill(&mut self.sample.volume, value.parse().ok()),
+                "Sample injection volume" => _fill(&mut self.sample.injection_volume, value.parse().ok()),
+                "Sample dilution factor" => _fill(&mut self.sample.dilution_factor, value.parse().ok()),
+                _ => {}
+            }
+        }
+
+        self
+    }
+
+    pub fn get_instrument_model_name(&self) -> Option<&str> {
+        self.instrument_model.as_ref().map(|cvp| cvp.name.as_str())
+    }
+}
+
+// Writes the ThermoRawFileParser TXT metadata file of a RAW file in a new temporary directory, then returns its content
+// (the directory being unique to each call, the same RAW file can be read from several threads)
+pub(crate) unsafe fn _read_thermo_metadata(raw_file_path: &str) -> Result<String> {
+    let output_dir = tempfile::Builder::new().prefix("thermo-metadata-").tempdir()
+        .context("can't create the temporary directory of the ThermoRawFileParser metadata file")?;
+    let file_stem = Path::new(raw_file_path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let metadata_file_path = output_dir.path().join(format!("{}-metadata.txt", file_stem));
+
+    let raw_file_path_cstr = CString::new(raw_file_path)?;
+    let output_dir_cstr = CString::new(output_dir.path().display().to_string())?;
+    let metadata_file_path_cstr = CString::new(metadata_file_path.display().to_string())?;
+
+    let parse_input = call_glue(|| ThermoRawFileParser_ParseInput_new_1(
+        raw_file_path_cstr.as_ptr(),
+        std::ptr::null(),
+        output_dir_cstr.as_ptr(),
+        ThermoRawFileParser_OutputFormat_ThermoRawFileParser_OutputFormat_NONE,
+    ))?;
+
+    let parse_res = call_glue(|| {
+        ThermoRawFileParser_ParseInput_set_MetadataFormat(parse_input, ThermoRawFileParser_MetadataFormat_ThermoRawFileParser_MetadataFormat_TXT)
+    }).and_then(|_| call_glue(|| {
+        ThermoRawFileParser_ParseInput_set_MetadataOutputFile(parse_input, metadata_file_path_cstr.as_ptr())
+    })).and_then(|_| call_glue(|| ThermoRawFileParser_RawFileParser_Parse(parse_input)));
+    mono_embeddinator_destroy_object(parse_input);
+    parse_res.context("can't write the ThermoRawFileParser metadata file")?;
+
+    let metadata_txt = std::fs::read_to_string(&metadata_file_path)
+        .with_context(|| format!("can't read the metadata file '{}' written by ThermoRawFileParser", metadata_file_path.display()))?;
+
+    Ok(metadata_txt)
+}
+
+// Maps an instrument name to its PSI-MS model term, using the ontology mapping of ThermoRawFileParser
+pub(crate) unsafe fn _resolve_instrument_model(instrument_name: &str) -> Result<Option<CvParam>> {
+    let instrument_name_cstr = CString::new(instrument_name)?;
+    let cv_param_ptr = call_glue(|| ThermoRawFileParser_Writer_OntologyMapping_getInstrumentModel(instrument_name_cstr.as_ptr()))?;
+    if cv_param_ptr.is_null() {
+        return Ok(None);
+    }
+
+    let cv_param_res = _read_cv_param(cv_param_ptr);
+    mono_embeddinator_destroy_object(cv_param_ptr);
+
+    Ok(Some(cv_param_res?))
+}
+
+unsafe fn _read_cv_param(cv_param_ptr: *mut ThermoRawFileParser_Writer_MzML_CVParamType) -> Result<CvParam> {
+    Ok(CvParam {
+        cv_ref: _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_cvRef(cv_param_ptr))?).unwrap_or_default(),
+        accession: _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_accession(cv_param_ptr))?).unwrap_or_default(),
+        name: _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_name(cv_param_ptr))?).unwrap_or_default(),
+        value: _take_string(call_glue(|| ThermoRawFileParser_Writer_MzML_CVParamType_get_value(cv_param_ptr))?),
+        ..Default::default()
+    })
+}
+
+fn _find_cv_value(cv_params: &[CvParam], accession: &str) -> Option<String> {
+    cv_params.iter().find(|cvp| cvp.accession == accession)?.value.clone().filter(|value| !value.is_empty())
+}
+
+fn _strip_cv_term(value: &str) -> &str {
+    match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        Some(cv_term) => cv_term.splitn(4, ", ").nth(3).unwrap_or_default().trim(),
+        None => value,
+    }
+}
+
+fn _fill<T>(field: &mut Option<T>, value_opt: Option<T>) {
+    if field.is_none() {
+        *field = value_opt;
+    }
+}
@@ -0,0 +1,543 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::mzml_spectrum::{ActivationMethod, Polarity, SpectrumRepresentation};
+
+/// Mass analyzer of a scan filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterAnalyzer {
+    /// Ion trap (ITMS)
+    IonTrap,
+    /// Orbitrap or FT-ICR (FTMS)
+    Fourier,
+    /// Time of flight (TOFMS)
+    TimeOfFlight,
+    /// Single quadrupole (SQMS)
+    SingleQuadrupole,
+    /// Triple quadrupole (TQMS)
+    TripleQuadrupole,
+    /// Magnetic sector (SECTOR)
+    Sector,
+    /// Astral (ASTMS)
+    Astral,
+}
+
+impl FilterAnalyzer {
+    const CODES: [(&'static str, FilterAnalyzer); 7] = [
+        ("ITMS", FilterAnalyzer::IonTrap),
+        ("FTMS", FilterAnalyzer::Fourier),
+        ("TOFMS", FilterAnalyzer::TimeOfFlight),
+        ("SQMS", FilterAnalyzer::SingleQuadrupole),
+        ("TQMS", FilterAnalyzer::TripleQuadrupole),
+        ("SECTOR", FilterAnalyzer::Sector),
+        ("ASTMS", FilterAnalyzer::Astral),
+    ];
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::CODES.iter().find(|(c, _)| *c == code).map(|(_, analyzer)| *analyzer)
+    }
+
+    pub fn get_code(&self) -> &'static str {
+        Self::CODES.iter().find(|(_, analyzer)| analyzer == self).map(|(c, _)| *c).unwrap()
+    }
+}
+
+/// Ionization source of a scan filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterIonization {
+    Ei,
+    Ci,
+    Fab,
+    Esi,
+    Nsi,
+    Apci,
+    Appi,
+    Tsp,
+    Fd,
+    Maldi,
+    Gd,
+}
+
+impl FilterIonization {
+    const CODES: [(&'static str, FilterIonization); 11] = [
+        ("EI", FilterIonization::Ei),
+        ("CI", FilterIonization::Ci),
+        ("FAB", FilterIonization::Fab),
+        ("ESI", FilterIonization::Esi),
+        ("NSI", FilterIonization::Nsi),
+        ("APCI", FilterIonization::Apci),
+        ("APPI", FilterIonization::Appi),
+        ("TSP", FilterIonization::Tsp),
+        ("FD", FilterIonization::Fd),
+        ("MALDI", FilterIonization::Maldi),
+        ("GD", FilterIonization::Gd),
+    ];
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::CODES.iter().find(|(c, _)| *c == code).map(|(_, ionization)| *ionization)
+    }
+
+    pub fn get_code(&self) -> &'static str {
+        Self::CODES.iter().find(|(_, ionization)| ionization == self).map(|(c, _)| *c).unwrap()
+    }
+}
+
+/// Scan mode of a scan filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterScanMode {
+    /// Full scan (Full)
+    Full,
+    /// Selected ion monitoring (SIM)
+    Sim,
+    /// Selected reaction monitoring (SRM)
+    Srm,
+    /// Consecutive reaction monitoring (CRM)
+    Crm,
+    /// Zoom scan (Z)
+    Zoom,
+    /// First quadrupole scan (Q1MS)
+    Q1Ms,
+    /// Third quadrupole scan (Q3MS)
+    Q3Ms,
+}
+
+impl FilterScanMode {
+    const CODES: [(&'static str, FilterScanMode); 7] = [
+        ("Full", FilterScanMode::Full),
+        ("SIM", FilterScanMode::Sim),
+        ("SRM", FilterScanMode::Srm),
+        ("CRM", FilterScanMode::Crm),
+        ("Z", FilterScanMode::Zoom),
+        ("Q1MS", FilterScanMode::Q1Ms),
+        ("Q3MS", FilterScanMode::Q3Ms),
+    ];
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::CODES.iter().find(|(c, _)| *c == code).map(|(_, scan_mode)| *scan_mode)
+    }
+
+    pub fn get_code(&self) -> &'static str {
+        Self::CODES.iter().find(|(_, scan_mode)| scan_mode == self).map(|(c, _)| *c).unwrap()
+    }
+}
+
+/// Flag located between the ionization and the scan mode of a scan filter.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FilterFlag {
+    /// Data dependent scan (d)
+    Dependent,
+    /// Supplemental activation (sa), e.g. the HCD part of EThcD
+    SupplementalActivation,
+    /// In-source CID energy (sid=)
+    SourceCidEnergy(f64),
+    /// FAIMS compensation voltage (cv=)
+    CompensationVoltage(f64),
+    /// Any other flag, e.g. a scan rate ("r", "t", "e", ...) or a negated flag ("!corona")
+    Other(String),
+}
+
+impl FilterFlag {
+    fn parse(token: &str) -> Result<Self> {
+        Ok(match token {
+            "d" => FilterFlag::Dependent,
+            "sa" => FilterFlag::SupplementalActivation,
+            _ => {
+                if let Some(energy) = token.strip_prefix("sid=") {
+                    FilterFlag::SourceCidEnergy(energy.parse().with_context(|| format!("invalid source CID energy '{}'", token))?)
+                } else if let Some(voltage) = token.strip_prefix("cv=") {
+                    FilterFlag::CompensationVoltage(voltage.parse().with_context(|| format!("invalid compensation voltage '{}'", token))?)
+                } else {
+                    FilterFlag::Other(token.to_string())
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for FilterFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterFlag::Dependent => f.write_str("d"),
+            FilterFlag::SupplementalActivation => f.write_str("sa"),
+            FilterFlag::SourceCidEnergy(energy) => write!(f, "sid={:.2}", energy),
+            FilterFlag::CompensationVoltage(voltage) => write!(f, "cv={:.2}", voltage),
+            FilterFlag::Other(token) => f.write_str(token),
+        }
+    }
+}
+
+/// Activation of a precursor, e.g. "cid35.00".
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterActivation {
+    pub method: ActivationMethod,
+    pub energy: f64,
+}
+
+impl FilterActivation {
+    const CODES: [(&'static str, ActivationMethod); 7] = [
+        ("cid", ActivationMethod::Cid),
+        ("hcd", ActivationMethod::Hcd),
+        ("etd", ActivationMethod::Etd),
+        ("ecd", ActivationMethod::Ecd),
+        ("pqd", ActivationMethod::Pqd),
+        ("mpd", ActivationMethod::Irmpd),
+        ("uvpd", ActivationMethod::Uvpd),
+    ];
+
+    fn parse(activation_str: &str) -> Result<Self> {
+        let code_len = activation_str.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(activation_str.len());
+        let (code, energy_str) = activation_str.split_at(code_len);
+
+        Ok(Self {
+            method: Self::CODES.iter().find(|(c, _)| *c == code).map(|(_, method)| *method)
+                .ok_or_else(|| anyhow!("unknown activation '{}'", code))?,
+            energy: energy_str.parse().with_context(|| format!("invalid activation energy '{}'", activation_str))?,
+        })
+    }
+
+    // Note: the filter string doesn't distinguish the supplemental activations, which are identified by their position
+    fn get_code(&self) -> &'static str {
+        match self.method {
+            ActivationMethod::Cid | ActivationMethod::SupplementalCid => "cid",
+            ActivationMethod::Hcd | ActivationMethod::SupplementalHcd => "hcd",
+            ActivationMethod::Etd => "etd",
+            ActivationMethod::Ecd => "ecd",
+            ActivationMethod::Pqd => "pqd",
+            ActivationMethod::Irmpd => "mpd",
+            ActivationMethod::Uvpd => "uvpd",
+        }
+    }
+}
+
+/// Precursor of a MSn scan filter, e.g. "523.77@etd100.00@hcd25.00".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FilterPrecursor {
+    pub mz: f64,
+    /// Activations in the order of the filter (the supplemental activation following the main one)
+    pub activations: Vec<FilterActivation>,
+}
+
+/// Structured Thermo scan filter (the "filter string" MS:1000512 of the spectra).
+///
+/// Parsing then formatting a filter gives back the original string, the number of decimals of the precursor m/z
+/// values and of the mass ranges being recorded while parsing (the energies and voltages being always written with
+/// 2 decimals, as done by Thermo).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScanFilter {
+    pub analyzer: Option<FilterAnalyzer>,
+    /// Segment and scan event numbers, e.g. "{1,2}"
+    pub segment_and_event: Option<(u32, u32)>,
+    pub polarity: Option<Polarity>,
+    pub data_type: Option<SpectrumRepresentation>,
+    pub ionization: Option<FilterIonization>,
+    pub flags: Vec<FilterFlag>,
+    pub scan_mode: Option<FilterScanMode>,
+    /// Lock mass correction (lock)
+    pub lock: bool,
+    /// Multiplexed scan (msx)
+    pub multiplex: bool,
+    /// MS order (1 for "ms", n for "msn"), None if not defined
+    pub ms_order: Option<u8>,
+    pub precursors: Vec<FilterPrecursor>,
+    pub mass_ranges: Vec<(f64, f64)>,
+    /// Number of decimals of the formatted precursor m/z values (2 by default)
+    pub precursor_mz_decimals: usize,
+    /// Number of decimals of the formatted mass ranges (2 by default)
+    pub mass_range_decimals: usize,
+}
+
+impl Default for ScanFilter {
+    fn default() -> Self {
+        Self {
+            analyzer: None,
+            segment_and_event: None,
+            polarity: None,
+            data_type: None,
+            ionization: None,
+            flags: vec![],
+            scan_mode: None,
+            lock: false,
+            multiplex: false,
+            ms_order: None,
+            precursors: vec![],
+            mass_ranges: vec![],
+            precursor_mz_decimals: 2,
+            mass_range_decimals: 2,
+        }
+    }
+}
+
+impl ScanFilter {
+    pub fn is_dependent(&self) -> bool {
+        self.flags.contains(&FilterFlag::Dependent)
+    }
+
+    pub fn has_supplemental_activation(&self) -> bool {
+        self.flags.contains(&FilterFlag::SupplementalActivation)
+    }
+
+    pub fn get_compensation_voltage(&self) -> Option<f64> {
+        self.flags.iter().find_map(|flag| match flag {
+            FilterFlag::CompensationVoltage(voltage) => Some(*voltage),
+            _ => None,
+        })
+    }
+
+    pub fn get_source_cid_energy(&self) -> Option<f64> {
+        self.flags.iter().find_map(|flag| match flag {
+            FilterFlag::SourceCidEnergy(energy) => Some(*energy),
+            _ => None,
+        })
+    }
+
+    /// Returns the m/z of the last precursor, i.e. the one isolated for this scan.
+    pub fn get_precursor_mz(&self) -> Option<f64> {
+        self.precursors.last().map(|precursor| precursor.mz)
+    }
+
+    /// Returns true if the filter of a scan has all the fields defined by this (possibly partial) filter.
+    ///
+    /// The undefined fields match any value, e.g. "ms2" matches all the MS2 scans whatever their precursor, while
+    /// "!d" matches the scans without the dependent flag. The precursor m/z values and the mass ranges are compared
+    /// with the precision of this filter (e.g. "445.1" matches "445.12", but "445.13" doesn't), as well as the activation
+    /// energies when defined.
+    pub fn matches(&self, scan_filter: &ScanFilter) -> bool {
+        fn _matches_field<T: PartialEq>(field: &Option<T>, scan_field: &Option<T>) -> bool {
+            field.is_none() || field == scan_field
+        }
+
+        _matches_field(&self.analyzer, &scan_filter.analyzer)
+            && _matches_field(&self.segment_and_event, &scan_filter.segment_and_event)
+            && _matches_field(&self.polarity, &scan_filter.polarity)
+            && _matches_field(&self.data_type, &scan_filter.data_type)
+            && _matches_field(&self.ionization, &scan_filter.ionization)
+            && _matches_field(&self.scan_mode, &scan_filter.scan_mode)
+            && _matches_field(&self.ms_order, &scan_filter.ms_order)
+            && (!self.lock || scan_filter.lock)
+            && (!self.multiplex || scan_filter.multiplex)
+            && self.flags.iter().all(|flag| _matches_flag(flag, &scan_filter.flags))
+            && self._matches_precursors(scan_filter)
+            && self._matches_mass_ranges(scan_filter)
+    }
+
+    fn _matches_precursors(&self, scan_filter: &ScanFilter) -> bool {
+        let mz_tolerance = _get_precision_tolerance(self.precursor_mz_decimals);
+
+        self.precursors.len() <= scan_filter.precursors.len() && self.precursors.iter().zip(scan_filter.precursors.iter())
+            .all(|(precursor, scan_precursor)| {
+                (precursor.mz - scan_precursor.mz).abs() < mz_tolerance
+                    && (precursor.activations.is_empty() || (precursor.activations.len() == scan_precursor.activations.len()
+                        && precursor.activations.iter().zip(scan_precursor.activations.iter()).all(|(activation, scan_activation)| {
+                            activation.get_code() == scan_activation.get_code()
+                                && (activation.energy - scan_activation.energy).abs() < _get_precision_tolerance(2)
+                        })))
+            })
+    }
+
+    fn _matches_mass_ranges(&self, scan_filter: &ScanFilter) -> bool {
+        let mass_tolerance = _get_precision_tolerance(self.mass_range_decimals);
+
+        self.mass_ranges.is_empty() || (self.mass_ranges.len() == scan_filter.mass_ranges.len()
+            && self.mass_ranges.iter().zip(scan_filter.mass_ranges.iter()).all(|((start, end), (scan_start, scan_end))| {
+                (start - scan_start).abs() < mass_tolerance && (end - scan_end).abs() < mass_tolerance
+            }))
+    }
+}
+
+impl FromStr for ScanFilter {
+    type Err = Error;
+
+    fn from_str(filter_string: &str) -> Result<Self> {
+        _parse_scan_filter(filter_string).with_context(|| format!("invalid scan filter '{}'", filter_string))
+    }
+}
+
+impl fmt::Display for ScanFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens: Vec<String> = Vec::new();
+
+        if let Some(analyzer) = self.analyzer {
+            tokens.push(analyzer.get_code().to_string());
+        }
+        if let Some((segment, event)) = self.segment_and_event {
+            tokens.push(format!("{{{},{}}}", segment, event));
+        }
+        if let Some(polarity) = self.polarity {
+            tokens.push(match polarity {
+                Polarity::Positive => "+",
+                Polarity::Negative => "-",
+            }.to_string());
+        }
+        if let Some(data_type) = self.data_type {
+            tokens.push(match data_type {
+                SpectrumRepresentation::Profile => "p",
+                SpectrumRepresentation::Centroid => "c",
+            }.to_string());
+        }
+        if let Some(ionization) = self.ionization {
+            tokens.push(ionization.get_code().to_string());
+        }
+        tokens.extend(self.flags.iter().map(|flag| flag.to_string()));
+        if let Some(scan_mode) = self.scan_mode {
+            tokens.push(scan_mode.get_code().to_string());
+        }
+        if self.lock {
+            tokens.push("lock".to_string());
+        }
+        if self.multiplex {
+            tokens.push("msx".to_string());
+        }
+        match self.ms_order {
+            Some(1) => tokens.push("ms".to_string()),
+            Some(ms_order) => tokens.push(format!("ms{}", ms_order)),
+            None => {}
+        }
+        for precursor in self.precursors.iter() {
+            let mut precursor_str = format!("{:.*}", self.precursor_mz_decimals, precursor.mz);
+            for activation in precursor.activations.iter() {
+                precursor_str += &format!("@{}{:.2}", activation.get_code(), activation.energy);
+            }
+            tokens.push(precursor_str);
+        }
+
+        if !self.mass_ranges.is_empty() {
+            let mass_ranges_as_strings: Vec<String> = self.mass_ranges.iter().map(|(start, end)| {
+                if start == end {
+                    format!("{:.*}", self.mass_range_decimals, start)
+                } else {
+                    format!("{:.*}-{:.*}", self.mass_range_decimals, start, self.mass_range_decimals, end)
+                }
+            }).collect();
+            tokens.push(format!("[{}]", mass_ranges_as_strings.join(", ")));
+        }
+
+        f.write_str(&tokens.join(" "))
+    }
+}
+
+fn _parse_scan_filter(filter_string: &str) -> Result<ScanFilter> {
+    let mut scan_filter = ScanFilter::default();
+
+    let (head, mass_ranges_str_opt) = match filter_string.split_once('[') {
+        Some((head, tail)) => {
+            let mass_ranges_str = tail.trim_end().strip_suffix(']').ok_or_else(|| anyhow!("unclosed mass ranges"))?;
+            (head, Some(mass_ranges_str))
+        }
+        None => (filter_string, None),
+    };
+
+    let mut tokens = head.split_whitespace().peekable();
+
+    if let Some(analyzer) = tokens.peek().and_then(|t| FilterAnalyzer::from_code(t)) {
+        scan_filter.analyzer = Some(analyzer);
+        tokens.next();
+    }
+    if let Some(segment_and_event_str) = tokens.peek().and_then(|t| t.strip_prefix('{')).and_then(|t| t.strip_suffix('}')) {
+        let (segment_str, event_str) = segment_and_event_str.split_once(',').ok_or_else(|| anyhow!("invalid segment and scan event"))?;
+        scan_filter.segment_and_event = Some((segment_str.trim().parse()?, event_str.trim().parse()?));
+        tokens.next();
+    }
+    scan_filter.polarity = match tokens.peek() {
+        Some(&"+") => Some(Polarity::Positive),
+        Some(&"-") => Some(Polarity::Negative),
+        _ => None,
+    };
+    if scan_filter.polarity.is_some() {
+        tokens.next();
+    }
+    scan_filter.data_type = match tokens.peek() {
+        Some(&"p") => Some(SpectrumRepresentation::Profile),
+        Some(&"c") => Some(SpectrumRepresentation::Centroid),
+        _ => None,
+    };
+    if scan_filter.data_type.is_some() {
+        tokens.next();
+    }
+    if let Some(ionization) = tokens.peek().and_then(|t| FilterIonization::from_code(t)) {
+        scan_filter.ionization = Some(ionization);
+        tokens.next();
+    }
+
+    // Flags up to the scan mode or to the MS order
+    while let Some(token) = tokens.peek() {
+        if let Some(scan_mode) = FilterScanMode::from_code(token) {
+            scan_filter.scan_mode = Some(scan_mode);
+            tokens.next();
+            break;
+        }
+        if _parse_ms_order(token).is_some() {
+            break;
+        }
+        scan_filter.flags.push(FilterFlag::parse(token)?);
+        tokens.next();
+    }
+
+    if tokens.peek() == Some(&"lock") {
+        scan_filter.lock = true;
+        tokens.next();
+    }
+    if tokens.peek() == Some(&"msx") {
+        scan_filter.multiplex = true;
+        tokens.next();
+    }
+    if let Some(ms_order) = tokens.peek().and_then(|t| _parse_ms_order(t)) {
+        scan_filter.ms_order = Some(ms_order);
+        tokens.next();
+    }
+
+    for (precursor_idx, precursor_str) in tokens.enumerate() {
+        let mut precursor_parts = precursor_str.split('@');
+        let mz_str = precursor_parts.next().unwrap_or_default();
+        if precursor_idx == 0 {
+            scan_filter.precursor_mz_decimals = _count_decimals(mz_str);
+        }
+
+        scan_filter.precursors.push(FilterPrecursor {
+            mz: mz_str.parse().with_context(|| format!("unexpected token '{}'", precursor_str))?,
+            activations: precursor_parts.map(FilterActivation::parse).collect::<Result<Vec<FilterActivation>>>()?,
+        });
+    }
+
+    if let Some(mass_ranges_str) = mass_ranges_str_opt {
+        for (range_idx, mass_range_str) in mass_ranges_str.split(',').map(|r| r.trim()).enumerate() {
+            let (start_str, end_str) = mass_range_str.split_once('-').unwrap_or((mass_range_str, mass_range_str));
+            if range_idx == 0 {
+                scan_filter.mass_range_decimals = _count_decimals(start_str);
+            }
+
+            let start = start_str.parse().with_context(|| format!("invalid mass range '{}'", mass_range_str))?;
+            let end = end_str.parse().with_context(|| format!("invalid mass range '{}'", mass_range_str))?;
+            scan_filter.mass_ranges.push((start, end));
+        }
+    }
+
+    Ok(scan_filter)
+}
+
+// A negated flag (e.g. "!d") matches the scans without this flag
+fn _matches_flag(flag: &FilterFlag, scan_flags: &[FilterFlag]) -> bool {
+    match flag {
+        FilterFlag::Other(token) if token.len() > 1 && token.starts_with('!') => {
+            FilterFlag::parse(&token[1..]).map(|negated_flag| !scan_flags.contains(&negated_flag)).unwrap_or(false)
+        }
+        _ => scan_flags.contains(flag),
+    }
+}
+
+// Half of the last decimal unit, e.g. 0.005 for 2 decimals
+fn _get_precision_tolerance(decimals: usize) -> f64 {
+    0.5 * 10f64.powi(-(decimals as i32))
+}
+
+fn _parse_ms_order(token: &str) -> Option<u8> {
+    match token.strip_prefix("ms")? {
+        "" => Some(1),
+        ms_order_str => ms_order_str.parse().ok(),
+    }
+}
+
+fn _count_decimals(number_str: &str) -> usize {
+    number_str.split_once('.').map(|(_, decimals)| decimals.len()).unwrap_or(0)
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use serde::{Serialize, Deserialize};
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::StreamerError;
+use crate::mzml::*;
+use crate::mzml_spectrum::MzMLSpectrum;
+use crate::native_scan::NativeScan;
+use crate::streamer::RawFileStreamer;
+
+/// Compact description of a scan, used to query a RAW file without converting its spectra.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScanIndexEntry {
+    pub scan_number: u32,
+    pub ms_level: u8,
+    /// Retention time in minutes
+    pub retention_time: f64,
+    pub precursor_mz: Option<f64>,
+    pub precursor_charge: Option<i8>,
+    pub tic: f64,
+    pub base_peak_mz: f64,
+    pub base_peak_intensity: f64,
+    pub filter_string: String,
+}
+
+impl ScanIndexEntry {
+    pub fn from_spectrum(scan_number: u32, spectrum: &MzMLSpectrum) -> Self {
+        let (precursor_mz, precursor_charge) = spectrum.get_precursor_mz_and_charge();
+        let spectrum_cv_params = &spectrum.metadata.cv_params;
+        let first_scan_cv_params = spectrum.metadata.scan_list.scans.first().map(|s| s.cv_params.as_slice()).unwrap_or(&[]);
+
+        Self {
+            scan_number,
+            ms_level: spectrum.get_ms_level(),
+            retention_time: spectrum.get_first_scan_start_time().unwrap_or(0.0),
+            precursor_mz,
+            precursor_charge,
+            tic: _find_cv_param_as_f64(spectrum_cv_params, TOTAL_ION_CURRENT_CV_ACCESSION).unwrap_or(0.0),
+            base_peak_mz: _find_cv_param_as_f64(spectrum_cv_params, BASE_PEAK_MZ_CV_ACCESSION).unwrap_or(0.0),
+            base_peak_intensity: _find_cv_param_as_f64(spectrum_cv_params, BASE_PEAK_INTENSITY_CV_ACCESSION).unwrap_or(0.0),
+            filter_string: first_scan_cv_params.iter()
+                .find(|cvp| cvp.accession == FILTER_STRING_CV_ACCESSION)
+                .and_then(|cvp| cvp.value.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn from_native_scan(native_scan: &NativeScan) -> Self {
+        Self {
+            scan_number: native_scan.scan_number,
+            ms_level: native_scan.ms_order,
+            retention_time: native_scan.retention_time,
+            precursor_mz: native_scan.precursor_mz,
+            precursor_charge: native_scan.precursor_charge,
+            tic: native_scan.tic,
+            base_peak_mz: native_scan.base_peak_mass,
+            base_peak_intensity: native_scan.base_peak_intensity,
+            filter_string: native_scan.filter_string.clone(),
+        }
+    }
+
+    /// Returns true if this is an MS2 scan whose precursor m/z matches the given one with a tolerance expressed in ppm.
+    pub fn ms2_matches(&self, precursor_mz: f64, tol_ppm: f64) -> bool {
+        let mz_tol = precursor_mz * tol_ppm / 1e6;
+        self.ms_level == 2 && self.precursor_mz.map(|mz| (mz - precursor_mz).abs() <= mz_tol).unwrap_or(false)
+    }
+}
+
+fn _find_cv_param_as_f64(cv_params: &[CvParam], accession: &str) -> Option<f64> {
+    cv_params.iter()
+        .find(|cvp| cvp.accession == accession)
+        .and_then(|cvp| cvp.value.as_ref())
+        .and_then(|value| value.parse::<f64>().ok())
+}
+
+/// One `ScanIndexEntry` per scan, sorted by scan number.
+///
+/// Retention time queries assume that the retention time increases with the scan number,
+/// which is the case for RAW files acquired with a single chromatographic run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanIndex {
+    entries: Vec<ScanIndexEntry>,
+}
+
+impl ScanIndex {
+    pub fn new(mut entries: Vec<ScanIndexEntry>) -> Self {
+        entries.sort_by_key(|e| e.scan_number);
+        Self { entries }
+    }
+
+    /// Builds the index by loading the metadata of all the spectra of the streamer.
+    pub fn build(streamer: &RawFileStreamer) -> Result<Self, StreamerError> {
+        let n_scans = (streamer.get_last_scan_number() - streamer.get_first_scan_number() + 1) as usize;
+        let mut entries = Vec::with_capacity(n_scans);
+
+        for scan_number in streamer.get_first_scan_number() ..= streamer.get_last_scan_number() {
+            let spectrum = streamer._get_mzml_spectrum(scan_number, false, true)
+                .with_context(|| format!("can't index spectrum {}", scan_number))
+                .map_err(StreamerError::from)?;
+            entries.push(ScanIndexEntry::from_spectrum(scan_number, &spectrum));
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn get_entries(&self) -> &[ScanIndexEntry] {
+        &self.entries
+    }
+
+    pub fn get_entry(&self, scan_number: u32) -> Option<&ScanIndexEntry> {
+        self.entries.binary_search_by_key(&scan_number, |e| e.scan_number).ok().map(|idx| &self.entries[idx])
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the scans having a retention time (in minutes) in the inclusive range [min_rt, max_rt].
+    pub fn scans_in_rt_range(&self, min_rt: f64, max_rt: f64) -> &[ScanIndexEntry] {
+        let first_idx = self.entries.partition_point(|e| e.retention_time < min_rt);
+        let last_idx = self.entries.partition_point(|e| e.retention_time <= max_rt);
+
+        if first_idx >= last_idx { &[] } else { &self.entries[first_idx..last_idx] }
+    }
+
+    /// Returns the MS2 scans whose precursor m/z matches the given one with a tolerance expressed in ppm.
+    pub fn ms2_for_precursor(&self, mz: f64, tol_ppm: f64) -> Vec<&ScanIndexEntry> {
+        self.entries.iter().filter(|e| e.ms2_matches(mz, tol_ppm)).collect()
+    }
+
+    /// Returns the MS1 scan having the closest retention time (in minutes) to the given one.
+    pub fn nearest_ms1(&self, rt: f64) -> Option<&ScanIndexEntry> {
+        let idx = self.entries.partition_point(|e| e.retention_time < rt);
+
+        let next_ms1_opt = self.entries[idx..].iter().find(|e| e.ms_level == 1);
+        let prev_ms1_opt = self.entries[..idx].iter().rev().find(|e| e.ms_level == 1);
+
+        match (prev_ms1_opt, next_ms1_opt) {
+            (Some(prev), Some(next)) => {
+                if (rt - prev.retention_time) <= (next.retention_time - rt) { Some(prev) } else { Some(next) }
+            }
+            (prev_opt, next_opt) => prev_opt.or(next_opt),
+        }
+    }
+
+    /// Returns true if the index has one entry per scan of the given scan range.
+    pub fn covers_scan_range(&self, first_scan_number: u32, last_scan_number: u32) -> bool {
+        let n_scans = last_scan_number.saturating_sub(first_scan_number) as usize + 1;
+
+        self.entries.len() == n_scans &&
+            self.entries.first().map(|e| e.scan_number) == Some(first_scan_number) &&
+            self.entries.last().map(|e| e.scan_number) == Some(last_scan_number)
+    }
+
+    pub fn write_to_file(&self, file_path: &Path) -> Result<(), StreamerError> {
+        let write_error = |e| StreamerError::io(format!("can't write scan index file '{}'", file_path.display()), e);
+
+        let file = std::fs::File::create(file_path)
+            .map_err(|e| StreamerError::io(format!("can't create scan index file '{}'", file_path.display()), e))?;
+
+        let mut file_writer = std::io::BufWriter::new(file);
+        serde_json::to_writer(&mut file_writer, self).map_err(|e| write_error(e.into()))?;
+        file_writer.flush().map_err(write_error)?;
+
+        Ok(())
+    }
+
+    pub fn read_from_file(file_path: &Path) -> Result<Self, StreamerError> {
+        let file = std::fs::File::open(file_path)
+            .map_err(|e| StreamerError::io(format!("can't open scan index file '{}'", file_path.display()), e))?;
+
+        let scan_index: ScanIndex = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| StreamerError::InvalidInput { input: "scan index file", message: format!("'{}': {}", file_path.display(), e) })?;
+
+        Ok(Self::new(scan_index.entries))
+    }
+}
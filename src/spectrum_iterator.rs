@@ -0,0 +1,220 @@
+use anyhow::Result;
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "arrow")]
+use crate::arrow_batches::SpectrumBatchIterator;
+use crate::error::StreamerError;
+use crate::mzml_spectrum::MzMLSpectrum;
+use crate::streamer::RawFileStreamer;
+
+/// Selection of the spectra of a `RawFileStreamer`, created with `RawFileStreamer::spectra()`.
+///
+/// The selection is restricted with the builder methods (`with_*`, `metadata_only`, `data_only`), then iterated through
+/// `IntoIterator` (e.g. in a `for` loop), which resolves the selected scan numbers once.
+/// Resolving MS level or retention time filters uses the streamer `ScanIndex` if any, or else loads the metadata of each
+/// scan of the scan range (which is as costly as iterating over the metadata of all these spectra, thus a scan index
+/// should be used when several selections are iterated, see `RawFileStreamer::new_with_scan_index`).
+pub struct SpectrumSelection<'a> {
+    streamer: &'a RawFileStreamer,
+    query: SpectrumQuery,
+}
+
+impl<'a> SpectrumSelection<'a> {
+    pub(crate) fn new(streamer: &'a RawFileStreamer) -> Self {
+        Self { streamer, query: SpectrumQuery::new(streamer.get_first_scan_number(), streamer.get_last_scan_number()) }
+    }
+
+    /// Restricts the iteration to a range of scan numbers (clamped to the file scan range).
+    pub fn with_scan_range(mut self, scan_range: RangeInclusive<u32>) -> Self {
+        self.query = self.query.with_scan_range(scan_range, self.streamer.get_first_scan_number() ..= self.streamer.get_last_scan_number());
+        self
+    }
+
+    /// Restricts the iteration to the given MS levels.
+    pub fn with_ms_levels(mut self, ms_levels: &[u8]) -> Self {
+        self.query.ms_levels = Some(ms_levels.to_vec());
+        self
+    }
+
+    /// Restricts the iteration to a retention time window (inclusive bounds, in minutes).
+    pub fn with_rt_range(mut self, min_rt: f64, max_rt: f64) -> Self {
+        self.query.rt_range = Some((min_rt, max_rt));
+        self
+    }
+
+    /// Only loads the spectra metadata (the peaks of returned spectra are empty).
+    pub fn metadata_only(mut self) -> Self {
+        self.query.load_data = false;
+        self.query.load_metadata = true;
+        self
+    }
+
+    /// Only loads the spectra peaks (the metadata of returned spectra are default values).
+    pub fn data_only(mut self) -> Self {
+        self.query.load_data = true;
+        self.query.load_metadata = false;
+        self
+    }
+
+    /// Groups the spectra into Arrow record batches of at most `batch_size` spectra.
+    #[cfg(feature = "arrow")]
+    pub fn record_batches(self, batch_size: usize) -> SpectrumBatchIterator<'a> {
+        SpectrumBatchIterator::new(self.into_iter(), batch_size)
+    }
+}
+
+impl<'a> IntoIterator for SpectrumSelection<'a> {
+    type Item = Result<MzMLSpectrum, StreamerError>;
+    type IntoIter = SpectrumIterator<'a>;
+
+    fn into_iter(self) -> SpectrumIterator<'a> {
+        let (scan_numbers, selection_error) = self.query.resolve(self.streamer);
+
+        SpectrumIterator {
+            streamer: self.streamer,
+            back: scan_numbers.len(),
+            scan_numbers,
+            selection_error,
+            load_data: self.query.load_data,
+            load_metadata: self.query.load_metadata,
+            front: 0,
+        }
+    }
+}
+
+/// Iterator over the spectra of a `SpectrumSelection`, whose scan numbers are resolved when it is created.
+///
+/// When the selection can't be resolved entirely (the metadata of a scan can't be loaded), the scans resolved before
+/// the failing one are returned, followed by the error, which counts as one item of the iterator length.
+pub struct SpectrumIterator<'a> {
+    streamer: &'a RawFileStreamer,
+    scan_numbers: Vec<u32>,
+    selection_error: Option<StreamerError>,
+    load_data: bool,
+    load_metadata: bool,
+    // Cursors in the selected scan numbers (back is exclusive)
+    front: usize,
+    back: usize,
+}
+
+impl SpectrumIterator<'_> {
+    fn _load_spectrum(&self, scan_number: u32) -> Result<MzMLSpectrum, StreamerError> {
+        self.streamer._get_mzml_spectrum(scan_number, self.load_data, self.load_metadata).map_err(StreamerError::from)
+    }
+}
+
+impl Iterator for SpectrumIterator<'_> {
+    type Item = Result<MzMLSpectrum, StreamerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front < self.back {
+            let scan_number = self.scan_numbers[self.front];
+            self.front += 1;
+            return Some(self._load_spectrum(scan_number));
+        }
+
+        // Selection errors are reported after the scans that could be resolved
+        self.selection_error.take().map(Err)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n_remaining = self.back - self.front + usize::from(self.selection_error.is_some());
+
+        (n_remaining, Some(n_remaining))
+    }
+}
+
+impl DoubleEndedIterator for SpectrumIterator<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.selection_error.take() {
+            return Some(Err(error));
+        }
+
+        if self.front < self.back {
+            self.back -= 1;
+            return Some(self._load_spectrum(self.scan_numbers[self.back]));
+        }
+
+        None
+    }
+}
+
+impl ExactSizeIterator for SpectrumIterator<'_> {}
+
+/// Scan range, filters and loaded parts of a spectrum selection, independent from the streamer.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SpectrumQuery {
+    first_scan_number: u32,
+    last_scan_number: u32,
+    pub(crate) ms_levels: Option<Vec<u8>>,
+    pub(crate) rt_range: Option<(f64, f64)>,
+    pub(crate) load_data: bool,
+    pub(crate) load_metadata: bool,
+}
+
+impl SpectrumQuery {
+    pub(crate) fn new(first_scan_number: u32, last_scan_number: u32) -> Self {
+        Self { first_scan_number, last_scan_number, ms_levels: None, rt_range: None, load_data: true, load_metadata: true }
+    }
+
+    pub(crate) fn with_scan_range(mut self, scan_range: RangeInclusive<u32>, file_scan_range: RangeInclusive<u32>) -> Self {
+        self.first_scan_number = (*scan_range.start()).max(*file_scan_range.start());
+        self.last_scan_number = (*scan_range.end()).min(*file_scan_range.end());
+        self
+    }
+
+    /// Returns the selected scan numbers, and the error raised while resolving the selection if any
+    /// (the scan numbers then being the ones resolved before the error).
+    pub(crate) fn resolve(&self, streamer: &RawFileStreamer) -> (Vec<u32>, Option<StreamerError>) {
+        let scan_range = self.first_scan_number ..= self.last_scan_number;
+
+        if self.ms_levels.is_none() && self.rt_range.is_none() {
+            return (scan_range.collect(), None);
+        }
+
+        // The scan index, when available, avoids loading the metadata of each spectrum
+        if let Some(scan_index) = streamer.get_scan_index() {
+            let scan_numbers = scan_index.get_entries().iter()
+                .filter(|e| scan_range.contains(&e.scan_number))
+                .filter(|e| self._is_scan_selected(e.ms_level, Some(e.retention_time)))
+                .map(|e| e.scan_number)
+                .collect();
+
+            return (scan_numbers, None);
+        }
+
+        let mut scan_numbers = Vec::new();
+        for scan_number in scan_range {
+            match streamer._get_mzml_spectrum(scan_number, false, true) {
+                Result::Ok(spectrum) => {
+                    if self._is_scan_selected(spectrum.get_ms_level(), spectrum.get_first_scan_start_time()) {
+                        scan_numbers.push(scan_number);
+                    }
+                }
+                Err(e) => {
+                    let error = e.context(format!("can't load metadata of spectrum {}", scan_number));
+                    return (scan_numbers, Some(StreamerError::from(error)));
+                }
+            }
+        }
+
+        (scan_numbers, None)
+    }
+
+    fn _is_scan_selected(&self, ms_level: u8, rt_opt: Option<f64>) -> bool {
+        if let Some(ms_levels) = self.ms_levels.as_ref() {
+            if !ms_levels.contains(&ms_level) {
+                return false;
+            }
+        }
+
+        if let Some((min_rt, max_rt)) = self.rt_range {
+            match rt_opt {
+                Some(rt) if rt >= min_rt && rt <= max_rt => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,102 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::chromatogram::{Chromatogram, ChromatogramKind};
+use crate::mzml::*;
+use crate::mzml_spectrum::ActivationMethod;
+use crate::scan_filter::{FilterScanMode, ScanFilter};
+use crate::scan_index::ScanIndexEntry;
+
+/// Precursor and product isolation of a selected reaction monitoring transition.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SrmTransition {
+    /// Q1 m/z
+    pub precursor_mz: f64,
+    /// Lower limit of the Q3 window
+    pub product_mz_start: f64,
+    /// Upper limit of the Q3 window
+    pub product_mz_end: f64,
+    /// CID if not defined by the scan filter
+    pub activation: ActivationMethod,
+    /// Collision energy in electronvolts, if found in the scan filter
+    pub collision_energy: Option<f64>,
+}
+
+impl SrmTransition {
+    /// Returns the Q3 m/z (center of the product window).
+    pub fn get_product_mz(&self) -> f64 {
+        (self.product_mz_start + self.product_mz_end) / 2.0
+    }
+
+    /// Returns the chromatogram id of the transition (e.g. "SRM SIC Q1=524.300 Q3=785.391").
+    pub fn get_id(&self) -> String {
+        match self.collision_energy {
+            Some(collision_energy) => format!("SRM SIC Q1={:.3} Q3={:.3} CE={}", self.precursor_mz, self.get_product_mz(), collision_energy),
+            None => format!("SRM SIC Q1={:.3} Q3={:.3}", self.precursor_mz, self.get_product_mz()),
+        }
+    }
+}
+
+/// Parses the transitions of a Thermo SRM scan filter, or returns None if the filter isn't a SRM one.
+///
+/// For instance "+ c ESI SRM ms2 524.300@cid25.00 [785.390-785.392, 914.430-914.432]" defines two transitions
+/// whose precursor m/z is 524.3 (the activation part being optional).
+pub fn parse_srm_filter(filter_string: &str) -> Option<Vec<SrmTransition>> {
+    let scan_filter: ScanFilter = filter_string.parse().ok()?;
+    if scan_filter.scan_mode != Some(FilterScanMode::Srm) {
+        return None;
+    }
+
+    let precursor = scan_filter.precursors.last()?;
+    let activation_opt = precursor.activations.first();
+
+    Some(scan_filter.mass_ranges.iter().map(|(product_mz_start, product_mz_end)| SrmTransition {
+        precursor_mz: precursor.mz,
+        product_mz_start: *product_mz_start,
+        product_mz_end: *product_mz_end,
+        activation: activation_opt.map(|activation| activation.method).unwrap_or(ActivationMethod::Cid),
+        collision_energy: activation_opt.map(|activation| activation.energy),
+    }).collect())
+}
+
+/// Accumulates the SRM chromatograms of a file, one per distinct transition (in order of first appearance).
+#[derive(Default)]
+pub(crate) struct SrmChromatogramsBuilder {
+    chromatograms: Vec<Chromatogram>,
+    indices_by_id: HashMap<String, usize>,
+}
+
+impl SrmChromatogramsBuilder {
+    /// Adds the summed intensity of the peaks of each product window (the masses being sorted in ascending order).
+    pub(crate) fn add_scan(&mut self, scan_entry: &ScanIndexEntry, transitions: &[SrmTransition], masses: &[f64], intensities: &[f64]) {
+        for transition in transitions {
+            let id = transition.get_id();
+            let idx = *self.indices_by_id.entry(id.clone()).or_insert_with(|| {
+                self.chromatograms.push(Chromatogram {
+                    id,
+                    kind: ChromatogramKind::SelectedReactionMonitoring,
+                    ms_levels: None,
+                    mz_range: None,
+                    transition: Some(*transition),
+                    time_unit: TimeUnit::Minute,
+                    scan_numbers: vec![],
+                    times: vec![],
+                    intensities: vec![],
+                });
+                self.chromatograms.len() - 1
+            });
+
+            let first_idx = masses.partition_point(|mz| *mz < transition.product_mz_start);
+            let last_idx = first_idx + masses[first_idx..].partition_point(|mz| *mz <= transition.product_mz_end);
+
+            let chromatogram = &mut self.chromatograms[idx];
+            chromatogram.scan_numbers.push(scan_entry.scan_number);
+            chromatogram.times.push(scan_entry.retention_time);
+            chromatogram.intensities.push(intensities[first_idx..last_idx].iter().sum());
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<Chromatogram> {
+        self.chromatograms
+    }
+}
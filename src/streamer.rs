@@ -1,21 +1,37 @@
 #![allow(unused)]
 
-use anyhow::*;
+use anyhow::{anyhow, Context, Error, Result};
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use std::path::Path;
 use path_absolutize::Absolutize;
 
+#[cfg(feature = "arrow")]
+use crate::arrow_batches::SpectrumBatchIterator;
 use crate::bindings::*;
+use crate::chromatogram::{Chromatogram, ChromatogramKind};
+use crate::error::StreamerError;
 use crate::{mzml, mzml_spectrum};
-use crate::mono::MONO_EMBEDDINATOR;
+use crate::index_cache::*;
+use crate::mono::{call_glue, MonoRuntime, MONO_EMBEDDINATOR};
 use crate::mzml::{MzMLMetaData};
 use crate::mzml_spectrum::{MzMLSpectrum, MzMLSpectrumMetaData, SpectrumData};
-
-pub fn get_thermo_raw_file_parser_version() -> Result<String> { // Result<String>
+use crate::native_scan::{self, NativeScan};
+use crate::raw_file_probe::{probe_raw_file, RawFileProbe};
+use crate::run_info::{self, RunInfo};
+use crate::scan_index::{ScanIndex, ScanIndexEntry};
+use crate::spectrum_iterator::SpectrumSelection;
+use crate::srm::{parse_srm_filter, SrmChromatogramsBuilder};
+use crate::streamer_options::*;
+use crate::trailer_extra::TrailerExtra;
+use crate::xic::XicRequest;
+use crate::xic_batch::XicBatch;
+
+pub fn get_thermo_raw_file_parser_version() -> Result<String, StreamerError> {
     MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
 
     unsafe {
-        Ok(cstr_to_string(ThermoRawFileParser_MainClass_get_Version()))
+        Ok(cstr_to_string(call_glue(|| ThermoRawFileParser_MainClass_get_Version())?))
     }
 }
 
@@ -25,87 +41,306 @@ unsafe fn cstr_to_string(cstr: *const std::os::raw::c_char) -> String {
     c_string.to_string_lossy().into_owned()
 }
 
-#[derive(Clone, Debug)]
+// Beginning of the message of the exception thrown by ThermoRawFileParser when the RAW file is being acquired
+const RAW_FILE_IN_ACQUISITION_MESSAGE: &str = "RAW file still being acquired";
+
+// Metadata XML and peaks of a spectrum, as copied from the .NET spectrum writer
+type UnparsedSpectrumResult = Result<(Option<String>, Option<SpectrumData>), StreamerError>;
+
+/// Note: the streamer holds a `MonoRuntime` guard, thus the Mono runtime can't be disposed before the streamer is dropped.
+#[derive(Debug)]
 pub struct RawFileStreamer {
     raw_file_path: String,
     first_scan_number: u32,
     last_scan_number: u32,
     meta_data: MzMLMetaData,
+    scan_index: Option<ScanIndex>,
+    index_cache_status: Option<IndexCacheStatus>,
+    options: StreamerOptions,
+    pipeline: SpectrumWriterPipeline,
+    // Only used when the peak picking is enabled for some MS levels and disabled for others
+    secondary_pipeline: Option<SpectrumWriterPipeline>,
+    // Declared after the pipelines, so that it is dropped once their .NET objects have been disposed
+    _runtime: MonoRuntime,
+}
+
+// .NET objects converting the spectra of the RAW file, with peak picking enabled or not
+// Note: not cloneable, since the objects are disposed when the pipeline is dropped
+#[derive(Debug)]
+struct SpectrumWriterPipeline {
+    peak_picking: bool,
+    first_scan_number: u32,
+    last_scan_number: u32,
     raw_file_wrapper_ptr: *mut ThermoRawFileParser_RawFileWrapper,
     mzml_writer_ptr: *mut ThermoRawFileParser_Writer_MzMlSpectrumWriter,
     spectrum_wrapper_ptr: *mut ThermoRawFileParser_Writer_SpectrumWrapper
 }
 
-impl Drop for RawFileStreamer {
-    fn drop(&mut self) {
-        self.dispose()
+impl SpectrumWriterPipeline {
+    // Note: each glue call is checked since the objects it returns are null when a .NET exception is thrown
+    unsafe fn open(raw_file_path_cstr: &CString, options: &StreamerOptions, peak_picking: bool) -> Result<Self, StreamerError> {
+        let parse_input = call_glue(|| ThermoRawFileParser_ParseInput_new_1(
+            raw_file_path_cstr.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null(),
+            ThermoRawFileParser_OutputFormat_ThermoRawFileParser_OutputFormat_MzML,
+        ))?;
+
+        let raw_file_path = raw_file_path_cstr.to_string_lossy().into_owned();
+        let raw_file_wrapper_res = Self::_init_raw_file(parse_input, options, peak_picking).map_err(|glue_error| match glue_error {
+            StreamerError::DotNetException { message, .. } if message.starts_with(RAW_FILE_IN_ACQUISITION_MESSAGE) => {
+                StreamerError::RawFileInAcquisition { path: raw_file_path.clone() }
+            }
+            _ => glue_error,
+        });
+        // Note: the wrapper keeps its own reference to the managed object
+        mono_embeddinator_destroy_object(parse_input);
+
+        let raw_file_wrapper = raw_file_wrapper_res?;
+        if raw_file_wrapper.is_null() {
+            return Err(anyhow!("ThermoRawFileParser didn't return any wrapper for the RAW file '{}'", raw_file_path).into());
+        }
+
+        // From now on, the objects are disposed by Drop if an error occurs
+        let mut pipeline = SpectrumWriterPipeline {
+            peak_picking,
+            first_scan_number: 0,
+            last_scan_number: 0,
+            raw_file_wrapper_ptr: raw_file_wrapper,
+            mzml_writer_ptr: std::ptr::null_mut(),
+            spectrum_wrapper_ptr: std::ptr::null_mut(),
+        };
+
+        let first_scan_number = call_glue(|| ThermoRawFileParser_RawFileWrapper_get_FirstScanNumber(raw_file_wrapper))?;
+        let last_scan_number = call_glue(|| ThermoRawFileParser_RawFileWrapper_get_LastScanNumber(raw_file_wrapper))?;
+        if first_scan_number < 1 || last_scan_number < first_scan_number {
+            return Err(StreamerError::NoScans { path: raw_file_path, first_scan_number, last_scan_number });
+        }
+        pipeline.first_scan_number = first_scan_number as u32;
+        pipeline.last_scan_number = last_scan_number as u32;
+
+        let mzml_writer = call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_new(raw_file_wrapper))?;
+        pipeline.mzml_writer_ptr = mzml_writer;
+
+        call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_CreateXmlWriter(mzml_writer))?;
+
+        // Note: the header has to be written even if the meta-data are already known, to initialize the writer state
+        call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_WriteHeader(
+            mzml_writer,
+            first_scan_number,
+            last_scan_number,
+        ))?;
+
+        pipeline.spectrum_wrapper_ptr = call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_getSpectrumWrapper(mzml_writer))?;
+
+        Ok(pipeline)
+    }
+
+    unsafe fn _init_raw_file(
+        parse_input: *mut ThermoRawFileParser_ParseInput,
+        options: &StreamerOptions,
+        peak_picking: bool
+    ) -> Result<*mut ThermoRawFileParser_RawFileWrapper, StreamerError> {
+        call_glue(|| ThermoRawFileParser_ParseInput_set_UseInMemoryWriter(parse_input, true))?;
+        call_glue(|| ThermoRawFileParser_ParseInput_set_NoPeakPicking(parse_input, !peak_picking))?;
+        call_glue(|| ThermoRawFileParser_ParseInput_set_IgnoreInstrumentErrors(parse_input, options.is_ignoring_instrument_errors()))?;
+        call_glue(|| ThermoRawFileParser_ParseInput_set_NoZlibCompression(parse_input, options.is_zlib_compression_disabled()))?;
+        call_glue(|| ThermoRawFileParser_ParseInput_set_LogFormat(parse_input, options.get_log_format().to_thermo_log_format()))?;
+
+        call_glue(|| ThermoRawFileParser_RawFileParser_InitRawFile(parse_input))
+    }
+
+    unsafe fn write_spectrum(&self, number: u32) -> Result<(), StreamerError> {
+        let scan_number = number as i32;
+        call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_ResetWriter(self.mzml_writer_ptr, false))?;
+        call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_WriteSpectrumNoReturn(self.mzml_writer_ptr, scan_number , scan_number, false))
     }
 }
 
-impl RawFileStreamer {
+impl Drop for SpectrumWriterPipeline {
+    fn drop(&mut self) {
+        // Note: the runtime is kept alive by the guard of the streamer, but it can't be called from another thread
+        if MONO_EMBEDDINATOR.lock().unwrap().check_availability().is_err() {
+            return;
+        }
 
-    pub fn dispose(&self) {
-        if MONO_EMBEDDINATOR.lock().unwrap().check_availability().is_ok() {
-            unsafe {
-                // TODO: double check if this is enough
-                ThermoRawFileParser_RawFileWrapper_Dispose(self.raw_file_wrapper_ptr)
+        // Note: the pointers are null if the opening of the pipeline failed before their creation
+        unsafe {
+            if !self.spectrum_wrapper_ptr.is_null() {
+                mono_embeddinator_destroy_object(self.spectrum_wrapper_ptr);
+            }
+            if !self.mzml_writer_ptr.is_null() {
+                mono_embeddinator_destroy_object(self.mzml_writer_ptr);
             }
+            // Note: any exception thrown while disposing is discarded, since Drop can't report it
+            let _ = call_glue(|| ThermoRawFileParser_RawFileWrapper_Dispose(self.raw_file_wrapper_ptr));
+            mono_embeddinator_destroy_object(self.raw_file_wrapper_ptr);
         }
     }
+}
 
-    pub fn new(raw_file_path: &str) -> Result<RawFileStreamer> {
-        MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
+impl RawFileStreamer {
 
-        unsafe {
-            let abs_raw_file_path: String = Path::new(raw_file_path).absolutize()?.display().to_string();
-            let raw_file_path_cstr = CString::new(abs_raw_file_path)?;
+    /// Disposes the .NET objects of the streamer, then releases its `MonoRuntime` guard.
+    ///
+    /// This is equivalent to dropping the streamer.
+    pub fn dispose(self) {
+        drop(self)
+    }
 
-            let parse_input = ThermoRawFileParser_ParseInput_new_1(
-                raw_file_path_cstr.as_ptr(),
-                std::ptr::null(),
-                std::ptr::null(),
-                ThermoRawFileParser_OutputFormat_ThermoRawFileParser_OutputFormat_MzML,
-            );
+    /// Returns the facts read from the header of a RAW file, without opening it with ThermoRawFileParser.
+    ///
+    /// Fails if the file is missing, is a directory, can't be read or is not a Thermo RAW file.
+    pub fn probe(raw_file_path: &str) -> Result<RawFileProbe, StreamerError> {
+        probe_raw_file(raw_file_path)
+    }
 
-            ThermoRawFileParser_ParseInput_set_UseInMemoryWriter(parse_input, true);
+    pub fn new(raw_file_path: &str) -> Result<RawFileStreamer, StreamerError> {
+        Self::new_with_options(raw_file_path, &StreamerOptions::default())
+    }
 
-            let raw_file_wrapper = ThermoRawFileParser_RawFileParser_InitRawFile(parse_input);
+    /// Opens the RAW file using the given options (see `StreamerOptions`), which are validated first.
+    ///
+    /// The file is checked before being opened by ThermoRawFileParser (see `RawFileStreamer::probe`).
+    pub fn new_with_options(raw_file_path: &str, options: &StreamerOptions) -> Result<RawFileStreamer, StreamerError> {
+        options.validate().context("invalid streamer options")?;
+        probe_raw_file(raw_file_path)?;
 
-            let first_scan_number = ThermoRawFileParser_RawFileWrapper_get_FirstScanNumber(raw_file_wrapper);
-            let last_scan_number = ThermoRawFileParser_RawFileWrapper_get_LastScanNumber(raw_file_wrapper);
+        if let Some(cache_location) = options.get_index_cache_location() {
+            return Self::_open_with_index_cache(raw_file_path, options, cache_location).map_err(StreamerError::from);
+        }
 
-            let mzml_writer = ThermoRawFileParser_Writer_MzMlSpectrumWriter_new(raw_file_wrapper);
+        let mut streamer = Self::_open(raw_file_path, options, None)?;
 
-            ThermoRawFileParser_Writer_MzMlSpectrumWriter_CreateXmlWriter(mzml_writer);
+        if options.is_scan_index_enabled() {
+            streamer.scan_index = Some(ScanIndex::build(&streamer)?);
+        }
 
-            ThermoRawFileParser_Writer_MzMlSpectrumWriter_WriteHeader(
-                mzml_writer,
-                first_scan_number,
-                last_scan_number,
-            );
+        Ok(streamer)
+    }
 
-            let meta_data_as_xml_string = cstr_to_string(
-                ThermoRawFileParser_Writer_MzMlSpectrumWriter_GetInMemoryStreamAsString(mzml_writer)
-            ) + "/></mzML>";
+    fn _open(raw_file_path: &str, options: &StreamerOptions, cached_meta_data: Option<MzMLMetaData>) -> Result<RawFileStreamer> {
+        // Note: acquired first, so that the pipelines are disposed if the opening fails
+        let runtime = MonoRuntime::acquire()?;
 
-            //println!("XML header:\n{}", meta_data_as_xml_string);
+        let peak_picking = options.get_peak_picking();
 
-            let meta_data = mzml::parse_mzml_metadata(&meta_data_as_xml_string)?;
+        unsafe {
+            let abs_raw_file_path: String = Path::new(raw_file_path).absolutize()?.display().to_string();
+            let raw_file_path_cstr = CString::new(abs_raw_file_path)?;
+
+            let pipeline = SpectrumWriterPipeline::open(&raw_file_path_cstr, options, peak_picking.is_enabled_for_any())?;
+
+            // NoPeakPicking applies to a whole RAW file wrapper, thus mixed modes need a second one
+            let secondary_pipeline = if peak_picking.is_enabled_for_any() && peak_picking.is_disabled_for_any() {
+                Some(SpectrumWriterPipeline::open(&raw_file_path_cstr, options, false)?)
+            } else {
+                None
+            };
+
+
+            let mut meta_data = match cached_meta_data {
+                Some(meta_data) => meta_data,
+                None => {
+                    let meta_data_as_xml_string = cstr_to_string(call_glue(||
+                        ThermoRawFileParser_Writer_MzMlSpectrumWriter_GetInMemoryStreamAsString(pipeline.mzml_writer_ptr)
+                    )?) + "/></mzML>";
 
-            let spectrum_wrapper_ptr = ThermoRawFileParser_Writer_MzMlSpectrumWriter_getSpectrumWrapper(mzml_writer);
+                    //println!("XML header:\n{}", meta_data_as_xml_string);
+
+                    mzml::parse_mzml_metadata(&meta_data_as_xml_string)?
+                }
+            };
+
+            // Note: the entry is replaced if the meta-data come from an index cache built with other options
+            options.record_in_metadata(&mut meta_data);
 
             Ok(RawFileStreamer {
                 raw_file_path: raw_file_path.to_string(),
-                first_scan_number: first_scan_number as u32,
-                last_scan_number: last_scan_number as u32,
+                first_scan_number: pipeline.first_scan_number,
+                last_scan_number: pipeline.last_scan_number,
                 meta_data: meta_data,
-                raw_file_wrapper_ptr: raw_file_wrapper,
-                mzml_writer_ptr: mzml_writer,
-                spectrum_wrapper_ptr,
+                scan_index: None,
+                index_cache_status: None,
+                options: options.clone(),
+                pipeline,
+                secondary_pipeline,
+                _runtime: runtime,
             })
         }
     }
 
+    /// Opens the RAW file and builds its `ScanIndex`, which is then persisted in an index cache next to the RAW file.
+    ///
+    /// This is equivalent to `new_with_index_cache` with `IndexCacheLocation::NextToRawFile`.
+    pub fn new_with_scan_index(raw_file_path: &str) -> Result<RawFileStreamer, StreamerError> {
+        Self::new_with_index_cache(raw_file_path, &IndexCacheLocation::NextToRawFile)
+    }
+
+    /// Opens the RAW file and loads its meta-data and `ScanIndex` from an index cache file.
+    ///
+    /// The cache is validated using the RAW file fingerprint (size, modification time and hash of the first block).
+    /// Missing, stale or corrupt caches are rebuilt and written to the given location.
+    /// Failing to write the cache (e.g. in a read-only directory) doesn't prevent the opening of the file,
+    /// and is reported by `get_index_cache_status`.
+    pub fn new_with_index_cache(raw_file_path: &str, cache_location: &IndexCacheLocation) -> Result<RawFileStreamer, StreamerError> {
+        Self::new_with_options(raw_file_path, &StreamerOptions::new().with_index_cache(cache_location.clone()))
+    }
+
+    fn _open_with_index_cache(raw_file_path: &str, options: &StreamerOptions, cache_location: &IndexCacheLocation) -> Result<RawFileStreamer> {
+        let fingerprint = RawFileFingerprint::compute(Path::new(raw_file_path))?;
+        let cache_file_path = IndexCache::get_cache_file_path(Path::new(raw_file_path), cache_location)?;
+
+        let (index_cache_opt, invalid_cache_reason_opt) = match IndexCache::read_if_valid(&cache_file_path, &fingerprint) {
+            Result::Ok(Some(index_cache)) if &index_cache.peak_picking != options.get_peak_picking() => {
+                (None, Some("stale index cache: the peak picking options have changed".to_string()))
+            }
+            Result::Ok(index_cache_opt) => (index_cache_opt, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let (cached_meta_data, cached_scan_index) = match index_cache_opt {
+            Some(index_cache) => (Some(index_cache.metadata), Some(index_cache.scan_index)),
+            None => (None, None),
+        };
+
+        let mut streamer = Self::_open(raw_file_path, options, cached_meta_data)?;
+
+        let cache_was_loaded = cached_scan_index.is_some();
+        let (first_scan_number, last_scan_number) = (streamer.first_scan_number, streamer.last_scan_number);
+        let valid_scan_index_opt = cached_scan_index.filter(|scan_index| scan_index.covers_scan_range(first_scan_number, last_scan_number));
+
+        let index_cache_status = match valid_scan_index_opt {
+            Some(scan_index) => {
+                streamer.scan_index = Some(scan_index);
+                IndexCacheStatus::Reused
+            }
+            None => {
+                let scan_index = ScanIndex::build(&streamer)?;
+                let index_cache = IndexCache {
+                    peak_picking: options.get_peak_picking().clone(),
+                    ..IndexCache::new(fingerprint, streamer.meta_data.clone(), scan_index)
+                };
+                let write_res = index_cache.write_to_file(&cache_file_path);
+
+                streamer.scan_index = Some(index_cache.scan_index);
+
+                match (write_res, invalid_cache_reason_opt) {
+                    (Err(e), _) => IndexCacheStatus::NotWritten(format!("{:#}", e)),
+                    (Result::Ok(()), Some(reason)) => IndexCacheStatus::Rebuilt(reason),
+                    (Result::Ok(()), None) if cache_was_loaded => {
+                        IndexCacheStatus::Rebuilt("stale index cache: the scan range has changed".to_string())
+                    }
+                    (Result::Ok(()), None) => IndexCacheStatus::Created,
+                }
+            }
+        };
+
+        streamer.index_cache_status = Some(index_cache_status);
+
+        Ok(streamer)
+    }
+
     pub fn get_raw_file_path(&self) -> &str {
         &self.raw_file_path
     }
@@ -118,9 +353,130 @@ impl RawFileStreamer {
         self.last_scan_number
     }
 
-    pub fn process_spectra_in_parallel<F>(&self, mut on_each_spectrum: F, queue_size: usize) -> Result<()>
+    pub fn get_metadata(&self) -> &MzMLMetaData {
+        &self.meta_data
+    }
+
+    /// Returns the provenance information of the run (instrument, sample, operator and acquisition date).
+    ///
+    /// Note: ThermoRawFileParser parses the RAW file again to write its metadata file, thus this call isn't cheap.
+    pub fn get_run_info(&self) -> Result<RunInfo, StreamerError> {
+        MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
+
+        let metadata_txt = unsafe { run_info::_read_thermo_metadata(&self.raw_file_path)? };
+        let mut run_info = RunInfo::from_mzml_metadata(&self.meta_data).with_thermo_metadata(&metadata_txt);
+
+        let instrument_name_opt = run_info.instrument_name.clone().or_else(|| run_info.get_instrument_model_name().map(str::to_string));
+        if let Some(instrument_name) = instrument_name_opt {
+            if let Some(instrument_model) = unsafe { run_info::_resolve_instrument_model(&instrument_name)? } {
+                run_info.instrument_model = Some(instrument_model);
+            }
+        }
+
+        Ok(run_info)
+    }
+
+    pub fn get_options(&self) -> &StreamerOptions {
+        &self.options
+    }
+
+    pub fn get_scan_index(&self) -> Option<&ScanIndex> {
+        self.scan_index.as_ref()
+    }
+
+    /// Returns the outcome of the index cache lookup, if the streamer was created with `new_with_index_cache`
+    /// or `new_with_scan_index`.
+    pub fn get_index_cache_status(&self) -> Option<&IndexCacheStatus> {
+        self.index_cache_status.as_ref()
+    }
+
+    /// Returns a selection of all the spectra of the file, which can be restricted using its builder methods, then iterated.
+    pub fn spectra(&self) -> SpectrumSelection<'_> {
+        SpectrumSelection::new(self)
+    }
+
+    /// Returns an iterator over all the spectra of the file grouped into Arrow record batches (see `SpectrumBatchIterator`).
+    #[cfg(feature = "arrow")]
+    pub fn record_batches(&self, batch_size: usize) -> SpectrumBatchIterator<'_> {
+        self.spectra().record_batches(batch_size)
+    }
+
+    /// Returns the total ion current chromatogram of all the scans.
+    pub fn tic(&self) -> Result<Chromatogram, StreamerError> {
+        self.get_chromatogram(ChromatogramKind::TotalIonCurrent, None)
+    }
+
+    /// Returns the base peak chromatogram of all the scans.
+    pub fn bpc(&self) -> Result<Chromatogram, StreamerError> {
+        self.get_chromatogram(ChromatogramKind::BasePeak, None)
+    }
+
+    /// Computes a TIC or BPC chromatogram from the per-scan TIC and base peak values, optionally restricted to some MS levels.
+    ///
+    /// The values are read from the scan index if any, or else from the native scan headers (the peaks are not loaded).
+    pub fn get_chromatogram(&self, kind: ChromatogramKind, ms_levels: Option<&[u8]>) -> Result<Chromatogram, StreamerError> {
+        Ok(Chromatogram::from_scan_entries(kind, ms_levels, self._get_scan_entries()?.iter()))
+    }
+
+    /// Returns true if the file contains selected reaction monitoring scans (e.g. acquired by a triple quadrupole).
+    pub fn has_srm_scans(&self) -> Result<bool, StreamerError> {
+        Ok(self._get_scan_entries()?.iter().any(|entry| parse_srm_filter(&entry.filter_string).is_some()))
+    }
+
+    /// Computes one chromatogram per SRM transition, the transitions being read from the scan filters.
+    ///
+    /// The intensity of a transition is the summed intensity of the peaks of its product window, for each scan
+    /// monitoring it. Files without SRM scans give no chromatogram.
+    pub fn srm_chromatograms(&self) -> Result<Vec<Chromatogram>, StreamerError> {
+        self._get_srm_chromatograms(&self._get_scan_entries()?).map_err(StreamerError::from)
+    }
+
+    pub(crate) fn _get_srm_chromatograms(&self, scan_entries: &[ScanIndexEntry]) -> Result<Vec<Chromatogram>> {
+        let mut srm_builder = SrmChromatogramsBuilder::default();
+        for scan_entry in scan_entries {
+            if let Some(transitions) = parse_srm_filter(&scan_entry.filter_string) {
+                let scan = self.get_native_scan(scan_entry.scan_number)
+                    .with_context(|| format!("can't read peaks of SRM scan {}", scan_entry.scan_number))?;
+                srm_builder.add_scan(scan_entry, &transitions, &scan.masses, &scan.intensities);
+            }
+        }
+
+        Ok(srm_builder.finish())
+    }
+
+    /// Computes one extracted ion chromatogram per request, in the same order.
+    ///
+    /// The intensities of the peaks falling in the m/z window of a request are summed for each scan matching its filter
+    /// and retention time range (scans without such peaks giving zero intensity points). The peaks of a scan are loaded
+    /// at most once, whatever the number of requests (see `XicBatch`).
+    ///
+    /// Note: the chromatograms are computed from the native scans rather than by the ThermoRawFileParser XIC reader,
+    /// whose results aren't exposed by the glue. The filters follow the RawFileReader semantics, with case sensitive
+    /// codes (see `filter_matches`). Without a scan index, every scan is read with its peaks.
+    pub fn extract_xics(&self, requests: &[XicRequest]) -> Result<Vec<Chromatogram>, StreamerError> {
+        XicBatch::new(requests.to_vec()).extract_chromatograms(self)
+    }
+
+    // Returns the scan index entries, or builds them from the native scan headers when the streamer has no scan index
+    // Note: each header is then written by the .NET spectrum writer, thus repeated calls should use a scan index
+    pub(crate) fn _get_scan_entries(&self) -> Result<Cow<'_, [ScanIndexEntry]>> {
+        if let Some(scan_index) = self.scan_index.as_ref() {
+            return Ok(Cow::Borrowed(scan_index.get_entries()));
+        }
+
+        let mut scan_entries = Vec::with_capacity((self.last_scan_number - self.first_scan_number + 1) as usize);
+        for scan_number in self.first_scan_number ..= self.last_scan_number {
+            let scan_header = self.get_native_scan_header(scan_number)
+                .with_context(|| format!("can't read header of scan {}", scan_number))?;
+            scan_entries.push(ScanIndexEntry::from_native_scan(&scan_header));
+        }
+
+        Ok(Cow::Owned(scan_entries))
+    }
+
+    pub fn process_spectra_in_parallel<F>(&self, mut on_each_spectrum: F, queue_size: usize) -> Result<(), StreamerError>
     where
-        F: FnMut(Result<MzMLSpectrum>) -> Result<()> + Send + Sync {
+        F: FnMut(Result<MzMLSpectrum, StreamerError>) -> Result<()> + Send + Sync {
 
         let (sender_queue, receiver_queue) = std::sync::mpsc::sync_channel(queue_size);
 
@@ -134,9 +490,8 @@ impl RawFileStreamer {
 
             self.convert_spectra_in_parallel(|unparsed_spectrum_res| {
                 let spectrum_res = unparsed_spectrum_res.and_then(|(metadata_str_opt,data_opt)| {
-                    mzml_spectrum::parse_mzml_spectrum_metadata(&metadata_str_opt.unwrap()).and_then(|mzml_spectrum_metadata|{
-                        Ok(MzMLSpectrum::new(mzml_spectrum_metadata, data_opt.unwrap()))
-                    })
+                    let mzml_spectrum_metadata = mzml_spectrum::parse_mzml_spectrum_metadata(&metadata_str_opt.unwrap())?;
+                    Ok(MzMLSpectrum::new(mzml_spectrum_metadata, data_opt.unwrap()))
                 });
 
                 sender_queue.send(spectrum_res).map_err(|e| anyhow!(e))
@@ -151,9 +506,9 @@ impl RawFileStreamer {
         Ok(())
     }
 
-    pub fn convert_spectra_in_parallel<F>(&self, mut on_each_spectrum: F, queue_size: usize) -> Result<()>
+    pub fn convert_spectra_in_parallel<F>(&self, mut on_each_spectrum: F, queue_size: usize) -> Result<(), StreamerError>
     where
-        F: FnMut(Result<(Option<String>,Option<SpectrumData>)>) -> Result<()> + Send + Sync  {
+        F: FnMut(UnparsedSpectrumResult) -> Result<()> + Send + Sync  {
 
         let (sender_queue, receiver_queue) = std::sync::mpsc::sync_channel(queue_size);
 
@@ -187,7 +542,7 @@ impl RawFileStreamer {
         Err(error_message)
     }
 
-    fn _enqueue_all_unparsed_spectra(&self, queue: std::sync::mpsc::SyncSender<Result<(Option<String>, Option<SpectrumData>)>>) -> Result<()> {
+    fn _enqueue_all_unparsed_spectra(&self, queue: std::sync::mpsc::SyncSender<UnparsedSpectrumResult>) -> Result<()> {
 
         MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
 
@@ -201,7 +556,7 @@ impl RawFileStreamer {
         Ok(())
     }
 
-    pub fn get_spectrum(&self, number: u32) -> Result<MzMLSpectrum> {
+    pub fn get_spectrum(&self, number: u32) -> Result<MzMLSpectrum, StreamerError> {
         MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
 
         let (metadata_str_opt,data_opt) = self._get_spectrum(number, true, true)?;
@@ -211,45 +566,79 @@ impl RawFileStreamer {
         Ok(spectrum)
     }
 
-    pub fn get_spectrum_metadadata(&self, number: u32) -> Result<MzMLSpectrumMetaData> {
+    pub fn get_spectrum_metadadata(&self, number: u32) -> Result<MzMLSpectrumMetaData, StreamerError> {
         MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
 
-        self._get_spectrum(number, false, true).map(|tuple| {
-            let metadata_str = tuple.0;
-            let mzml_spectrum_metadata = mzml_spectrum::parse_mzml_spectrum_metadata(&metadata_str.unwrap());
-            mzml_spectrum_metadata
-        })?
+        let (metadata_str_opt, _) = self._get_spectrum(number, false, true)?;
+
+        mzml_spectrum::parse_mzml_spectrum_metadata(&metadata_str_opt.unwrap())
     }
 
-    pub fn get_spectrum_data(&self, number: u32) -> Result<SpectrumData> {
+    /// Returns the trailer extra records of a scan, as exported by ThermoRawFileParser in the mzML scan user params.
+    pub fn trailer_extra(&self, number: u32) -> Result<TrailerExtra, StreamerError> {
+        let metadata = self.get_spectrum_metadadata(number)?;
+        let spectrum = MzMLSpectrum::new(metadata, SpectrumData { mz_list: vec![], intensity_list: vec![] });
+
+        Ok(spectrum.get_trailer_extra())
+    }
+
+    pub fn get_spectrum_data(&self, number: u32) -> Result<SpectrumData, StreamerError> {
         MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
 
         self._get_spectrum(number, true, false).map(|tuple| tuple.1.unwrap())
     }
 
-    // TODO: we may want to use something like realloc to maintain a single buffer instead of allocating memory every time
-    fn _get_spectrum(&self, number: u32, load_data: bool, load_metadata: bool) -> Result<(Option<String>,Option<SpectrumData>)> {
+    /// Returns the scan as a `NativeScan`, read from the .NET spectrum object instead of the mzML spectrum XML.
+    ///
+    /// This skips the copy of the mzML spectrum XML and its parsing, but the .NET spectrum is still written
+    /// by ThermoRawFileParser (see the limitations in the `NativeScan` documentation).
+    pub fn get_native_scan(&self, number: u32) -> Result<NativeScan, StreamerError> {
+        self._get_native_scan(number, true).map_err(StreamerError::from)
+    }
 
-        if number < self.first_scan_number {
-            bail!("requested spectrum number ({}) is lower than first scan number ({})", number, self.first_scan_number);
-        }
-        if number > self.last_scan_number {
-            bail!("requested spectrum number ({}) is higher than last scan number ({})", number, self.last_scan_number);
-        }
+    /// Same as `get_native_scan` without copying the peaks (`masses` and `intensities` are empty).
+    pub fn get_native_scan_header(&self, number: u32) -> Result<NativeScan, StreamerError> {
+        self._get_native_scan(number, false).map_err(StreamerError::from)
+    }
+
+    fn _get_native_scan(&self, number: u32, load_data: bool) -> Result<NativeScan> {
+        MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
+
+        let pipeline = self._write_spectrum(number)?;
 
         unsafe {
-            let scan_number = number as i32;
-            ThermoRawFileParser_Writer_MzMlSpectrumWriter_ResetWriter(self.mzml_writer_ptr, false);
-            ThermoRawFileParser_Writer_MzMlSpectrumWriter_WriteSpectrumNoReturn(self.mzml_writer_ptr, scan_number , scan_number, false);
+            NativeScan::from_spectrum_wrapper(number, pipeline.spectrum_wrapper_ptr, load_data)
+        }
+    }
+
+    pub(crate) fn _get_mzml_spectrum(&self, number: u32, load_data: bool, load_metadata: bool) -> Result<MzMLSpectrum> {
+        MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
+
+        let (metadata_str_opt, data_opt) = self._get_spectrum(number, load_data, load_metadata)?;
 
+        let mzml_spectrum_metadata = match metadata_str_opt {
+            Some(metadata_str) => mzml_spectrum::parse_mzml_spectrum_metadata(&metadata_str)?,
+            None => MzMLSpectrumMetaData::default(),
+        };
+        let spectrum_data = data_opt.unwrap_or_else(|| SpectrumData { mz_list: vec![], intensity_list: vec![] });
+
+        Ok(MzMLSpectrum::new(mzml_spectrum_metadata, spectrum_data))
+    }
+
+    // TODO: we may want to use something like realloc to maintain a single buffer instead of allocating memory every time
+    fn _get_spectrum(&self, number: u32, load_data: bool, load_metadata: bool) -> Result<(Option<String>,Option<SpectrumData>), StreamerError> {
+
+        let pipeline = self._write_spectrum(number)?;
+
+        unsafe {
             let metadata_opt = if load_metadata {
-                Some(self._retrieve_unparsed_spectrum_meta_data()?)
+                Some(Self::_retrieve_unparsed_spectrum_meta_data(pipeline)?)
             } else{
                 None
             };
 
             let data_opt = if load_data {
-                Some(self._retrieve_unparsed_spectrum_data()?)
+                Some(Self::_retrieve_unparsed_spectrum_data(pipeline)?)
             } else {
                 None
             };
@@ -258,30 +647,71 @@ impl RawFileStreamer {
         }
     }
 
-    unsafe fn _retrieve_unparsed_spectrum_meta_data(&self) -> Result<String> {
+    // Writes the spectrum using the pipeline matching the peak picking of its MS level, and returns this pipeline
+    fn _write_spectrum(&self, number: u32) -> Result<&SpectrumWriterPipeline, StreamerError> {
+        if number < self.first_scan_number || number > self.last_scan_number {
+            return Err(StreamerError::ScanOutOfRange {
+                scan_number: number,
+                first_scan_number: self.first_scan_number,
+                last_scan_number: self.last_scan_number,
+            });
+        }
+
+        let secondary_pipeline = match self.secondary_pipeline.as_ref() {
+            Some(secondary_pipeline) => secondary_pipeline,
+            None => {
+                unsafe { self.pipeline.write_spectrum(number)? };
+                return Ok(&self.pipeline);
+            }
+        };
 
-        let xml_chunk_len = ThermoRawFileParser_Writer_MzMlSpectrumWriter_FlushWriterThenGetXmlStreamLength(self.mzml_writer_ptr);
+        // The MS level is taken from the scan index if any, or else from the spectrum written by the main pipeline
+        let (ms_level, already_written) = match self.scan_index.as_ref().and_then(|scan_index| scan_index.get_entry(number)) {
+            Some(entry) => (entry.ms_level, false),
+            None => unsafe {
+                self.pipeline.write_spectrum(number)?;
+                (native_scan::read_ms_level(number, self.pipeline.spectrum_wrapper_ptr)?, true)
+            }
+        };
+
+        if self.options.get_peak_picking().is_enabled_for(ms_level) == self.pipeline.peak_picking {
+            if !already_written {
+                unsafe { self.pipeline.write_spectrum(number)? };
+            }
+            Ok(&self.pipeline)
+        } else {
+            unsafe { secondary_pipeline.write_spectrum(number)? };
+            Ok(secondary_pipeline)
+        }
+    }
+
+    unsafe fn _retrieve_unparsed_spectrum_meta_data(pipeline: &SpectrumWriterPipeline) -> Result<String, StreamerError> {
+
+        let xml_chunk_len = call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_FlushWriterThenGetXmlStreamLength(pipeline.mzml_writer_ptr))?;
 
         // Allocate memory
         let layout = std::alloc::Layout::from_size_align(xml_chunk_len as usize, std::mem::align_of::<u8>()).unwrap();
         let xml_chunk_ptr = std::alloc::alloc(layout) as *mut u8;
         let xml_chunk_ptr_address = xml_chunk_ptr as usize as i64;
 
-        ThermoRawFileParser_Writer_MzMlSpectrumWriter_CopyXmlStreamToPointers(self.mzml_writer_ptr, xml_chunk_ptr_address);
+        if let Err(glue_error) = call_glue(|| ThermoRawFileParser_Writer_MzMlSpectrumWriter_CopyXmlStreamToPointers(pipeline.mzml_writer_ptr, xml_chunk_ptr_address)) {
+            std::alloc::dealloc(xml_chunk_ptr, layout);
+            return Err(glue_error);
+        }
 
         let xml_chunk_bytes= std::slice::from_raw_parts(xml_chunk_ptr, xml_chunk_len as usize);
-        let xml_chunk = std::str::from_utf8(xml_chunk_bytes)?.to_string();
+        let xml_chunk_res = std::str::from_utf8(xml_chunk_bytes).map(|xml_chunk| xml_chunk.to_string());
         //println!("xml_chunk={}", xml_chunk);
 
         // Deallocate memory for XML chunk
         std::alloc::dealloc(xml_chunk_ptr as *mut u8, layout);
 
-        Ok(xml_chunk)
+        Ok(xml_chunk_res.context("the spectrum XML written by ThermoRawFileParser is not valid UTF-8")?)
     }
 
-    unsafe fn _retrieve_unparsed_spectrum_data(&self) -> Result<SpectrumData> {
+    unsafe fn _retrieve_unparsed_spectrum_data(pipeline: &SpectrumWriterPipeline) -> Result<SpectrumData, StreamerError> {
 
-        let peaks_count = ThermoRawFileParser_Writer_SpectrumWrapper_getPeaksCount(self.spectrum_wrapper_ptr) as usize;
+        let peaks_count = call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_getPeaksCount(pipeline.spectrum_wrapper_ptr))? as usize;
 
         if peaks_count == 0  {
             Ok(SpectrumData { mz_list: vec![], intensity_list: vec![] })
@@ -299,7 +729,11 @@ impl RawFileStreamer {
             let intensity_ptr = std::alloc::alloc(layout) as *mut f64;
             let intensity_ptr_address = intensity_ptr as usize as i64;
 
-            ThermoRawFileParser_Writer_SpectrumWrapper_CopyDataToPointers(self.spectrum_wrapper_ptr, mz_ptr_address, intensity_ptr_address);
+            if let Err(glue_error) = call_glue(|| ThermoRawFileParser_Writer_SpectrumWrapper_CopyDataToPointers(pipeline.spectrum_wrapper_ptr, mz_ptr_address, intensity_ptr_address)) {
+                std::alloc::dealloc(mz_ptr as *mut u8, layout);
+                std::alloc::dealloc(intensity_ptr as *mut u8, layout);
+                return Err(glue_error);
+            }
 
             let mz_values: &[f64] = std::slice::from_raw_parts(mz_ptr as *const f64, peaks_count);
             let intensity_values: &[f64] = std::slice::from_raw_parts(intensity_ptr as *const f64, peaks_count);
@@ -0,0 +1,254 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::bindings::*;
+use crate::index_cache::IndexCacheLocation;
+use crate::mzml::*;
+
+/// Identifier of the `dataProcessing` entry recording the options in the meta-data of a streamer.
+pub const STREAMER_OPTIONS_DATA_PROCESSING_ID: &str = "RawFileStreamerOptions";
+
+// Thermo instruments support MS levels up to MS10
+const MAX_MS_LEVEL: u8 = 10;
+
+/// Peak picking (centroiding) applied by ThermoRawFileParser, which can be restricted to some MS levels.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeakPicking {
+    /// Peak picking is applied to all the MS levels (ThermoRawFileParser default).
+    #[default]
+    AllMsLevels,
+    /// Profile data are kept for all the MS levels (ThermoRawFileParser NoPeakPicking option).
+    Disabled,
+    /// Peak picking is only applied to the given MS levels.
+    MsLevels(Vec<u8>),
+    /// Peak picking is applied to all the MS levels except the given ones.
+    AllMsLevelsExcept(Vec<u8>),
+}
+
+impl PeakPicking {
+    pub fn is_enabled_for(&self, ms_level: u8) -> bool {
+        match self {
+            PeakPicking::AllMsLevels => true,
+            PeakPicking::Disabled => false,
+            PeakPicking::MsLevels(ms_levels) => ms_levels.contains(&ms_level),
+            PeakPicking::AllMsLevelsExcept(ms_levels) => !ms_levels.contains(&ms_level),
+        }
+    }
+
+    /// Returns true if the peak picking is enabled for at least one MS level.
+    pub fn is_enabled_for_any(&self) -> bool {
+        match self {
+            PeakPicking::AllMsLevels | PeakPicking::AllMsLevelsExcept(_) => true,
+            PeakPicking::Disabled => false,
+            PeakPicking::MsLevels(ms_levels) => !ms_levels.is_empty(),
+        }
+    }
+
+    /// Returns true if the peak picking is disabled for at least one MS level.
+    pub fn is_disabled_for_any(&self) -> bool {
+        match self {
+            PeakPicking::AllMsLevels => false,
+            PeakPicking::Disabled | PeakPicking::MsLevels(_) => true,
+            PeakPicking::AllMsLevelsExcept(ms_levels) => !ms_levels.is_empty(),
+        }
+    }
+}
+
+impl fmt::Display for PeakPicking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join_ms_levels = |ms_levels: &[u8]| ms_levels.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(",");
+
+        match self {
+            PeakPicking::AllMsLevels => write!(f, "all MS levels"),
+            PeakPicking::Disabled => write!(f, "disabled"),
+            PeakPicking::MsLevels(ms_levels) => write!(f, "MS levels {}", join_ms_levels(ms_levels)),
+            PeakPicking::AllMsLevelsExcept(ms_levels) => write!(f, "all MS levels except {}", join_ms_levels(ms_levels)),
+        }
+    }
+}
+
+/// Verbosity of the ThermoRawFileParser logs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogFormat {
+    Silent,
+    Verbose,
+    #[default]
+    Default,
+    None,
+}
+
+impl LogFormat {
+    pub(crate) fn to_thermo_log_format(self) -> ThermoRawFileParser_LogFormat {
+        match self {
+            LogFormat::Silent => ThermoRawFileParser_LogFormat_ThermoRawFileParser_LogFormat_SILENT,
+            LogFormat::Verbose => ThermoRawFileParser_LogFormat_ThermoRawFileParser_LogFormat_VERBOSE,
+            LogFormat::Default => ThermoRawFileParser_LogFormat_ThermoRawFileParser_LogFormat_DEFAULT,
+            LogFormat::None => ThermoRawFileParser_LogFormat_ThermoRawFileParser_LogFormat_NONE,
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogFormat::Silent => "silent",
+            LogFormat::Verbose => "verbose",
+            LogFormat::Default => "default",
+            LogFormat::None => "none",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Options defining how a `RawFileStreamer` opens a RAW file and converts its spectra.
+///
+/// The default options match `RawFileStreamer::new`, which uses the ThermoRawFileParser defaults,
+/// and doesn't build any scan index or index cache.
+/// The options are validated when opening the RAW file, and recorded in the `dataProcessingList` of its meta-data.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamerOptions {
+    peak_picking: PeakPicking,
+    ignore_instrument_errors: bool,
+    no_zlib_compression: bool,
+    log_format: LogFormat,
+    scan_index: bool,
+    index_cache_location: Option<IndexCacheLocation>,
+}
+
+impl StreamerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_peak_picking(mut self, peak_picking: PeakPicking) -> Self {
+        self.peak_picking = peak_picking;
+        self
+    }
+
+    /// Skips the instrument errors instead of failing (ThermoRawFileParser IgnoreInstrumentErrors option).
+    pub fn with_ignore_instrument_errors(mut self, ignore_instrument_errors: bool) -> Self {
+        self.ignore_instrument_errors = ignore_instrument_errors;
+        self
+    }
+
+    /// Disables the zlib compression of the binary arrays written by ThermoRawFileParser.
+    pub fn with_no_zlib_compression(mut self, no_zlib_compression: bool) -> Self {
+        self.no_zlib_compression = no_zlib_compression;
+        self
+    }
+
+    pub fn with_log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = log_format;
+        self
+    }
+
+    /// Builds the `ScanIndex` of the file when opening it, without persisting it (see `with_index_cache`).
+    pub fn with_scan_index(mut self) -> Self {
+        self.scan_index = true;
+        self
+    }
+
+    /// Loads the meta-data and the `ScanIndex` from an index cache (see `RawFileStreamer::new_with_index_cache`).
+    pub fn with_index_cache(mut self, cache_location: IndexCacheLocation) -> Self {
+        self.scan_index = true;
+        self.index_cache_location = Some(cache_location);
+        self
+    }
+
+    pub fn get_peak_picking(&self) -> &PeakPicking {
+        &self.peak_picking
+    }
+
+    pub fn is_ignoring_instrument_errors(&self) -> bool {
+        self.ignore_instrument_errors
+    }
+
+    pub fn is_zlib_compression_disabled(&self) -> bool {
+        self.no_zlib_compression
+    }
+
+    pub fn get_log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
+    pub fn is_scan_index_enabled(&self) -> bool {
+        self.scan_index
+    }
+
+    pub fn get_index_cache_location(&self) -> Option<&IndexCacheLocation> {
+        self.index_cache_location.as_ref()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let PeakPicking::MsLevels(ms_levels) | PeakPicking::AllMsLevelsExcept(ms_levels) = &self.peak_picking {
+            for (idx, ms_level) in ms_levels.iter().enumerate() {
+                if *ms_level < 1 || *ms_level > MAX_MS_LEVEL {
+                    bail!("invalid MS level {} in peak picking options (expected a value between 1 and {})", ms_level, MAX_MS_LEVEL);
+                }
+                if ms_levels[..idx].contains(ms_level) {
+                    bail!("MS level {} is defined twice in peak picking options", ms_level);
+                }
+            }
+        }
+
+        if let Some(IndexCacheLocation::Directory(cache_dir)) = self.index_cache_location.as_ref() {
+            if cache_dir.as_os_str().is_empty() {
+                bail!("the index cache directory path is empty");
+            }
+            if cache_dir.is_file() {
+                bail!("the index cache directory '{}' is an existing file", cache_dir.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `dataProcessing` entry describing the options affecting the conversion of the spectra.
+    pub fn to_data_processing(&self, software_ref: &str) -> DataProcessing {
+        let user_param = |name: &str, value: String, r#type: &str| UserParam {
+            name: name.to_string(),
+            value,
+            r#type: r#type.to_string(),
+        };
+
+        let cv_params = if self.peak_picking.is_enabled_for_any() {
+            vec![CvParam {
+                cv_ref: "MS".to_string(),
+                accession: PEAK_PICKING_CV_ACCESSION.to_string(),
+                name: "peak picking".to_string(),
+                value: Some(String::new()),
+                ..Default::default()
+            }]
+        } else {
+            vec![]
+        };
+
+        DataProcessing {
+            id: STREAMER_OPTIONS_DATA_PROCESSING_ID.to_string(),
+            processing_method: ProcessingMethod {
+                order: "1".to_string(),
+                software_ref: software_ref.to_string(),
+                cv_params,
+                user_params: vec![
+                    user_param("peak picking", self.peak_picking.to_string(), "xsd:string"),
+                    user_param("ignore instrument errors", self.ignore_instrument_errors.to_string(), "xsd:boolean"),
+                    user_param("no zlib compression", self.no_zlib_compression.to_string(), "xsd:boolean"),
+                    user_param("log format", self.log_format.to_string(), "xsd:string"),
+                ],
+            },
+        }
+    }
+
+    /// Adds (or replaces) the `dataProcessing` entry of the options in the given meta-data.
+    pub fn record_in_metadata(&self, meta_data: &mut MzMLMetaData) {
+        let software_ref = meta_data.software_list.software_entries.first()
+            .map(|software| software.id.clone())
+            .unwrap_or_else(|| "ThermoRawFileParser".to_string());
+
+        let data_processing_list = &mut meta_data.data_processing_list;
+        data_processing_list.data_processings.retain(|dp| dp.id != STREAMER_OPTIONS_DATA_PROCESSING_ID);
+        data_processing_list.data_processings.push(self.to_data_processing(&software_ref));
+        data_processing_list.count = data_processing_list.data_processings.len().to_string();
+    }
+}
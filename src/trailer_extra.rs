@@ -0,0 +1,89 @@
+//! Thermo trailer extra values of a scan.
+//!
+//! ThermoRawFileParser doesn't expose the trailer extra records directly: they are only reachable through
+//! the "[Thermo Trailer Extra]" user params it writes in the mzML scan element. The status log and the tune data
+//! of a run aren't exported at all by ThermoRawFileParser, so they can't be accessed through its glue.
+
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::mzml::{UserParam, THERMO_TRAILER_EXTRA_PREFIX};
+
+/// Value of a trailer extra record, parsed as a number when possible.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrailerValue {
+    Number(f64),
+    Text(String),
+}
+
+impl TrailerValue {
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        match value.parse::<f64>() {
+            Ok(number) => TrailerValue::Number(number),
+            Err(_) => TrailerValue::Text(value.to_string()),
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            TrailerValue::Number(number) => Some(*number),
+            TrailerValue::Text(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for TrailerValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrailerValue::Number(number) => write!(f, "{}", number),
+            TrailerValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Trailer extra records of a scan, in order of appearance.
+///
+/// The labels are stored without the Thermo trailing colon (e.g. "Monoisotopic M/Z").
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrailerExtra {
+    entries: Vec<(String, TrailerValue)>,
+}
+
+impl TrailerExtra {
+    /// Collects the "[Thermo Trailer Extra]" user params of a mzML scan.
+    pub fn from_user_params(user_params: &[UserParam]) -> Self {
+        let entries = user_params.iter().filter_map(|user_param| {
+            let label = user_param.name.strip_prefix(THERMO_TRAILER_EXTRA_PREFIX)?;
+            Some((_normalize_label(label).to_string(), TrailerValue::parse(&user_param.value)))
+        }).collect();
+
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the value of a record, the label being matched with or without its trailing colon.
+    pub fn get(&self, label: &str) -> Option<&TrailerValue> {
+        let label = _normalize_label(label);
+        self.entries.iter().find(|(entry_label, _)| entry_label == label).map(|(_, value)| value)
+    }
+
+    pub fn get_number(&self, label: &str) -> Option<f64> {
+        self.get(label)?.as_number()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TrailerValue)> {
+        self.entries.iter().map(|(label, value)| (label.as_str(), value))
+    }
+}
+
+fn _normalize_label(label: &str) -> &str {
+    label.trim().trim_end_matches(':').trim_end()
+}
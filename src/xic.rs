@@ -0,0 +1,258 @@
+use anyhow::*;
+use serde::{Serialize, Deserialize};
+use std::ffi::CString;
+use std::fmt;
+
+use crate::bindings::*;
+use crate::chromatogram::{Chromatogram, ChromatogramKind};
+use crate::mono::call_glue;
+use crate::mzml::TimeUnit;
+use crate::scan_filter::ScanFilter;
+use crate::scan_index::ScanIndexEntry;
+
+/// Scan filter used by ThermoRawFileParser when an XIC request doesn't define any (MS1 scans only).
+pub const DEFAULT_XIC_FILTER: &str = "ms";
+
+// Residues supported by the ThermoRawFileParser Peptide class
+const AMINO_ACIDS: &str = "ACDEFGHIKLMNPQRSTVWY";
+
+/// Tolerance applied around a target m/z value (ThermoRawFileParser tolerance units).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MzTolerance {
+    Ppm(f64),
+    Da(f64),
+    Amu(f64),
+    Mmu(f64),
+}
+
+impl MzTolerance {
+    pub fn to_mz_range(&self, mz: f64) -> (f64, f64) {
+        let mz_tol = match self {
+            MzTolerance::Ppm(tol) => mz * tol / 1e6,
+            MzTolerance::Da(tol) | MzTolerance::Amu(tol) => *tol,
+            MzTolerance::Mmu(tol) => tol / 1000.0,
+        };
+
+        (mz - mz_tol, mz + mz_tol)
+    }
+}
+
+impl fmt::Display for MzTolerance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MzTolerance::Ppm(tol) => write!(f, "{} ppm", tol),
+            MzTolerance::Da(tol) => write!(f, "{} Da", tol),
+            MzTolerance::Amu(tol) => write!(f, "{} amu", tol),
+            MzTolerance::Mmu(tol) => write!(f, "{} mmu", tol),
+        }
+    }
+}
+
+/// Target of an XIC request, following the ThermoRawFileParser XIC JSON input units.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum XicTarget {
+    Mz { mz: f64, tolerance: MzTolerance },
+    MzRange { mz_start: f64, mz_end: f64 },
+    /// Unmodified peptide, whose m/z is computed by ThermoRawFileParser for the given charge
+    Peptide { sequence: String, charge: i32, tolerance: MzTolerance },
+}
+
+/// Request of an extracted ion chromatogram, see `RawFileStreamer::extract_xics`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XicRequest {
+    target: XicTarget,
+    filter: Option<String>,
+    rt_range: Option<(f64, f64)>,
+}
+
+impl XicRequest {
+    pub fn mz(mz: f64, tolerance: MzTolerance) -> Self {
+        Self::new(XicTarget::Mz { mz, tolerance })
+    }
+
+    pub fn mz_range(mz_start: f64, mz_end: f64) -> Self {
+        Self::new(XicTarget::MzRange { mz_start, mz_end })
+    }
+
+    pub fn peptide(sequence: &str, charge: i32, tolerance: MzTolerance) -> Self {
+        Self::new(XicTarget::Peptide { sequence: sequence.to_string(), charge, tolerance })
+    }
+
+    fn new(target: XicTarget) -> Self {
+        Self { target, filter: None, rt_range: None }
+    }
+
+    /// Restricts the XIC to the scans matching a Thermo scan filter (`DEFAULT_XIC_FILTER` if not defined).
+    pub fn with_filter(mut self, filter: &str) -> Self {
+        self.filter = Some(filter.to_string());
+        self
+    }
+
+    /// Restricts the XIC to a retention time window (inclusive bounds, in minutes).
+    pub fn with_rt_range(mut self, min_rt: f64, max_rt: f64) -> Self {
+        self.rt_range = Some((min_rt, max_rt));
+        self
+    }
+
+    pub fn get_target(&self) -> &XicTarget {
+        &self.target
+    }
+
+    pub fn get_filter(&self) -> &str {
+        self.filter.as_deref().unwrap_or(DEFAULT_XIC_FILTER)
+    }
+
+    pub fn get_rt_range(&self) -> Option<(f64, f64)> {
+        self.rt_range
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match &self.target {
+            XicTarget::Mz { mz, tolerance } => {
+                if *mz <= 0.0 {
+                    bail!("invalid XIC target m/z {}", mz);
+                }
+                _validate_tolerance(tolerance)?;
+            }
+            XicTarget::MzRange { mz_start, mz_end } => {
+                if *mz_start < 0.0 || mz_end <= mz_start {
+                    bail!("invalid XIC m/z range [{}, {}]", mz_start, mz_end);
+                }
+            }
+            XicTarget::Peptide { sequence, charge, tolerance } => {
+                if sequence.is_empty() || !sequence.chars().all(|aa| AMINO_ACIDS.contains(aa)) {
+                    bail!("invalid XIC peptide sequence '{}' (only unmodified residues are supported)", sequence);
+                }
+                if *charge < 1 {
+                    bail!("invalid XIC peptide charge {}", charge);
+                }
+                _validate_tolerance(tolerance)?;
+            }
+        }
+
+        self.get_filter().parse::<ScanFilter>()?;
+
+        if let Some((min_rt, max_rt)) = self.rt_range {
+            if max_rt < min_rt {
+                bail!("invalid XIC retention time range [{}, {}]", min_rt, max_rt);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the chromatogram id describing the request (e.g. "XIC 445.12 ± 10 ppm").
+    pub fn get_id(&self) -> String {
+        match &self.target {
+            XicTarget::Mz { mz, tolerance } => format!("XIC {} ± {}", mz, tolerance),
+            XicTarget::MzRange { mz_start, mz_end } => format!("XIC {}-{}", mz_start, mz_end),
+            XicTarget::Peptide { sequence, charge, tolerance } => format!("XIC {}/{} ± {}", sequence, charge, tolerance),
+        }
+    }
+
+    /// Returns true if the scan matches the filter and the retention time range of the request (see `filter_matches`).
+    pub fn matches_scan(&self, scan_entry: &ScanIndexEntry) -> bool {
+        self.matches_retention_time(scan_entry.retention_time) && filter_matches(self.get_filter(), &scan_entry.filter_string)
+    }
+
+    pub fn matches_retention_time(&self, retention_time: f64) -> bool {
+        self.rt_range.map(|(min_rt, max_rt)| retention_time >= min_rt && retention_time <= max_rt).unwrap_or(true)
+    }
+
+    /// Computes the m/z window of the request (the m/z of peptides being computed by ThermoRawFileParser).
+    pub(crate) fn resolve_mz_range(&self) -> Result<(f64, f64)> {
+        self.validate()?;
+
+        Ok(match &self.target {
+            XicTarget::Mz { mz, tolerance } => tolerance.to_mz_range(*mz),
+            XicTarget::MzRange { mz_start, mz_end } => (*mz_start, *mz_end),
+            XicTarget::Peptide { sequence, charge, tolerance } => tolerance.to_mz_range(unsafe { _compute_peptide_mz(sequence, *charge)? }),
+        })
+    }
+}
+
+/// Returns true if the filter string of a scan matches a (possibly partial) Thermo scan filter, see `ScanFilter::matches`.
+///
+/// For instance "ms" matches "FTMS + p ESI Full ms [400.00-2000.00]", but not "ITMS + c ESI d Full ms2 445.12@cid35.00 [110.00-460.00]",
+/// which is matched by "ms2" and "ITMS d Full ms2 445.1". Invalid filters don't match any scan.
+///
+/// Note: this follows the RawFileReader filter semantics used by the ThermoRawFileParser XIC reader, but the codes are
+/// case sensitive (as written in the filter strings, e.g. "Full" and "ITMS").
+pub fn filter_matches(filter: &str, scan_filter_string: &str) -> bool {
+    match (filter.parse::<ScanFilter>(), scan_filter_string.parse::<ScanFilter>()) {
+        (Result::Ok(filter), Result::Ok(scan_filter)) => filter.matches(&scan_filter),
+        _ => false,
+    }
+}
+
+/// Accumulates the XIC points of a request over the scans of a file.
+pub(crate) struct XicBuilder {
+    mz_range: (f64, f64),
+    chromatogram: Chromatogram,
+}
+
+impl XicBuilder {
+    pub(crate) fn new(request: &XicRequest, mz_range: (f64, f64)) -> Self {
+        Self {
+            mz_range,
+            chromatogram: Chromatogram {
+                id: request.get_id(),
+                kind: ChromatogramKind::SelectedIonCurrent,
+                ms_levels: None,
+                time_unit: TimeUnit::Minute,
+                mz_range: Some(mz_range),
+                transition: None,
+                scan_numbers: vec![],
+                times: vec![],
+                intensities: vec![],
+            },
+        }
+    }
+
+    pub(crate) fn get_mz_range(&self) -> (f64, f64) {
+        self.mz_range
+    }
+
+    /// Adds the summed intensity of the peaks of the m/z window (the masses being sorted in ascending order),
+    /// the peaks before `start_idx` being known to be below the window.
+    ///
+    /// Returns the index of the first peak of the window, which is the start index of the next window when the targets are sorted by m/z.
+    pub(crate) fn add_scan(&mut self, scan_entry: &ScanIndexEntry, masses: &[f64], intensities: &[f64], start_idx: usize) -> usize {
+        let (mz_start, mz_end) = self.mz_range;
+        let first_idx = start_idx + masses[start_idx..].partition_point(|mz| *mz < mz_start);
+        let last_idx = first_idx + masses[first_idx..].partition_point(|mz| *mz <= mz_end);
+
+        self.chromatogram.scan_numbers.push(scan_entry.scan_number);
+        self.chromatogram.times.push(scan_entry.retention_time);
+        self.chromatogram.intensities.push(intensities[first_idx..last_idx].iter().sum());
+
+        first_idx
+    }
+
+    pub(crate) fn finish(self) -> Chromatogram {
+        self.chromatogram
+    }
+}
+
+fn _validate_tolerance(tolerance: &MzTolerance) -> Result<()> {
+    let (MzTolerance::Ppm(tol) | MzTolerance::Da(tol) | MzTolerance::Amu(tol) | MzTolerance::Mmu(tol)) = tolerance;
+    if *tol <= 0.0 {
+        bail!("invalid XIC m/z tolerance {}", tolerance);
+    }
+
+    Ok(())
+}
+
+unsafe fn _compute_peptide_mz(sequence: &str, charge: i32) -> Result<f64> {
+    let sequence_cstr = CString::new(sequence)?;
+    let peptide_ptr = call_glue(|| ThermoRawFileParser_Util_Peptide_new_1(sequence_cstr.as_ptr()))
+        .with_context(|| format!("can't create peptide '{}'", sequence))?;
+    if peptide_ptr.is_null() {
+        bail!("can't create peptide '{}'", sequence);
+    }
+
+    let mz_res = call_glue(|| ThermoRawFileParser_Util_Peptide_GetMz(peptide_ptr, charge));
+    mono_embeddinator_destroy_object(peptide_ptr);
+
+    Ok(mz_res?)
+}
@@ -0,0 +1,365 @@
+use anyhow::{bail, Context, Result};
+use serde::{Serialize, Deserialize};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::chromatogram::Chromatogram;
+use crate::error::StreamerError;
+use crate::mono::MONO_EMBEDDINATOR;
+use crate::native_scan::NativeScan;
+use crate::scan_filter::ScanFilter;
+use crate::scan_index::ScanIndexEntry;
+use crate::streamer::RawFileStreamer;
+use crate::xic::{MzTolerance, XicBuilder, XicRequest};
+
+// Columns of the TSV target lists
+const TSV_COLUMN_NAMES: [&str; 10] = ["mz", "tolerance", "tolerance_unit", "mz_start", "mz_end", "rt_start", "rt_end", "sequence", "charge", "scan_filter"];
+
+/// Item of the ThermoRawFileParser XIC JSON input (`xic` command), also used for the rows of TSV target lists.
+///
+/// A target is defined by `mz` and `tolerance`, by `mz_start` and `mz_end`, or by `sequence` and `tolerance`,
+/// retention times being expressed in minutes, and the `charge` of peptide targets being 1 if not defined.
+/// Like ThermoRawFileParser, unknown JSON properties are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct XicTargetRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mz: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    /// "ppm" (default), "amu", "mmu" or "da"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance_unit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mz_start: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mz_end: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rt_start: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rt_end: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charge: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_filter: Option<String>,
+}
+
+impl XicTargetRecord {
+    pub fn to_request(&self) -> Result<XicRequest, StreamerError> {
+        self._to_request().map_err(|e| StreamerError::invalid_input("XIC target", e))
+    }
+
+    fn _to_request(&self) -> Result<XicRequest> {
+        let request = match (self.mz, self.mz_start, self.mz_end, self.sequence.as_ref(), self.tolerance) {
+            (Some(mz), None, None, None, Some(tolerance)) => XicRequest::mz(mz, self._get_tolerance(tolerance)?),
+            (None, Some(mz_start), Some(mz_end), None, None) => XicRequest::mz_range(mz_start, mz_end),
+            (None, None, None, Some(sequence), Some(tolerance)) => XicRequest::peptide(sequence, self.charge.unwrap_or(1), self._get_tolerance(tolerance)?),
+            _ => bail!("use m/z and tolerance, m/z start and m/z end or sequence and tolerance, not a combination"),
+        };
+
+        let request = match (self.rt_start, self.rt_end) {
+            (None, None) => request,
+            (rt_start, rt_end) => request.with_rt_range(rt_start.unwrap_or(0.0), rt_end.unwrap_or(f64::INFINITY)),
+        };
+
+        let request = match self.scan_filter.as_deref() {
+            Some(filter) => request.with_filter(filter),
+            None => request,
+        };
+
+        request.validate()?;
+
+        Ok(request)
+    }
+
+    fn _get_tolerance(&self, tolerance: f64) -> Result<MzTolerance> {
+        let tolerance_unit = self.tolerance_unit.as_deref().unwrap_or("ppm");
+        Ok(match tolerance_unit.to_lowercase().as_str() {
+            "ppm" => MzTolerance::Ppm(tolerance),
+            "amu" => MzTolerance::Amu(tolerance),
+            "mmu" => MzTolerance::Mmu(tolerance),
+            "da" => MzTolerance::Da(tolerance),
+            _ => bail!("unknown tolerance unit '{}'", tolerance_unit),
+        })
+    }
+}
+
+/// Peak detected around the most intense point of a chromatogram.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XicPeak {
+    pub apex_time: f64,
+    pub apex_intensity: f64,
+    pub start_time: f64,
+    pub end_time: f64,
+    /// Trapezoidal area between the start and end times (intensity x chromatogram time unit)
+    pub area: f64,
+    pub points_across_peak: usize,
+}
+
+impl XicPeak {
+    /// Detects the peak of the most intense point, which extends on both sides as long as the intensities decrease without reaching zero.
+    pub fn from_chromatogram(chromatogram: &Chromatogram) -> Option<Self> {
+        let intensities = &chromatogram.intensities;
+        let times = &chromatogram.times;
+
+        let (apex_idx, apex_intensity) = intensities.iter().copied().enumerate()
+            .max_by(|(_, i1), (_, i2)| i1.total_cmp(i2))?;
+        if apex_intensity <= 0.0 {
+            return None;
+        }
+
+        let mut start_idx = apex_idx;
+        while start_idx > 0 && intensities[start_idx - 1] > 0.0 && intensities[start_idx - 1] <= intensities[start_idx] {
+            start_idx -= 1;
+        }
+        let mut end_idx = apex_idx;
+        while end_idx + 1 < intensities.len() && intensities[end_idx + 1] > 0.0 && intensities[end_idx + 1] <= intensities[end_idx] {
+            end_idx += 1;
+        }
+
+        let area = (start_idx..end_idx)
+            .map(|idx| (times[idx + 1] - times[idx]) * (intensities[idx] + intensities[idx + 1]) / 2.0)
+            .sum();
+
+        Some(Self {
+            apex_time: times[apex_idx],
+            apex_intensity,
+            start_time: times[start_idx],
+            end_time: times[end_idx],
+            area,
+            points_across_peak: end_idx - start_idx + 1,
+        })
+    }
+}
+
+/// Chromatogram and peak of a target of a `XicBatch`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XicBatchResult {
+    pub request: XicRequest,
+    pub chromatogram: Chromatogram,
+    /// None if the target was never observed
+    pub peak: Option<XicPeak>,
+}
+
+/// List of XIC targets extracted together in a single pass over the scans of a RAW file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XicBatch {
+    requests: Vec<XicRequest>,
+}
+
+impl XicBatch {
+    pub fn new(requests: Vec<XicRequest>) -> Self {
+        Self { requests }
+    }
+
+    /// Reads a JSON (".json" extension) or TSV target list.
+    pub fn from_file<P: AsRef<Path>>(targets_file_path: P) -> Result<Self, StreamerError> {
+        let targets_file_path = targets_file_path.as_ref();
+        let targets_str = std::fs::read_to_string(targets_file_path)
+            .map_err(|e| StreamerError::io(format!("can't read XIC targets file '{}'", targets_file_path.display()), e))?;
+
+        let is_json = targets_file_path.extension().map(|ext| ext.eq_ignore_ascii_case("json")).unwrap_or(false);
+        if is_json {
+            Self::from_json_str(&targets_str)
+        } else {
+            Self::from_tsv_str(&targets_str)
+        }
+    }
+
+    /// Parses an array of `XicTargetRecord`, as expected by the ThermoRawFileParser `xic` command.
+    pub fn from_json_str(json_str: &str) -> Result<Self, StreamerError> {
+        Self::_from_json_str(json_str).map_err(|e| StreamerError::invalid_input("XIC targets", e))
+    }
+
+    /// Parses a tab-separated target list, whose header names columns after the `XicTargetRecord` fields
+    /// (empty cells being undefined values, and lines starting with '#' being ignored).
+    pub fn from_tsv_str(tsv_str: &str) -> Result<Self, StreamerError> {
+        Self::_from_tsv_str(tsv_str).map_err(|e| StreamerError::invalid_input("XIC targets", e))
+    }
+
+    fn _from_json_str(json_str: &str) -> Result<Self> {
+        let records: Vec<XicTargetRecord> = serde_json::from_str(json_str).context("invalid XIC JSON input")?;
+        let requests = records.iter().enumerate()
+            .map(|(idx, record)| record._to_request().with_context(|| format!("invalid XIC target {}", idx + 1)))
+            .collect::<Result<Vec<XicRequest>>>()?;
+
+        Ok(Self::new(requests))
+    }
+
+    fn _from_tsv_str(tsv_str: &str) -> Result<Self> {
+        let mut lines = tsv_str.lines().enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+        let column_names: Vec<&str> = match lines.next() {
+            Some((_, header)) => header.split('\t').map(|name| name.trim()).collect(),
+            None => return Ok(Self::default()),
+        };
+        if let Some(unknown_column_name) = column_names.iter().find(|name| !TSV_COLUMN_NAMES.contains(name)) {
+            bail!("unknown XIC targets column '{}' (expected {})", unknown_column_name, TSV_COLUMN_NAMES.join(", "));
+        }
+
+        let mut requests = Vec::new();
+        for (line_idx, line) in lines {
+            let line_number = line_idx + 1;
+            let values: Vec<&str> = line.split('\t').map(|value| value.trim()).collect();
+            if values.len() > column_names.len() {
+                bail!("XIC targets line {} has {} columns but the header has {}", line_number, values.len(), column_names.len());
+            }
+
+            let mut record = XicTargetRecord::default();
+            for (column_name, value) in column_names.iter().zip(values) {
+                if !value.is_empty() {
+                    _set_record_field(&mut record, column_name, value)
+                        .with_context(|| format!("invalid XIC target at line {}", line_number))?;
+                }
+            }
+
+            requests.push(record._to_request().with_context(|| format!("invalid XIC target at line {}", line_number))?);
+        }
+
+        Ok(Self::new(requests))
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn get_requests(&self) -> &[XicRequest] {
+        &self.requests
+    }
+
+    /// Extracts the chromatograms and peaks of all the targets, returned in the order of the target list.
+    ///
+    /// The targets are sorted by m/z, so that the m/z windows are located with a single walk through the peaks of each scan.
+    /// The peaks of a scan are loaded only once, and only if the scan matches the filter and retention time range of a target.
+    pub fn extract(&self, streamer: &RawFileStreamer) -> Result<Vec<XicBatchResult>, StreamerError> {
+        Ok(self.extract_chromatograms(streamer)?.into_iter().zip(self.requests.iter())
+            .map(|(chromatogram, request)| XicBatchResult {
+                request: request.clone(),
+                peak: XicPeak::from_chromatogram(&chromatogram),
+                chromatogram,
+            })
+            .collect())
+    }
+
+    /// Same as `extract` without the peak detection.
+    pub fn extract_chromatograms(&self, streamer: &RawFileStreamer) -> Result<Vec<Chromatogram>, StreamerError> {
+        self._extract_chromatograms(streamer).map_err(StreamerError::from)
+    }
+
+    fn _extract_chromatograms(&self, streamer: &RawFileStreamer) -> Result<Vec<Chromatogram>> {
+        MONO_EMBEDDINATOR.lock().unwrap().check_availability()?;
+
+        let mut xic_builders = self.requests.iter()
+            .map(|request| Ok(XicBuilder::new(request, request.resolve_mz_range()?)))
+            .collect::<Result<Vec<XicBuilder>>>()?;
+
+        let mut sorted_indices: Vec<usize> = (0..xic_builders.len()).collect();
+        sorted_indices.sort_by(|idx1, idx2| xic_builders[*idx1].get_mz_range().0.total_cmp(&xic_builders[*idx2].get_mz_range().0));
+
+        // Each distinct scan filter is parsed once, and matched once per scan
+        let mut distinct_filters: Vec<&str> = Vec::new();
+        let filter_indices: Vec<usize> = self.requests.iter().map(|request| {
+            let filter = request.get_filter();
+            distinct_filters.iter().position(|f| *f == filter).unwrap_or_else(|| {
+                distinct_filters.push(filter);
+                distinct_filters.len() - 1
+            })
+        }).collect();
+        let parsed_filters = distinct_filters.iter().map(|filter| filter.parse())
+            .collect::<Result<Vec<ScanFilter>>>()?;
+
+        let mut matching_indices = Vec::with_capacity(sorted_indices.len());
+        let mut add_scan = |scan_entry: &ScanIndexEntry, loaded_scan_opt: Option<NativeScan>| -> Result<()> {
+            let matching_filters: Vec<bool> = match scan_entry.filter_string.parse::<ScanFilter>() {
+                Result::Ok(scan_filter) => parsed_filters.iter().map(|filter| filter.matches(&scan_filter)).collect(),
+                Err(_) => vec![false; parsed_filters.len()],
+            };
+
+            matching_indices.clear();
+            matching_indices.extend(sorted_indices.iter().copied().filter(|idx| {
+                matching_filters[filter_indices[*idx]] && self.requests[*idx].matches_retention_time(scan_entry.retention_time)
+            }));
+            if matching_indices.is_empty() {
+                return Ok(());
+            }
+
+            let scan = match loaded_scan_opt {
+                Some(scan) => scan,
+                None => streamer.get_native_scan(scan_entry.scan_number)
+                    .with_context(|| format!("can't read peaks of scan {}", scan_entry.scan_number))?,
+            };
+            let mut start_idx = 0;
+            for idx in matching_indices.iter() {
+                start_idx = xic_builders[*idx].add_scan(scan_entry, &scan.masses, &scan.intensities, start_idx);
+            }
+
+            Ok(())
+        };
+
+        match streamer.get_scan_index() {
+            Some(scan_index) => {
+                for scan_entry in scan_index.get_entries() {
+                    add_scan(scan_entry, None)?;
+                }
+            }
+            // Without a scan index, each scan is read once with its peaks (instead of writing its header then its peaks)
+            None => {
+                for scan_number in streamer.get_first_scan_number() ..= streamer.get_last_scan_number() {
+                    let scan = streamer.get_native_scan(scan_number)
+                        .with_context(|| format!("can't read scan {}", scan_number))?;
+                    add_scan(&ScanIndexEntry::from_native_scan(&scan), Some(scan))?;
+                }
+            }
+        }
+
+        Ok(xic_builders.into_iter().map(|b| b.finish()).collect())
+    }
+}
+
+/// Writes a tab-separated summary of the results, with one line per target.
+pub fn write_xic_summary<W: Write>(results: &[XicBatchResult], writer: W) -> Result<W, StreamerError> {
+    _write_xic_summary(results, writer).map_err(|e| StreamerError::export("XIC summary", e))
+}
+
+fn _write_xic_summary<W: Write>(results: &[XicBatchResult], writer: W) -> Result<W> {
+    let mut writer = BufWriter::new(writer);
+    writeln!(writer, "id\tscan_filter\tmz_start\tmz_end\tpoints\tapex_rt\tapex_intensity\tarea\tpoints_across_peak")?;
+
+    for result in results {
+        let chromatogram = &result.chromatogram;
+        let (mz_start, mz_end) = chromatogram.mz_range.unwrap_or_default();
+        write!(writer, "{}\t{}\t{}\t{}\t{}", chromatogram.id, result.request.get_filter(), mz_start, mz_end, chromatogram.len())?;
+        match result.peak.as_ref() {
+            Some(peak) => writeln!(writer, "\t{}\t{}\t{}\t{}", peak.apex_time, peak.apex_intensity, peak.area, peak.points_across_peak)?,
+            None => writeln!(writer, "\t\t\t\t0")?,
+        }
+    }
+
+    writer.into_inner().map_err(|e| e.into_error()).context("can't flush XIC summary")
+}
+
+fn _set_record_field(record: &mut XicTargetRecord, column_name: &str, value: &str) -> Result<()> {
+    let parse_f64 = |value: &str| value.parse::<f64>().with_context(|| format!("invalid {} '{}'", column_name, value));
+
+    match column_name {
+        "mz" => record.mz = Some(parse_f64(value)?),
+        "tolerance" => record.tolerance = Some(parse_f64(value)?),
+        "tolerance_unit" => record.tolerance_unit = Some(value.to_string()),
+        "mz_start" => record.mz_start = Some(parse_f64(value)?),
+        "mz_end" => record.mz_end = Some(parse_f64(value)?),
+        "rt_start" => record.rt_start = Some(parse_f64(value)?),
+        "rt_end" => record.rt_end = Some(parse_f64(value)?),
+        "sequence" => record.sequence = Some(value.to_string()),
+        "charge" => record.charge = Some(value.parse().with_context(|| format!("invalid charge '{}'", value))?),
+        "scan_filter" => record.scan_filter = Some(value.to_string()),
+        _ => bail!("unknown XIC targets column '{}'", column_name),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+// Directory where build.rs copies the ThermoRawFileParser assemblies
+pub const RAW_FILE_PARSER_PATH_STR: &str =
+    if cfg!(debug_assertions) {
+        "./target/debug/rawfileparser"
+    } else {
+        "./target/release/rawfileparser"
+    };
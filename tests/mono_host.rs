@@ -0,0 +1,43 @@
+// The host configures Mono on its own thread, which can only be done once per process
+mod common;
+
+use thermostreaming::*;
+use common::RAW_FILE_PARSER_PATH_STR;
+
+#[test]
+fn get_spectra_from_any_thread() {
+    let host = MonoHost::start(RAW_FILE_PARSER_PATH_STR).expect("Mono host start failed");
+    let handle = host.open("./resources/small.RAW").expect("handle creation failed");
+
+    let first_scan_number = handle.get_first_scan_number();
+    let last_scan_number = handle.get_last_scan_number();
+    let mid_scan_number = (first_scan_number + last_scan_number) / 2;
+
+    let total_n_peaks: usize = std::thread::scope(|thread_scope| {
+        let handle_ref = &handle;
+        let worker_threads = [(first_scan_number, mid_scan_number), (mid_scan_number + 1, last_scan_number)].map(|(first, last)| {
+            thread_scope.spawn(move || {
+                (first ..= last).map(|s_num| {
+                    handle_ref.get_spectrum_data(s_num).expect("get_spectrum_data failed").mz_list.len()
+                }).sum::<usize>()
+            })
+        });
+
+        worker_threads.into_iter().map(|t| t.join().unwrap()).sum()
+    });
+
+    assert_eq!(total_n_peaks, 47971, "inconsistency between expected and obtained total number of peaks");
+
+    // The typed accessors are forwarded as well
+    let n_scans = (last_scan_number - first_scan_number + 1) as usize;
+    assert_eq!(handle.tic().expect("tic failed").len(), n_scans, "one TIC point is expected per scan");
+
+    let spectra = handle.spectra().with_scan_range(first_scan_number ..= first_scan_number + 4).metadata_only().into_iter();
+    assert_eq!(spectra.len(), 5);
+    for spectrum_res in spectra {
+        assert!(spectrum_res.expect("spectrum loading failed").data.mz_list.is_empty());
+    }
+
+    assert!(handle.get_native_scan(first_scan_number).is_ok());
+    assert!(handle.trailer_extra(first_scan_number).is_ok());
+}
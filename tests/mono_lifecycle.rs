@@ -0,0 +1,39 @@
+// The Mono runtime is configured and disposed once per process, thus this scenario has its own test binary
+mod common;
+
+use thermostreaming::*;
+use common::RAW_FILE_PARSER_PATH_STR;
+
+#[test]
+fn dispose_mono_runtime() {
+    assert!(matches!(MONO_EMBEDDINATOR.lock().unwrap().dispose(), Err(StreamerError::MonoNotConfigured)));
+    assert!(matches!(MonoRuntime::acquire(), Err(StreamerError::MonoNotConfigured)));
+
+    // A failed discovery leaves Mono unconfigured
+    let err = MONO_EMBEDDINATOR.lock().unwrap().configure_with_discovery(&MonoDiscovery::new().with_assembly_dir("./resources")).unwrap_err();
+    assert!(matches!(err, StreamerError::DiscoveryFailed { .. }));
+
+    MONO_EMBEDDINATOR.lock().unwrap().configure(RAW_FILE_PARSER_PATH_STR).expect("e4k config failed");
+
+    let streamer = RawFileStreamer::new("./resources/small.RAW").expect("streamer creation failed");
+    let runtime = MonoRuntime::acquire().expect("can't acquire the Mono runtime");
+    assert_eq!(MonoRuntime::get_guard_count(), 2);
+
+    // The runtime can't be disposed while it is used by a streamer or held by a guard
+    assert!(matches!(MONO_EMBEDDINATOR.lock().unwrap().dispose(), Err(StreamerError::MonoRuntimeInUse { guard_count: 2 })));
+    assert!(streamer.get_spectrum_data(1).is_ok());
+
+    streamer.dispose();
+    let runtime_clone = runtime.clone();
+    drop(runtime);
+    assert!(matches!(MONO_EMBEDDINATOR.lock().unwrap().dispose(), Err(StreamerError::MonoRuntimeInUse { guard_count: 1 })));
+    drop(runtime_clone);
+    assert_eq!(MonoRuntime::get_guard_count(), 0);
+
+    // The disposal can't be repeated, and the runtime can't be used anymore
+    MONO_EMBEDDINATOR.lock().unwrap().dispose().expect("Mono runtime disposal failed");
+    assert!(matches!(MONO_EMBEDDINATOR.lock().unwrap().dispose(), Err(StreamerError::MonoDisposed)));
+    assert!(matches!(MonoRuntime::acquire(), Err(StreamerError::MonoDisposed)));
+
+    assert!(matches!(RawFileStreamer::new("./resources/small.RAW"), Err(StreamerError::MonoDisposed)));
+}
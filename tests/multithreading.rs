@@ -0,0 +1,16 @@
+// Thread attachment is never enabled in this test binary, unlike in the unit tests
+mod common;
+
+use thermostreaming::*;
+use common::RAW_FILE_PARSER_PATH_STR;
+
+#[test]
+fn multithreading_fails() {
+    MONO_EMBEDDINATOR.lock().unwrap().configure(RAW_FILE_PARSER_PATH_STR).expect("e4k config failed");
+    assert!(MONO_EMBEDDINATOR.lock().unwrap().check_availability().is_ok());
+
+    std::thread::spawn(|| {
+        assert!(matches!(MONO_EMBEDDINATOR.lock().unwrap().check_availability(), Err(StreamerError::WrongThread { .. })));
+        assert!(MonoRuntime::acquire().is_err());
+    }).join().unwrap();
+}